@@ -78,6 +78,43 @@ impl<D> GroundLineVertexArray<D> where D: Device {
     }
 }
 
+/// An arrowhead pointing along +x, centered on the origin, drawn as a triangle fan.
+///
+/// The ground program places vertex positions in the XZ plane, so the transform used to draw this
+/// must map Z, not Y, to the second coordinate.
+static TURTLE_SPRITE_VERTEX_POSITIONS: [(f32, f32); 4] = [
+    (1.0, 0.0), (-0.6, 0.5), (-0.3, 0.0), (-0.6, -0.5),
+];
+
+pub const TURTLE_SPRITE_VERTEX_COUNT: u32 = 4;
+
+pub struct TurtleSpriteVertexArray<D> where D: Device {
+    pub vertex_array: D::VertexArray,
+    #[allow(dead_code)]
+    sprite_vertex_positions_buffer: D::Buffer,
+}
+
+impl<D> TurtleSpriteVertexArray<D> where D: Device {
+    pub fn new(device: &D, ground_program: &GroundProgram<D>) -> TurtleSpriteVertexArray<D> {
+        let sprite_vertex_positions_buffer = device.create_buffer();
+        device.allocate_buffer(&sprite_vertex_positions_buffer,
+                               BufferData::Memory(&TURTLE_SPRITE_VERTEX_POSITIONS[..]),
+                               BufferTarget::Vertex,
+                               BufferUploadMode::Static);
+
+        let vertex_array = device.create_vertex_array();
+
+        let position_attr = device.get_vertex_attr(&ground_program.program, "Position");
+
+        device.bind_vertex_array(&vertex_array);
+        device.use_program(&ground_program.program);
+        device.bind_buffer(&sprite_vertex_positions_buffer, BufferTarget::Vertex);
+        device.configure_float_vertex_attr(&position_attr, 2, VertexAttrType::F32, false, 0, 0, 0);
+
+        TurtleSpriteVertexArray { vertex_array, sprite_vertex_positions_buffer }
+    }
+}
+
 fn create_grid_vertex_positions() -> Vec<(u8, u8)> {
     let mut positions = vec![];
     for index in 0..(GRIDLINE_COUNT + 1) {
@@ -11,12 +11,13 @@
 //! A demo app for Pathfinder.
 
 use crate::device::{GroundLineVertexArray, GroundProgram, GroundSolidVertexArray};
+use crate::device::{TURTLE_SPRITE_VERTEX_COUNT, TurtleSpriteVertexArray};
 use crate::ui::{DemoUI, UIAction};
 use crate::window::{CameraTransform, Event, Keycode, SVGPath, ResourcePath, TurtlePath, View, Window, WindowSize};
 use clap::{App, Arg};
 use image::ColorType;
 use pathfinder_geometry::basic::point::{Point2DF32, Point2DI32, Point3DF32};
-use pathfinder_geometry::basic::rect::RectF32;
+use pathfinder_geometry::basic::rect::{RectF32, RectI32};
 use pathfinder_geometry::basic::transform2d::Transform2DF32;
 use pathfinder_geometry::basic::transform3d::{Perspective, Transform3DF32};
 use pathfinder_geometry::color::ColorU;
@@ -30,24 +31,26 @@ use pathfinder_renderer::gpu::renderer::{DestFramebuffer, RenderMode, RenderStat
 use pathfinder_renderer::gpu_data::RenderCommand;
 use pathfinder_renderer::post::{DEFRINGING_KERNEL_CORE_GRAPHICS, STEM_DARKENING_FACTORS};
 use pathfinder_renderer::scene::{Scene, SceneDescriptor};
-use pathfinder_svg::BuiltSVG;
-use pathfinder_turtle::BuiltTurtle;
+use pathfinder_svg::animation::Animations;
+use pathfinder_svg::graph::SceneGraph;
+use pathfinder_svg::{BuildOptions as SVGBuildOptions, BuiltSVG};
+use pathfinder_turtle::{BuiltTurtle, TurtlePose};
 use pathfinder_ui::{MousePosition, UIEvent};
 use rayon::ThreadPoolBuilder;
+use std::ffi::OsStr;
 use std::f32::consts::FRAC_PI_4;
 use std::fmt::{Debug, Formatter, Result as DebugResult};
 use std::fs::File;
 use std::io::Read;
 use std::iter;
 use std::panic::{self, AssertUnwindSafe};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process;
 use std::str;
 use std::sync::mpsc::{self, Receiver, Sender, SyncSender};
 use std::thread;
 use std::time::{Duration, Instant};
-use usvg::{Options as UsvgOptions, Tree};
-use uturtle::Parser as TurtleParser;
+use uturtle::Dialect as TurtleDialect;
 
 static DEFAULT_SVG_VIRTUAL_PATH: &'static str = "svg/Ghostscript_Tiger.svg";
 static DEFAULT_TURTLE_VIRTUAL_PATH: &'static str = "turtle/tiger.turtle";
@@ -70,6 +73,12 @@ const TRANSPARENT_BG_COLOR: ColorU = ColorU { r: 0,   g: 0,   b: 0,   a: 0   };
 const GROUND_SOLID_COLOR:   ColorU = ColorU { r: 80,  g: 80,  b: 80,  a: 255 };
 const GROUND_LINE_COLOR:    ColorU = ColorU { r: 127, g: 127, b: 127, a: 255 };
 
+const TURTLE_SPRITE_COLOR:  ColorU = ColorU { r: 34,  g: 139, b: 34,  a: 255 };
+
+// The length of the turtle sprite, in device pixels in 2D and as a fraction of the view box in 3D.
+const TURTLE_SPRITE_SIZE_2D: f32 = 12.0;
+const TURTLE_SPRITE_SIZE_3D: f32 = 0.02;
+
 const APPROX_FONT_SIZE: f32 = 16.0;
 
 const MESSAGE_TIMEOUT_SECS: u64 = 5;
@@ -92,6 +101,8 @@ pub struct DemoApp<W> where W: Window {
 
     scene_view_box: RectF32,
     monochrome_scene_color: Option<ColorU>,
+    turtle_pose: Option<TurtlePose>,
+    svg_animation: Option<SVGAnimation>,
 
     camera: Camera,
     frame_counter: u32,
@@ -111,6 +122,7 @@ pub struct DemoApp<W> where W: Window {
     ground_program: GroundProgram<GLDevice>,
     ground_solid_vertex_array: GroundSolidVertexArray<GLDevice>,
     ground_line_vertex_array: GroundLineVertexArray<GLDevice>,
+    turtle_sprite_vertex_array: TurtleSpriteVertexArray<GLDevice>,
 }
 
 impl<W> DemoApp<W> where W: Window {
@@ -128,16 +140,41 @@ impl<W> DemoApp<W> where W: Window {
         thread_pool_builder = window.adjust_thread_pool_settings(thread_pool_builder);
         thread_pool_builder.build_global().unwrap();
 
-        let (scene, message) = match &options.input_path{
+        // If the input fails to load, the error is shown over an empty scene the size of the
+        // window, so that another file can be opened.
+        let mut error_message = String::new();
+        let empty_scene = || {
+            let mut scene = Scene::new();
+            scene.view_box = RectF32::new(Point2DF32::default(),
+                                          window_size.logical_size.to_f32());
+            scene
+        };
+        let (scene, message, turtle_pose, svg_animation) = match &options.input_path{
             ResourcePath::SVG(svg_path) =>{
-                let built_svg = load_scene(resources, &svg_path);
-                let message = get_svg_building_message(&built_svg);
-                (built_svg.scene, message)
+                match load_scene(resources, &svg_path) {
+                    Ok((built_svg, animations)) => {
+                        let message = get_svg_building_message(&built_svg);
+                        let svg_animation = SVGAnimation::new(&options, &built_svg, animations);
+                        (built_svg.scene, message, None, svg_animation)
+                    }
+                    Err(error) => {
+                        error_message = error;
+                        (empty_scene(), String::new(), None, None)
+                    }
+                }
             }
             ResourcePath::Turtle(turtle_path)=>{
-                let built_turtle = load_turtle_scene(resources, &turtle_path);
-                let message = get_turtle_building_message(&built_turtle);
-                (built_turtle.scene, message)
+                match load_turtle_scene(resources, &turtle_path) {
+                    Ok(built_turtle) => {
+                        let message = get_turtle_building_message(&built_turtle);
+                        let pose = built_turtle.pose();
+                        (built_turtle.scene, message, Some(pose), None)
+                    }
+                    Err(error) => {
+                        error_message = error;
+                        (empty_scene(), String::new(), None, None)
+                    }
+                }
             }
         };
         let scene_view_box = scene.view_box;
@@ -162,8 +199,12 @@ impl<W> DemoApp<W> where W: Window {
                                         &renderer.quad_vertex_positions_buffer());
         let ground_line_vertex_array = GroundLineVertexArray::new(&renderer.device,
                                                                   &ground_program);
+        let turtle_sprite_vertex_array = TurtleSpriteVertexArray::new(&renderer.device,
+                                                                      &ground_program);
 
         let mut ui = DemoUI::new(&renderer.device, resources, options.clone());
+        ui.set_turtle_pose(turtle_pose);
+        ui.error_message = error_message;
         let mut message_epoch = 0;
         emit_message::<W>(&mut ui, &mut message_epoch, expire_message_event_id, message);
 
@@ -176,6 +217,8 @@ impl<W> DemoApp<W> where W: Window {
 
             scene_view_box,
             monochrome_scene_color,
+            turtle_pose,
+            svg_animation,
 
             camera,
             frame_counter: 0,
@@ -195,6 +238,7 @@ impl<W> DemoApp<W> where W: Window {
             ground_program,
             ground_solid_vertex_array,
             ground_line_vertex_array,
+            turtle_sprite_vertex_array,
         }
     }
 
@@ -205,6 +249,13 @@ impl<W> DemoApp<W> where W: Window {
         // Handle events.
         let ui_events = self.handle_events(events);
 
+        // Advance the animation, if any.
+        if let Some(ref svg_animation) = self.svg_animation {
+            let viewport_size = self.window.viewport(self.ui.mode.view(0)).size();
+            self.scene_thread_proxy.load_scene(svg_animation.current_scene(), viewport_size);
+            self.dirty = true;
+        }
+
         // Update the scene.
         self.build_scene();
 
@@ -376,27 +427,15 @@ impl<W> DemoApp<W> where W: Window {
                     }
                 }
                 Event::OpenSVG(ref svg_path) => {
-                    let built_svg = load_scene(self.window.resource_loader(), svg_path);
-                    self.ui.message = get_svg_building_message(&built_svg);
-
-                    let viewport_size = self.window.viewport(self.ui.mode.view(0)).size();
-                    self.scene_view_box = built_svg.scene.view_box;
-                    self.monochrome_scene_color = built_svg.scene.monochrome_color();
-                    self.camera = Camera::new(self.ui.mode, self.scene_view_box, viewport_size);
-                    self.scene_thread_proxy.load_scene(built_svg.scene, viewport_size);
+                    self.load_svg(svg_path);
                     self.dirty = true;
                 }
                 Event::OpenTurtle(ref turtle_path) => {
-                    let built_turtle =
-                        load_turtle_scene(self.window.resource_loader(), turtle_path);
-                    self.ui.message = get_turtle_building_message(&built_turtle);
-
-                    let viewport_size = self.window.viewport(self.ui.mode.view(0)).size();
-                    self.scene_view_box = built_turtle.scene.view_box;
-                    self.monochrome_scene_color = built_turtle.scene.monochrome_color();
-                    self.camera = Camera::new(self.ui.mode, self.scene_view_box, viewport_size);
-                    self.scene_thread_proxy
-                        .load_scene(built_turtle.scene, viewport_size);
+                    self.load_turtle(turtle_path, false);
+                    self.dirty = true;
+                }
+                Event::ReloadTurtle(ref turtle_path) => {
+                    self.load_turtle(turtle_path, true);
                     self.dirty = true;
                 }
                 Event::User { message_type: event_id, message_data: expected_epoch } if
@@ -412,6 +451,54 @@ impl<W> DemoApp<W> where W: Window {
         ui_events
     }
 
+    // As with turtle scripts, if the file fails to load, the error stays on screen and the
+    // previous scene is kept.
+    fn load_svg(&mut self, svg_path: &SVGPath) {
+        let (built_svg, animations) = match load_scene(self.window.resource_loader(), svg_path) {
+            Ok(loaded) => loaded,
+            Err(error) => {
+                self.ui.error_message = error;
+                return;
+            }
+        };
+        self.ui.error_message = String::new();
+        self.ui.message = get_svg_building_message(&built_svg);
+        self.svg_animation = SVGAnimation::new(&self.options, &built_svg, animations);
+
+        let viewport_size = self.window.viewport(self.ui.mode.view(0)).size();
+        self.scene_view_box = built_svg.scene.view_box;
+        self.monochrome_scene_color = built_svg.scene.monochrome_color();
+        self.turtle_pose = None;
+        self.ui.set_turtle_pose(None);
+        self.camera = Camera::new(self.ui.mode, self.scene_view_box, viewport_size);
+        self.scene_thread_proxy.load_scene(built_svg.scene, viewport_size);
+    }
+
+    // If the script fails to load, the error stays on screen and the previous scene is kept, so
+    // that a typo made while editing doesn't blank the window.
+    fn load_turtle(&mut self, turtle_path: &TurtlePath, keep_camera: bool) {
+        let built_turtle = match load_turtle_scene(self.window.resource_loader(), turtle_path) {
+            Ok(built_turtle) => built_turtle,
+            Err(error) => {
+                self.ui.error_message = error;
+                return;
+            }
+        };
+        self.ui.error_message = String::new();
+        self.ui.message = get_turtle_building_message(&built_turtle);
+        self.svg_animation = None;
+
+        let viewport_size = self.window.viewport(self.ui.mode.view(0)).size();
+        self.scene_view_box = built_turtle.scene.view_box;
+        self.monochrome_scene_color = built_turtle.scene.monochrome_color();
+        self.turtle_pose = Some(built_turtle.pose());
+        self.ui.set_turtle_pose(self.turtle_pose);
+        if !keep_camera {
+            self.camera = Camera::new(self.ui.mode, self.scene_view_box, viewport_size);
+        }
+        self.scene_thread_proxy.load_scene(built_turtle.scene, viewport_size);
+    }
+
     fn process_mouse_position(&mut self, new_position: Point2DI32) -> MousePosition {
         let absolute = new_position.scale(self.window_size.backing_scale_factor as i32);
         let relative = absolute - self.last_mouse_position;
@@ -429,6 +516,7 @@ impl<W> DemoApp<W> where W: Window {
         });
         self.draw_environment(render_scene_index);
         self.render_vector_scene();
+        self.draw_turtle_sprite(render_scene_index, viewport);
 
         if let Some(rendering_time) = self.renderer.shift_timer_query() {
             self.current_frame.as_mut().unwrap().scene_rendering_times.push(rendering_time)
@@ -589,6 +677,60 @@ impl<W> DemoApp<W> where W: Window {
         });
     }
 
+    fn draw_turtle_sprite(&self, viewport_index: u32, viewport: RectI32) {
+        let pose = match self.turtle_pose {
+            Some(pose) if self.ui.turtle_sprite_enabled => pose,
+            _ => return,
+        };
+
+        // The sprite vertices are in the XZ plane; see `TurtleSpriteVertexArray`. Map them onto
+        // the scene plane, rotated to the turtle's heading and centered on its position.
+        let frame = &self.current_frame.as_ref().unwrap();
+        let transform = match frame.transforms[viewport_index as usize] {
+            RenderTransform::Transform2D(ref transform) => {
+                // Work in device pixels, so that the sprite stays the same size when zooming.
+                let center = transform.transform_point(&pose.position);
+                let angle = pose.direction.to_radians() + transform.rotation();
+                let size = TURTLE_SPRITE_SIZE_2D * self.window_size.backing_scale_factor;
+                let (sin, cos) = (angle.sin() * size, angle.cos() * size);
+                let viewport_size = viewport.size().to_f32();
+                let (x_scale, y_scale) = (2.0 / viewport_size.x(), -2.0 / viewport_size.y());
+                let (x, y) = (center.x() * x_scale - 1.0, center.y() * y_scale + 1.0);
+                Transform3DF32::row_major(cos * x_scale, 0.0, -sin * x_scale, x,
+                                          sin * y_scale, 0.0, cos * y_scale,  y,
+                                          0.0,           0.0, 0.0,            0.0,
+                                          0.0,           0.0, 0.0,            1.0)
+            }
+            RenderTransform::Perspective(ref perspective) => {
+                let angle = pose.direction.to_radians();
+                let view_box_size = self.scene_view_box.size();
+                let size = TURTLE_SPRITE_SIZE_3D * f32::max(view_box_size.x(), view_box_size.y());
+                let (sin, cos) = (angle.sin() * size, angle.cos() * size);
+                let (x, y) = (pose.position.x(), pose.position.y());
+                perspective.transform.post_mul(&Transform3DF32::row_major(cos, 0.0, -sin, x,
+                                                                          sin, 0.0, cos,  y,
+                                                                          0.0, 0.0, 0.0,  0.0,
+                                                                          0.0, 0.0, 0.0,  1.0))
+            }
+        };
+
+        let device = &self.renderer.device;
+        device.bind_default_framebuffer(viewport);
+        device.bind_vertex_array(&self.turtle_sprite_vertex_array.vertex_array);
+        device.use_program(&self.ground_program.program);
+        device.set_uniform(&self.ground_program.transform_uniform, UniformData::Mat4([
+            transform.c0,
+            transform.c1,
+            transform.c2,
+            transform.c3,
+        ]));
+        device.set_uniform(&self.ground_program.color_uniform,
+                           UniformData::Vec4(TURTLE_SPRITE_COLOR.to_f32().0));
+        device.draw_arrays(Primitive::TriangleFan,
+                           TURTLE_SPRITE_VERTEX_COUNT,
+                           &RenderState::default());
+    }
+
     fn render_vector_scene(&mut self) {
         let built_scene = match self.scene_thread_proxy.receiver.recv().unwrap() {
             SceneToMainMsg::BeginRenderScene(built_scene) => built_scene,
@@ -848,6 +990,7 @@ pub struct Options {
     pub input_path: ResourcePath,
     pub ui: UIVisibility,
     pub background_color: BackgroundColor,
+    pub animate: bool,
     hidden_field_for_future_proofing: (),
 }
 
@@ -859,6 +1002,7 @@ impl Default for Options {
             input_path: ResourcePath::SVG(SVGPath::Default),
             ui: UIVisibility::All,
             background_color: BackgroundColor::Light,
+            animate: false,
             hidden_field_for_future_proofing: (),
         }
     }
@@ -893,6 +1037,12 @@ impl Options {
                     .possible_values(&["light", "dark", "transparent"])
                     .help("The background color to use"),
             )
+            .arg(
+                Arg::with_name("animate")
+                    .short("a")
+                    .long("animate")
+                    .help("Play back the animations in the SVG"),
+            )
             .arg(Arg::with_name("INPUT").help("Path to the SVG file to render").index(1))
             .get_matches();
 
@@ -922,6 +1072,10 @@ impl Options {
             };
         }
 
+        if matches.is_present("animate") {
+            self.animate = true;
+        }
+
         if let Some(path) = matches.value_of("INPUT") {
             self.input_path = ResourcePath::SVG(SVGPath::Path(PathBuf::from(path)));
         };
@@ -960,38 +1114,93 @@ pub enum UIVisibility {
     All,
 }
 
-fn load_scene(resource_loader: &dyn ResourceLoader, input_path: &SVGPath) -> BuiltSVG {
+// The scene graph is always kept, so that animations can be played back.
+fn load_scene(resource_loader: &dyn ResourceLoader, input_path: &SVGPath)
+              -> Result<(BuiltSVG, Animations), String> {
     let mut data;
     match *input_path {
         SVGPath::Default => data = resource_loader.slurp(DEFAULT_SVG_VIRTUAL_PATH).unwrap(),
-        SVGPath::Resource(ref name) => data = resource_loader.slurp(name).unwrap(),
+        SVGPath::Resource(ref name) => {
+            data = resource_loader.slurp(name)
+                                  .map_err(|e| format!("Failed to read {}: {}", name, e))?;
+        }
         SVGPath::Path(ref path) => {
             data = vec![];
-            File::open(path).unwrap().read_to_end(&mut data).unwrap();
+            File::open(path).and_then(|mut file| file.read_to_end(&mut data))
+                            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
         }
     };
 
-    BuiltSVG::from_tree(Tree::from_data(&data, &UsvgOptions::default()).unwrap())
+    let options = SVGBuildOptions { build_graph: true, ..SVGBuildOptions::default() };
+    let built_svg = BuiltSVG::from_data_with_resources(&data, &options, resource_loader)
+                            .map_err(|e| e.to_string())?;
+    Ok((built_svg, Animations::from_data(&data)))
+}
+
+// An SVG being played back, from when it was loaded.
+struct SVGAnimation {
+    animations: Animations,
+    scene: Scene,
+    graph: SceneGraph,
+    start_time: Instant,
 }
 
-fn load_turtle_scene(resource_loader: &dyn ResourceLoader, input_path: &TurtlePath) -> BuiltTurtle {
+impl SVGAnimation {
+    fn new(options: &Options, built_svg: &BuiltSVG, animations: Animations)
+           -> Option<SVGAnimation> {
+        if !options.animate || animations.is_empty() {
+            return None;
+        }
+        Some(SVGAnimation {
+            animations,
+            scene: built_svg.scene.clone(),
+            graph: built_svg.graph.clone()?,
+            start_time: Instant::now(),
+        })
+    }
+
+    // Loops once every animation has finished.
+    fn current_scene(&self) -> Scene {
+        let elapsed = Instant::now() - self.start_time;
+        let mut time = elapsed.as_secs() as f32 + elapsed.subsec_nanos() as f32 * 1e-9;
+        if let Some(duration) = self.animations.duration() {
+            if duration > 0.0 {
+                time %= duration;
+            }
+        }
+        self.animations.apply(&self.scene, &self.graph, time)
+    }
+}
+
+fn load_turtle_scene(resource_loader: &dyn ResourceLoader, input_path: &TurtlePath)
+                     -> Result<BuiltTurtle, String> {
     let mut data;
+    let extension;
     match *input_path {
-        TurtlePath::Default => data = resource_loader.slurp(DEFAULT_TURTLE_VIRTUAL_PATH).unwrap(),
-        TurtlePath::Resource(ref name) => data = resource_loader.slurp(name).unwrap(),
+        TurtlePath::Default => {
+            data = resource_loader.slurp(DEFAULT_TURTLE_VIRTUAL_PATH).unwrap();
+            extension = Path::new(DEFAULT_TURTLE_VIRTUAL_PATH).extension();
+        }
+        TurtlePath::Resource(ref name) => {
+            data = resource_loader.slurp(name).unwrap();
+            extension = Path::new(name).extension();
+        }
         TurtlePath::Path(ref path) => {
             data = vec![];
-            File::open(path).unwrap().read_to_end(&mut data).unwrap();
+            File::open(path).and_then(|mut file| file.read_to_end(&mut data))
+                            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+            extension = path.extension();
         }
     };
 
     let s = match str::from_utf8(&data) {
         Ok(v) => v,
-        Err(e) => panic!("Invalid UTF-8 sequence: {}", e),
+        Err(e) => return Err(format!("Invalid UTF-8 sequence: {}", e)),
     };
 
-    let t = TurtleParser::new().parse(s).unwrap();
-    BuiltTurtle::from_ast(t)
+    let dialect = TurtleDialect::detect(extension.and_then(OsStr::to_str), s);
+    let t = uturtle::parse(s, dialect).map_err(|e| format!("Parse error: {}", e))?;
+    Ok(BuiltTurtle::from_ast(t))
 }
 
 fn center_of_window(window_size: &WindowSize) -> Point2DF32 {
@@ -12,9 +12,11 @@ use crate::window::Window;
 use crate::{BackgroundColor, Mode, Options};
 use pathfinder_geometry::basic::point::Point2DI32;
 use pathfinder_geometry::basic::rect::RectI32;
+use pathfinder_geometry::color::ColorU;
 use pathfinder_gpu::resources::ResourceLoader;
 use pathfinder_gpu::Device;
 use pathfinder_renderer::gpu::debug::DebugUI;
+use pathfinder_turtle::TurtlePose;
 use pathfinder_ui::{BUTTON_HEIGHT, BUTTON_TEXT_OFFSET, BUTTON_WIDTH, FONT_ASCENT, PADDING};
 use pathfinder_ui::{TEXT_COLOR, TOOLTIP_HEIGHT, WINDOW_COLOR};
 use std::f32::consts::PI;
@@ -35,6 +37,8 @@ const BACKGROUND_PANEL_HEIGHT: i32 = BUTTON_HEIGHT * 3;
 const ROTATE_PANEL_WIDTH: i32 = SLIDER_WIDTH + PADDING * 2;
 const ROTATE_PANEL_HEIGHT: i32 = PADDING * 2 + SLIDER_HEIGHT;
 
+const ERROR_WINDOW_COLOR: ColorU = ColorU { r: 160, g: 0, b: 0, a: 255 - 64 };
+
 static EFFECTS_PNG_NAME: &'static str = "demo-effects";
 static OPEN_PNG_NAME: &'static str = "demo-open";
 static ROTATE_PNG_NAME: &'static str = "demo-rotate";
@@ -67,7 +71,10 @@ where
     pub subpixel_aa_effect_enabled: bool,
     pub rotation: i32,
     pub message: String,
+    pub error_message: String,
     pub show_text_effects: bool,
+    pub turtle_sprite_enabled: bool,
+    pub show_turtle_sprite_switch: bool,
 }
 
 impl<D> DemoUI<D>
@@ -103,10 +110,20 @@ where
             subpixel_aa_effect_enabled: false,
             rotation: SLIDER_WIDTH / 2,
             message: String::new(),
+            error_message: String::new(),
             show_text_effects: true,
+            turtle_sprite_enabled: true,
+            show_turtle_sprite_switch: false,
         }
     }
 
+    /// Shows the turtle sprite switch only while a Turtle script is loaded, and starts it in the
+    /// state that the script left the sprite in.
+    pub fn set_turtle_pose(&mut self, pose: Option<TurtlePose>) {
+        self.show_turtle_sprite_switch = pose.is_some();
+        self.turtle_sprite_enabled = pose.map_or(true, |pose| pose.visible);
+    }
+
     fn rotation(&self) -> f32 {
         (self.rotation as f32 / SLIDER_WIDTH as f32 * 2.0 - 1.0) * PI
     }
@@ -123,6 +140,7 @@ where
         // Draw message text.
 
         self.draw_message_text(device, debug_ui);
+        self.draw_error_text(device, debug_ui);
 
         // Draw button strip.
 
@@ -223,6 +241,25 @@ where
         self.draw_background_panel(device, debug_ui, position.x(), action);
         position += Point2DI32::new(button_size.x() + PADDING, 0);
 
+        // Draw turtle sprite switch, if applicable.
+        if self.show_turtle_sprite_switch {
+            let new_sprite_enabled = debug_ui.ui.draw_text_switch(device,
+                                                                  position,
+                                                                  &["Off", "On"],
+                                                                  self.turtle_sprite_enabled as u8);
+            if new_sprite_enabled != self.turtle_sprite_enabled as u8 {
+                self.turtle_sprite_enabled = new_sprite_enabled != 0;
+                *action = UIAction::ModelChanged;
+            }
+
+            let sprite_switch_width = debug_ui.ui.measure_switch(2);
+            let sprite_switch_size = Point2DI32::new(sprite_switch_width, BUTTON_HEIGHT);
+            debug_ui.ui.draw_tooltip(device,
+                                     "Turtle Sprite",
+                                     RectI32::new(position, sprite_switch_size));
+            position += Point2DI32::new(sprite_switch_width + PADDING, 0);
+        }
+
         // Draw effects panel, if necessary.
         self.draw_effects_panel(device, debug_ui);
 
@@ -289,6 +326,32 @@ where
         );
     }
 
+    // Unlike the message text, errors don't time out; they stay until the next successful load.
+    fn draw_error_text(&mut self, device: &D, debug_ui: &mut DebugUI<D>) {
+        if self.error_message.is_empty() {
+            return;
+        }
+
+        let mut window_origin = Point2DI32::new(PADDING, PADDING);
+        if !self.message.is_empty() {
+            window_origin += Point2DI32::new(0, TOOLTIP_HEIGHT + PADDING);
+        }
+
+        let message_size = debug_ui.ui.measure_text(&self.error_message);
+        let window_size = Point2DI32::new(PADDING * 2 + message_size, TOOLTIP_HEIGHT);
+        debug_ui.ui.draw_solid_rounded_rect(
+            device,
+            RectI32::new(window_origin, window_size),
+            ERROR_WINDOW_COLOR,
+        );
+        debug_ui.ui.draw_text(
+            device,
+            &self.error_message,
+            window_origin + Point2DI32::new(PADDING, PADDING + FONT_ASCENT),
+            false,
+        );
+    }
+
     fn draw_effects_panel(&mut self, device: &D, debug_ui: &mut DebugUI<D>) {
         if !self.effects_panel_visible {
             return;
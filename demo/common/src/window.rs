@@ -56,6 +56,8 @@ pub enum Event {
     CameraTransforms(Vec<CameraTransform>),
     OpenSVG(SVGPath),
     OpenTurtle(TurtlePath),
+    /// The Turtle script at this path changed on disk. Rebuilds the scene but keeps the camera.
+    ReloadTurtle(TurtlePath),
     User { message_type: u32, message_data: u32 },
 }
 
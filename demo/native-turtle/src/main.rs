@@ -9,6 +9,8 @@
 // except according to those terms.
 
 //! A demo app for Pathfinder using SDL 2.
+//!
+//! The Turtle script opened from the UI is watched for changes and reloaded whenever it is saved.
 
 use jemallocator;
 use nfd::Response;
@@ -24,8 +26,12 @@ use sdl2::keyboard::Keycode as SDLKeycode;
 use sdl2::video::{GLContext, GLProfile, Window as SDLWindow};
 use sdl2::{EventPump, EventSubsystem, Sdl, VideoSubsystem};
 use sdl2_sys::{SDL_Event, SDL_UserEvent};
+use std::fs;
 use std::path::PathBuf;
 use std::ptr;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime};
 
 #[global_allocator]
 static ALLOC: jemallocator::Jemalloc = jemallocator::Jemalloc;
@@ -33,6 +39,8 @@ static ALLOC: jemallocator::Jemalloc = jemallocator::Jemalloc;
 const DEFAULT_WINDOW_WIDTH: u32 = 1067;
 const DEFAULT_WINDOW_HEIGHT: u32 = 800;
 
+const FILE_WATCH_INTERVAL_MS: u64 = 250;
+
 fn main() {
     let window = WindowImpl::new();
     let window_size = window.size();
@@ -71,6 +79,8 @@ struct WindowImpl {
     resource_loader: FilesystemResourceLoader,
     selected_file: Option<PathBuf>,
     open_svg_message_type: u32,
+    watched_file: Arc<Mutex<Option<PathBuf>>>,
+    reload_turtle_message_type: u32,
 }
 
 impl Window for WindowImpl {
@@ -126,7 +136,7 @@ impl Window for WindowImpl {
     }
 
     fn present_open_svg_dialog(&mut self) {
-        if let Ok(Response::Okay(path)) = nfd::open_file_dialog(Some("turtle"), None) {
+        if let Ok(Response::Okay(path)) = nfd::open_file_dialog(Some("turtle,logo,lg,py"), None) {
             self.selected_file = Some(PathBuf::from(path));
             WindowImpl::push_user_event(self.open_svg_message_type, 0);
         }
@@ -172,6 +182,11 @@ impl WindowImpl {
                 let resource_loader = FilesystemResourceLoader::locate();
 
                 let open_svg_message_type = unsafe { sdl_event.register_event().unwrap() };
+                let reload_turtle_message_type =
+                    unsafe { sdl_event.register_event().unwrap() };
+
+                let watched_file = Arc::new(Mutex::new(None));
+                spawn_file_watcher(watched_file.clone(), reload_turtle_message_type);
 
                 WindowImpl {
                     window,
@@ -180,6 +195,8 @@ impl WindowImpl {
                     resource_loader,
                     open_svg_message_type,
                     selected_file: None,
+                    watched_file,
+                    reload_turtle_message_type,
                 }
             })
         })
@@ -214,9 +231,15 @@ impl WindowImpl {
 
     fn convert_sdl_event(&self, sdl_event: SDLEvent) -> Option<Event> {
         match sdl_event {
-            SDLEvent::User { type_, .. } if type_ == self.open_svg_message_type => Some(
-                Event::OpenTurtle(TurtlePath::Path(self.selected_file.clone().unwrap())),
-            ),
+            SDLEvent::User { type_, .. } if type_ == self.open_svg_message_type => {
+                let path = self.selected_file.clone().unwrap();
+                *self.watched_file.lock().unwrap() = Some(path.clone());
+                Some(Event::OpenTurtle(TurtlePath::Path(path)))
+            }
+            SDLEvent::User { type_, .. } if type_ == self.reload_turtle_message_type => {
+                let path = self.watched_file.lock().unwrap().clone()?;
+                Some(Event::ReloadTurtle(TurtlePath::Path(path)))
+            }
             SDLEvent::User { type_, code, .. } => Some(Event::User {
                 message_type: type_,
                 message_data: code as u32,
@@ -265,3 +288,36 @@ impl WindowImpl {
         }
     }
 }
+
+// Polls the modification time of the watched file and posts a reload event when it changes. This
+// avoids a dependency on a platform file notification API, and editors that save by renaming a
+// temporary file over the original are handled for free.
+fn spawn_file_watcher(watched_file: Arc<Mutex<Option<PathBuf>>>, reload_message_type: u32) {
+    thread::spawn(move || {
+        let mut last_seen: Option<(PathBuf, SystemTime)> = None;
+        loop {
+            thread::sleep(Duration::from_millis(FILE_WATCH_INTERVAL_MS));
+
+            let path = match *watched_file.lock().unwrap() {
+                Some(ref path) => path.clone(),
+                None => continue,
+            };
+
+            // The file may briefly be missing while an editor saves it.
+            let modified = match fs::metadata(&path).and_then(|metadata| metadata.modified()) {
+                Ok(modified) => modified,
+                Err(_) => continue,
+            };
+
+            match last_seen {
+                Some((ref last_path, last_modified)) if *last_path == path => {
+                    if modified != last_modified {
+                        WindowImpl::push_user_event(reload_message_type, 0);
+                    }
+                }
+                _ => {}
+            }
+            last_seen = Some((path, modified));
+        }
+    });
+}
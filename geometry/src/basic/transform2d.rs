@@ -198,6 +198,14 @@ impl Transform2DF32 {
         *self == Transform2DF32::default()
     }
 
+    /// Returns the inverse of this transform. The matrix must not be degenerate.
+    #[inline]
+    pub fn inverse(&self) -> Transform2DF32 {
+        let matrix = self.matrix.inverse();
+        let vector = -matrix.transform_point(&self.vector);
+        Transform2DF32 { matrix, vector }
+    }
+
     #[inline]
     pub fn m11(&self) -> f32 { self.matrix.m11() }
     #[inline]
@@ -245,6 +253,16 @@ impl Transform2DF32 {
     pub fn scale_factor(&self) -> f32 {
         Point2DF32(self.matrix.0.zwxy()).length()
     }
+
+    /// Returns the smallest factor by which this matrix scales any vector: its smaller singular
+    /// value. This is zero if the matrix is degenerate.
+    pub fn min_scale_factor(&self) -> f32 {
+        let (m11, m12, m21, m22) = (self.m11(), self.m12(), self.m21(), self.m22());
+        let sum_of_squares = m11 * m11 + m12 * m12 + m21 * m21 + m22 * m22;
+        let det = m11 * m22 - m12 * m21;
+        let discriminant = f32::max(sum_of_squares * sum_of_squares - 4.0 * det * det, 0.0);
+        f32::sqrt(f32::max((sum_of_squares - discriminant.sqrt()) * 0.5, 0.0))
+    }
 }
 
 /// Transforms a path with a SIMD 2D transform.
@@ -147,6 +147,16 @@ impl Outline {
         self.bounds = new_bounds.unwrap_or_else(|| RectF32::default());
     }
 
+    /// Replaces curves with lines that stay within `tolerance` of them.
+    pub fn flatten(&mut self, tolerance: f32) {
+        let mut new_bounds = None;
+        for contour in &mut self.contours {
+            contour.flatten(tolerance);
+            contour.update_bounds(&mut new_bounds);
+        }
+        self.bounds = new_bounds.unwrap_or_else(|| RectF32::default());
+    }
+
     pub fn prepare_for_tiling(&mut self, view_box: RectF32) {
         self.contours.iter_mut().for_each(|contour| contour.prepare_for_tiling(view_box));
         self.bounds = self.bounds.intersection(view_box).unwrap_or_else(|| RectF32::default());
@@ -437,6 +447,45 @@ impl Contour {
         ContourBarrelDistorter::new(self, coefficients, window_size).distort();
     }
 
+    pub fn flatten(&mut self, tolerance: f32) {
+        if (0..self.len()).all(|point_index| self.point_is_endpoint(point_index)) {
+            return;
+        }
+
+        // Clear the closed flag on the old contour so that iterating over it doesn't yield the
+        // implicit closing segment.
+        let mut contour = self.take();
+        self.closed = contour.closed;
+        contour.closed = false;
+
+        for segment in contour.iter() {
+            if segment.is_line() {
+                self.push_segment(segment, true);
+                continue;
+            }
+
+            // Same subdivision as the tiler: halve `t` until the part before it is flat.
+            let mut segment = segment.to_cubic();
+            loop {
+                let mut split_t = 1.0;
+                let mut before_segment = segment;
+                let mut after_segment = None;
+                while !before_segment.as_cubic_segment().is_flat(tolerance) {
+                    split_t *= 0.5;
+                    let (before, after) = segment.as_cubic_segment().split(split_t);
+                    before_segment = before;
+                    after_segment = Some(after);
+                }
+
+                self.push_segment(Segment::line(&before_segment.baseline), true);
+                match after_segment {
+                    None => break,
+                    Some(after_segment) => segment = after_segment,
+                }
+            }
+        }
+    }
+
     fn prepare_for_tiling(&mut self, view_box: RectF32) {
         // Snap points to the view box bounds. This mops up floating point error from the clipping
         // process.
@@ -11,67 +11,463 @@
 //! Utilities for converting path strokes to fills.
 
 use crate::basic::line_segment::LineSegmentF32;
+use crate::basic::point::Point2DF32;
 use crate::basic::rect::RectF32;
-use crate::outline::{Contour, Outline};
+use crate::outline::{Contour, Outline, PointFlags};
 use crate::segment::Segment as SegmentPF3;
+use std::f32::consts::{FRAC_PI_2, PI};
 use std::mem;
 
 const TOLERANCE: f32 = 0.01;
 
+// The number of pieces each segment is divided into to measure its length for dashing.
+const DASH_SAMPLE_COUNT: usize = 16;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct StrokeStyle {
+    pub line_width: f32,
+    pub line_cap: LineCap,
+    pub line_join: LineJoin,
+    /// The maximum ratio of miter length to line width before a miter join becomes a bevel.
+    pub miter_limit: f32,
+    /// Alternating lengths of dashes and gaps. Empty for a solid line.
+    pub dash_array: Vec<f32>,
+    /// How far into the dash pattern each subpath starts.
+    pub dash_offset: f32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LineCap {
+    Butt,
+    Square,
+    Round,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LineJoin {
+    Miter,
+    Bevel,
+    Round,
+}
+
+impl Default for StrokeStyle {
+    #[inline]
+    fn default() -> StrokeStyle {
+        StrokeStyle {
+            line_width: 1.0,
+            line_cap: LineCap::default(),
+            line_join: LineJoin::default(),
+            miter_limit: 4.0,
+            dash_array: vec![],
+            dash_offset: 0.0,
+        }
+    }
+}
+
+impl Default for LineCap {
+    #[inline]
+    fn default() -> LineCap {
+        LineCap::Butt
+    }
+}
+
+impl Default for LineJoin {
+    #[inline]
+    fn default() -> LineJoin {
+        LineJoin::Miter
+    }
+}
+
 pub struct OutlineStrokeToFill {
     pub outline: Outline,
-    pub stroke_width: f32,
+    pub style: StrokeStyle,
 }
 
 impl OutlineStrokeToFill {
     #[inline]
-    pub fn new(outline: Outline, stroke_width: f32) -> OutlineStrokeToFill {
-        OutlineStrokeToFill { outline, stroke_width }
+    pub fn new(outline: Outline, style: StrokeStyle) -> OutlineStrokeToFill {
+        OutlineStrokeToFill { outline, style }
     }
 
-    #[inline]
     pub fn offset(&mut self) {
+        let mut cap_directions = vec![];
+        if !self.style.dash_array.is_empty() {
+            let (outline, directions) = dash_outline(&self.outline,
+                                                     &self.style.dash_array,
+                                                     self.style.dash_offset);
+            self.outline = outline;
+            cap_directions = directions;
+        }
+
+        let mut new_contours = vec![];
+        let contours = mem::replace(&mut self.outline.contours, vec![]);
+        for (contour_index, input) in contours.into_iter().enumerate() {
+            let cap_direction = match cap_directions.get(contour_index) {
+                Some(&direction) => direction,
+                None => Point2DF32::new(1.0, 0.0),
+            };
+            let contour_stroke_to_fill =
+                ContourStrokeToFill::new(input, &self.style, cap_direction);
+            contour_stroke_to_fill.offset(&mut new_contours);
+        }
+
         let mut new_bounds = None;
-        for contour in &mut self.outline.contours {
-            let input = mem::replace(contour, Contour::new());
-            let mut contour_stroke_to_fill =
-                ContourStrokeToFill::new(input, Contour::new(), self.stroke_width * 0.5);
-            contour_stroke_to_fill.offset_forward();
-            contour_stroke_to_fill.offset_backward();
-            *contour = contour_stroke_to_fill.output;
+        for contour in &new_contours {
             contour.update_bounds(&mut new_bounds);
         }
 
+        self.outline.contours = new_contours;
         self.outline.bounds = new_bounds.unwrap_or_else(|| RectF32::default());
     }
 }
 
-struct ContourStrokeToFill {
-    input: Contour,
-    output: Contour,
+struct ContourStrokeToFill<'a> {
+    // Segments with no direction are dropped, since they have no normal to offset along.
+    segments: Vec<SegmentPF3>,
+    first_point: Option<Point2DF32>,
+    // The direction that the caps face if the contour has no length.
+    cap_direction: Point2DF32,
+    closed: bool,
     radius: f32,
+    style: &'a StrokeStyle,
 }
 
-impl ContourStrokeToFill {
-    #[inline]
-    fn new(input: Contour, output: Contour, radius: f32) -> ContourStrokeToFill {
-        ContourStrokeToFill { input, output, radius }
+impl<'a> ContourStrokeToFill<'a> {
+    fn new(input: Contour, style: &'a StrokeStyle, cap_direction: Point2DF32)
+           -> ContourStrokeToFill<'a> {
+        let first_point = if input.is_empty() { None } else { Some(input.position_of(0)) };
+        let segments = input.iter().filter(|segment| !start_tangent(segment).is_zero()).collect();
+        ContourStrokeToFill {
+            segments,
+            first_point,
+            cap_direction,
+            closed: input.is_closed(),
+            radius: style.line_width * 0.5,
+            style,
+        }
     }
 
-    fn offset_forward(&mut self) {
-        for segment in self.input.iter() {
-            segment.offset(self.radius, &mut self.output);
+    fn offset(&self, output: &mut Vec<Contour>) {
+        if self.segments.is_empty() {
+            // A zero-length subpath still gets round or square caps.
+            if let Some(point) = self.first_point {
+                if self.style.line_cap != LineCap::Butt {
+                    let mut contour = Contour::new();
+                    let normal = left_normal(self.cap_direction).scale(self.radius);
+                    contour.push_point(point + normal, PointFlags::empty(), true);
+                    self.add_cap(point, self.cap_direction, &mut contour);
+                    self.add_cap(point, -self.cap_direction, &mut contour);
+                    contour.close();
+                    output.push(contour);
+                }
+            }
+            return;
         }
+
+        let backward: Vec<_> = self.segments.iter().rev().map(|segment| {
+            segment.reversed()
+        }).collect();
+
+        if self.closed {
+            // The two sides of a closed contour wind in opposite directions, leaving the
+            // interior unfilled.
+            for segments in &[&self.segments[..], &backward[..]] {
+                let mut contour = Contour::new();
+                self.offset_segments(segments, &mut contour);
+                self.add_join(segments.last().unwrap(), &segments[0], &mut contour);
+                contour.close();
+                output.push(contour);
+            }
+            return;
+        }
+
+        let mut contour = Contour::new();
+        self.offset_segments(&self.segments, &mut contour);
+        let last = self.segments.last().unwrap();
+        self.add_cap(last.baseline.to(), end_tangent(last).normalize(), &mut contour);
+        self.offset_segments(&backward, &mut contour);
+        let first = backward.last().unwrap();
+        self.add_cap(first.baseline.to(), end_tangent(first).normalize(), &mut contour);
+        contour.close();
+        output.push(contour);
     }
 
-    fn offset_backward(&mut self) {
-        // FIXME(pcwalton)
-        let mut segments: Vec<_> = self.input.iter().map(|segment| segment.reversed()).collect();
-        segments.reverse();
-        for segment in &segments {
-            segment.offset(self.radius, &mut self.output);
+    fn offset_segments(&self, segments: &[SegmentPF3], contour: &mut Contour) {
+        for (segment_index, segment) in segments.iter().enumerate() {
+            if segment_index > 0 {
+                self.add_join(&segments[segment_index - 1], segment, contour);
+            }
+            segment.offset(self.radius, contour);
+        }
+    }
+
+    // Offsets lie to the left of the direction of travel, so only right turns leave a gap on
+    // that side to be filled in.
+    fn add_join(&self, prev: &SegmentPF3, next: &SegmentPF3, contour: &mut Contour) {
+        let point = next.baseline.from();
+        let prev_tangent = end_tangent(prev).normalize();
+        let next_tangent = start_tangent(next).normalize();
+        let (cross, dot) = (prev_tangent.det(next_tangent), prev_tangent.dot(next_tangent));
+        if cross > 0.0 || (cross == 0.0 && dot >= 0.0) {
+            return;
+        }
+
+        let prev_normal = left_normal(prev_tangent).scale(self.radius);
+        let next_normal = left_normal(next_tangent).scale(self.radius);
+        match self.style.line_join {
+            LineJoin::Bevel => {}
+            LineJoin::Miter => {
+                // The miter is 1 / sin(θ / 2) times as long as the line is wide, where θ is the
+                // angle between the segments. Note that sin(θ / 2)² = (1 + dot) / 2.
+                let miter_limit = f32::max(self.style.miter_limit, 1.0);
+                if 1.0 + dot >= 2.0 / (miter_limit * miter_limit) {
+                    let miter = point + (prev_normal + next_normal).scale(1.0 / (1.0 + dot));
+                    contour.push_point(miter, PointFlags::empty(), true);
+                }
+            }
+            LineJoin::Round => {
+                let mut sweep = f32::atan2(cross, dot);
+                if sweep > 0.0 {
+                    sweep -= 2.0 * PI;
+                }
+                push_arc(point, prev_normal, sweep, contour);
+            }
+        }
+    }
+
+    // Caps the end of a side that arrives at `point` heading along `tangent`, continuing from
+    // the left side to the right.
+    fn add_cap(&self, point: Point2DF32, tangent: Point2DF32, contour: &mut Contour) {
+        let normal = left_normal(tangent).scale(self.radius);
+        match self.style.line_cap {
+            LineCap::Butt => {}
+            LineCap::Square => {
+                let extension = tangent.scale(self.radius);
+                contour.push_point(point + normal + extension, PointFlags::empty(), true);
+                contour.push_point(point - normal + extension, PointFlags::empty(), true);
+            }
+            LineCap::Round => push_arc(point, normal, -PI, contour),
+        }
+    }
+}
+
+// The tangent at the start of the segment, unnormalized. Zero if the segment is a point.
+fn start_tangent(segment: &SegmentPF3) -> Point2DF32 {
+    let from = segment.baseline.from();
+    if !segment.is_line() {
+        if segment.ctrl.from() != from {
+            return segment.ctrl.from() - from;
+        }
+        if segment.is_cubic() && segment.ctrl.to() != from {
+            return segment.ctrl.to() - from;
         }
     }
+    segment.baseline.to() - from
+}
+
+#[inline]
+fn end_tangent(segment: &SegmentPF3) -> Point2DF32 {
+    -start_tangent(&segment.reversed())
+}
+
+// Matches the side that `LineSegmentF32::offset()` moves toward for positive distances.
+#[inline]
+fn left_normal(tangent: Point2DF32) -> Point2DF32 {
+    Point2DF32::new(-tangent.y(), tangent.x())
+}
+
+// Appends a circular arc around `center`, starting at `center + start` and turning through
+// `sweep` radians, as cubic Béziers of at most 90° each.
+fn push_arc(center: Point2DF32, start: Point2DF32, sweep: f32, contour: &mut Contour) {
+    let count = f32::max(f32::ceil(f32::abs(sweep) / FRAC_PI_2), 1.0) as u32;
+    let step = sweep / count as f32;
+    let k = 4.0 / 3.0 * f32::tan(step * 0.25);
+    let (sin_step, cos_step) = step.sin_cos();
+
+    let mut vector_0 = start;
+    for _ in 0..count {
+        let vector_1 = Point2DF32::new(vector_0.x() * cos_step - vector_0.y() * sin_step,
+                                       vector_0.x() * sin_step + vector_0.y() * cos_step);
+        let ctrl_0 = center + vector_0 + left_normal(vector_0).scale(k);
+        let ctrl_1 = center + vector_1 - left_normal(vector_1).scale(k);
+        let baseline = LineSegmentF32::new(&(center + vector_0), &(center + vector_1));
+        let ctrl = LineSegmentF32::new(&ctrl_0, &ctrl_1);
+        contour.push_segment(SegmentPF3::cubic(&baseline, &ctrl), true);
+        vector_0 = vector_1;
+    }
+}
+
+// Splits each contour into open contours, one per dash. Per SVG, a pattern with an odd number of
+// entries is repeated to make it even, and an invalid pattern means a solid line.
+//
+// Zero-length dashes become zero-length contours, so that they still get caps. Alongside the
+// outline, this returns the direction of the path at each dash, for orienting those caps.
+//
+// FIXME(pcwalton): On closed contours, the first and last dashes should be joined if they meet.
+fn dash_outline(outline: &Outline, dash_array: &[f32], dash_offset: f32)
+                -> (Outline, Vec<Point2DF32>) {
+    let pattern_length: f32 = dash_array.iter().sum();
+    if pattern_length <= 0.0 || dash_array.iter().any(|&length| length < 0.0) {
+        return ((*outline).clone(), vec![]);
+    }
+
+    let mut dashes = dash_array.to_vec();
+    if dashes.len() % 2 == 1 {
+        dashes.extend_from_slice(dash_array);
+    }
+    let pattern_length = pattern_length * (dashes.len() / dash_array.len()) as f32;
+
+    let mut output = Outline::new();
+    let mut directions = vec![];
+    for contour in &outline.contours {
+        // Find where in the pattern this contour starts. A zero-length dash right at the start is
+        // stopped at, so that it's drawn.
+        let mut dash_index = 0;
+        let mut remaining = dash_offset % pattern_length;
+        if remaining < 0.0 {
+            remaining += pattern_length;
+        }
+        while remaining >= dashes[dash_index] && !(remaining == 0.0 && dashes[dash_index] == 0.0) {
+            remaining -= dashes[dash_index];
+            dash_index = (dash_index + 1) % dashes.len();
+        }
+        remaining = dashes[dash_index] - remaining;
+
+        let mut dash = Contour::new();
+        let mut last_segment = None;
+        for segment in contour.iter() {
+            let lengths = SegmentLengths::new(&segment);
+            let segment_length = lengths.total();
+            let mut distance = 0.0;
+            loop {
+                let dash_is_on = dash_index % 2 == 0;
+                let end_distance = distance + remaining;
+                if end_distance >= segment_length {
+                    if dash_is_on {
+                        push_segment_piece(&segment, &lengths, distance, segment_length, &mut dash);
+                    }
+                    remaining = end_distance - segment_length;
+                    break;
+                }
+
+                if dash_is_on {
+                    if dashes[dash_index] == 0.0 {
+                        let t = lengths.t_at(distance);
+                        let (point, direction) = (segment.sample(t), direction_at(&segment, t));
+                        output.contours.push(point_contour(point));
+                        directions.push(direction);
+                    } else {
+                        push_segment_piece(&segment, &lengths, distance, end_distance, &mut dash);
+                        if !dash.is_empty() {
+                            output.contours.push(mem::replace(&mut dash, Contour::new()));
+                            directions.push(Point2DF32::new(1.0, 0.0));
+                        }
+                    }
+                }
+
+                distance = end_distance;
+                dash_index = (dash_index + 1) % dashes.len();
+                remaining = dashes[dash_index];
+            }
+            last_segment = Some(segment);
+        }
+
+        if !dash.is_empty() {
+            output.contours.push(dash);
+            directions.push(Point2DF32::new(1.0, 0.0));
+        } else if let Some(segment) = last_segment {
+            // A zero-length dash exactly at the end of the contour.
+            let next_dash_index = (dash_index + 1) % dashes.len();
+            if remaining == 0.0 && next_dash_index % 2 == 0 && dashes[next_dash_index] == 0.0 {
+                output.contours.push(point_contour(segment.baseline.to()));
+                directions.push(direction_at(&segment, 1.0));
+            }
+        }
+    }
+
+    let mut bounds = None;
+    for contour in &output.contours {
+        contour.update_bounds(&mut bounds);
+    }
+    output.bounds = bounds.unwrap_or_else(|| RectF32::default());
+    (output, directions)
+}
+
+fn point_contour(point: Point2DF32) -> Contour {
+    let mut contour = Contour::new();
+    contour.push_point(point, PointFlags::empty(), true);
+    contour
+}
+
+// The normalized direction of the segment at `t`, or the x axis if it has none.
+fn direction_at(segment: &SegmentPF3, t: f32) -> Point2DF32 {
+    let tangent = if t < 1.0 { start_tangent(&segment.split(t).1) } else { end_tangent(segment) };
+    if tangent.is_zero() {
+        Point2DF32::new(1.0, 0.0)
+    } else {
+        tangent.normalize()
+    }
+}
+
+fn push_segment_piece(segment: &SegmentPF3,
+                      lengths: &SegmentLengths,
+                      start_distance: f32,
+                      end_distance: f32,
+                      contour: &mut Contour) {
+    let (start_t, end_t) = (lengths.t_at(start_distance), lengths.t_at(end_distance));
+    if end_t <= start_t {
+        return;
+    }
+
+    let mut piece = *segment;
+    if end_t < 1.0 {
+        piece = piece.split(end_t).0;
+    }
+    if start_t > 0.0 {
+        piece = piece.split(start_t / end_t).1;
+    }
+    contour.push_segment(piece, true);
+}
+
+// Cumulative lengths along a segment at evenly spaced values of t.
+struct SegmentLengths {
+    lengths: [f32; DASH_SAMPLE_COUNT + 1],
+}
+
+impl SegmentLengths {
+    fn new(segment: &SegmentPF3) -> SegmentLengths {
+        let mut lengths = [0.0; DASH_SAMPLE_COUNT + 1];
+        let mut prev_point = segment.baseline.from();
+        for sample_index in 1..(DASH_SAMPLE_COUNT + 1) {
+            let point = segment.sample(sample_index as f32 / DASH_SAMPLE_COUNT as f32);
+            lengths[sample_index] = lengths[sample_index - 1] + (point - prev_point).length();
+            prev_point = point;
+        }
+        SegmentLengths { lengths }
+    }
+
+    #[inline]
+    fn total(&self) -> f32 {
+        self.lengths[DASH_SAMPLE_COUNT]
+    }
+
+    fn t_at(&self, distance: f32) -> f32 {
+        if distance <= 0.0 {
+            return 0.0;
+        }
+        for sample_index in 1..(DASH_SAMPLE_COUNT + 1) {
+            let (length_0, length_1) = (self.lengths[sample_index - 1], self.lengths[sample_index]);
+            if distance <= length_1 {
+                let fraction = if length_1 > length_0 {
+                    (distance - length_0) / (length_1 - length_0)
+                } else {
+                    0.0
+                };
+                return (sample_index as f32 - 1.0 + fraction) / DASH_SAMPLE_COUNT as f32;
+            }
+        }
+        1.0
+    }
 }
 
 trait Offset {
@@ -191,3 +587,146 @@ impl Offset for SegmentPF3 {
         const SAMPLE_COUNT: u32 = 16;
     }
 }
+
+#[cfg(test)]
+mod test {
+    use crate::basic::line_segment::LineSegmentF32;
+    use crate::basic::point::Point2DF32;
+    use crate::outline::Outline;
+    use crate::segment::{Segment, SegmentFlags};
+    use crate::stroke::{LineCap, LineJoin, OutlineStrokeToFill, StrokeStyle};
+
+    fn horizontal_line(length: f32) -> Outline {
+        let mut segment = Segment::line(&LineSegmentF32::new(&Point2DF32::new(0.0, 0.0),
+                                                             &Point2DF32::new(length, 0.0)));
+        segment.flags = SegmentFlags::FIRST_IN_SUBPATH;
+        Outline::from_segments(vec![segment].into_iter())
+    }
+
+    // Strokes a right angle at (10, 0) two units wide, so the outer corner is at (11, -1).
+    fn stroke_corner(line_join: LineJoin, miter_limit: f32) -> Outline {
+        let points = [Point2DF32::new(0.0, 0.0),
+                      Point2DF32::new(10.0, 0.0),
+                      Point2DF32::new(10.0, 10.0)];
+        let segments: Vec<_> = points.windows(2).enumerate().map(|(index, pair)| {
+            let mut segment = Segment::line(&LineSegmentF32::new(&pair[0], &pair[1]));
+            if index == 0 {
+                segment.flags = SegmentFlags::FIRST_IN_SUBPATH;
+            }
+            segment
+        }).collect();
+        let style = StrokeStyle {
+            line_width: 2.0,
+            line_join,
+            miter_limit,
+            ..StrokeStyle::default()
+        };
+        let outline = Outline::from_segments(segments.into_iter());
+        let mut stroke_to_fill = OutlineStrokeToFill::new(outline, style);
+        stroke_to_fill.offset();
+        stroke_to_fill.outline
+    }
+
+    fn has_endpoint(outline: &Outline, point: Point2DF32) -> bool {
+        outline.contours.iter().any(|contour| {
+            (0..contour.len()).any(|index| {
+                contour.point_is_endpoint(index) &&
+                    (contour.position_of(index) - point).length() < 0.001
+            })
+        })
+    }
+
+    fn has_curve(outline: &Outline) -> bool {
+        outline.contours.iter().any(|contour| contour.iter().any(|segment| !segment.is_line()))
+    }
+
+    fn assert_beveled(outline: &Outline) {
+        assert!(!has_endpoint(outline, Point2DF32::new(11.0, -1.0)));
+        assert!(has_endpoint(outline, Point2DF32::new(10.0, -1.0)));
+        assert!(has_endpoint(outline, Point2DF32::new(11.0, 0.0)));
+        assert!(!has_curve(outline));
+    }
+
+    #[test]
+    fn test_dashes() {
+        let style = StrokeStyle { dash_array: vec![2.0, 1.0], ..StrokeStyle::default() };
+        let mut stroke_to_fill = OutlineStrokeToFill::new(horizontal_line(10.0), style);
+        stroke_to_fill.offset();
+        // Dashes start at 0, 3, 6 and 9.
+        assert_eq!(stroke_to_fill.outline.contours.len(), 4);
+    }
+
+    #[test]
+    fn test_zero_length_dashes() {
+        // Round dots at 0, 4 and 8.
+        let style = StrokeStyle {
+            line_width: 2.0,
+            line_cap: LineCap::Round,
+            dash_array: vec![0.0, 4.0],
+            ..StrokeStyle::default()
+        };
+        let mut stroke_to_fill = OutlineStrokeToFill::new(horizontal_line(8.0), style);
+        stroke_to_fill.offset();
+        assert_eq!(stroke_to_fill.outline.contours.len(), 3);
+        for (index, contour) in stroke_to_fill.outline.contours.iter().enumerate() {
+            let center = index as f32 * 4.0;
+            let bounds = contour.bounds();
+            assert!((bounds.min_x() - (center - 1.0)).abs() < 0.001);
+            assert!((bounds.max_x() - (center + 1.0)).abs() < 0.001);
+            assert!((bounds.min_y() - -1.0).abs() < 0.001);
+            assert!((bounds.max_y() - 1.0).abs() < 0.001);
+        }
+
+        // Butt caps leave nothing to draw.
+        let style = StrokeStyle { dash_array: vec![0.0, 4.0], ..StrokeStyle::default() };
+        let mut stroke_to_fill = OutlineStrokeToFill::new(horizontal_line(8.0), style);
+        stroke_to_fill.offset();
+        assert!(stroke_to_fill.outline.contours.is_empty());
+    }
+
+    #[test]
+    fn test_miter_join() {
+        // The miter of a right angle is √2 times as long as the line is wide.
+        let outline = stroke_corner(LineJoin::Miter, 1.5);
+        assert!(has_endpoint(&outline, Point2DF32::new(11.0, -1.0)));
+        assert!(!has_curve(&outline));
+    }
+
+    #[test]
+    fn test_miter_join_over_limit_is_beveled() {
+        assert_beveled(&stroke_corner(LineJoin::Miter, 1.3));
+    }
+
+    #[test]
+    fn test_bevel_join() {
+        assert_beveled(&stroke_corner(LineJoin::Bevel, 4.0));
+    }
+
+    #[test]
+    fn test_round_join() {
+        let outline = stroke_corner(LineJoin::Round, 4.0);
+        assert!(!has_endpoint(&outline, Point2DF32::new(11.0, -1.0)));
+        let arc = outline.contours.iter().flat_map(|contour| contour.iter()).find(|segment| {
+            !segment.is_line()
+        }).unwrap();
+        let middle = arc.sample(0.5) - Point2DF32::new(10.0, 0.0);
+        assert!((middle.x() - 0.5f32.sqrt()).abs() < 0.001);
+        assert!((middle.y() + 0.5f32.sqrt()).abs() < 0.001);
+    }
+
+    #[test]
+    fn test_square_cap() {
+        let style = StrokeStyle {
+            line_width: 2.0,
+            line_cap: LineCap::Square,
+            ..StrokeStyle::default()
+        };
+        let mut stroke_to_fill = OutlineStrokeToFill::new(horizontal_line(10.0), style);
+        stroke_to_fill.offset();
+        let bounds = stroke_to_fill.outline.bounds();
+        assert!((bounds.min_x() - -1.0).abs() < 0.001);
+        assert!((bounds.max_x() - 11.0).abs() < 0.001);
+        assert!((bounds.min_y() - -1.0).abs() < 0.001);
+        assert!((bounds.max_y() - 1.0).abs() < 0.001);
+    }
+}
@@ -15,7 +15,7 @@ use pathfinder_geometry::basic::point::Point2DI32;
 use pathfinder_geometry::basic::rect::RectI32;
 use pathfinder_gpu::{BlendState, BufferData, BufferTarget, BufferUploadMode, DepthFunc, Device};
 use pathfinder_gpu::{Primitive, RenderState, ShaderKind, StencilFunc, TextureFormat};
-use pathfinder_gpu::{UniformData, VertexAttrType};
+use pathfinder_gpu::{TextureData, UniformData, VertexAttrType};
 use pathfinder_simd::default::F32x4;
 use rustache::{HashBuilder, Render};
 use std::ffi::CString;
@@ -80,6 +80,11 @@ impl GLDevice {
                                           gl::ONE); ck();
                     gl::Enable(gl::BLEND); ck();
                 }
+                BlendState::RGBDstColorAlphaZero => {
+                    gl::BlendEquation(gl::FUNC_ADD); ck();
+                    gl::BlendFunc(gl::DST_COLOR, gl::ZERO); ck();
+                    gl::Enable(gl::BLEND); ck();
+                }
             }
 
             // Set depth.
@@ -126,7 +131,8 @@ impl GLDevice {
                 BlendState::Off => {}
                 BlendState::RGBOneAlphaOneMinusSrcAlpha |
                 BlendState::RGBOneAlphaOne |
-                BlendState::RGBSrcAlphaAlphaOneMinusSrcAlpha => {
+                BlendState::RGBSrcAlphaAlphaOneMinusSrcAlpha |
+                BlendState::RGBDstColorAlphaZero => {
                     gl::Disable(gl::BLEND); ck();
                 }
             }
@@ -174,6 +180,11 @@ impl Device for GLDevice {
                 gl_format = gl::RGBA;
                 gl_type = gl::UNSIGNED_BYTE;
             }
+            TextureFormat::RGBA32F => {
+                gl_internal_format = gl::RGBA32F as GLint;
+                gl_format = gl::RGBA;
+                gl_type = gl::FLOAT;
+            }
         }
 
         let mut texture = GLTexture { gl_texture: 0, size };
@@ -367,6 +378,9 @@ impl Device for GLDevice {
                 UniformData::Int(value) => {
                     gl::Uniform1i(uniform.location, value); ck();
                 }
+                UniformData::Float(value) => {
+                    gl::Uniform1f(uniform.location, value); ck();
+                }
                 UniformData::Mat4(data) => {
                     assert_eq!(mem::size_of::<[F32x4; 4]>(), 4 * 4 * 4);
                     let data_ptr: *const F32x4 = data.as_ptr();
@@ -444,19 +458,30 @@ impl Device for GLDevice {
         texture.size
     }
 
-    fn upload_to_texture(&self, texture: &Self::Texture, size: Point2DI32, data: &[u8]) {
-        assert!(data.len() >= size.x() as usize * size.y() as usize * 4);
+    fn upload_to_texture(&self, texture: &Self::Texture, size: Point2DI32, data: TextureData) {
+        let texel_count = size.x() as usize * size.y() as usize;
+        let (gl_internal_format, gl_type, data_ptr) = match data {
+            TextureData::U8(data) => {
+                assert!(data.len() >= texel_count * 4);
+                (gl::RGBA, gl::UNSIGNED_BYTE, data.as_ptr() as *const GLvoid)
+            }
+            TextureData::F32(data) => {
+                assert!(data.len() >= texel_count * 4);
+                (gl::RGBA32F, gl::FLOAT, data.as_ptr() as *const GLvoid)
+            }
+        };
+
         unsafe {
             self.bind_texture(texture, 0);
             gl::TexImage2D(gl::TEXTURE_2D,
                            0,
-                           gl::RGBA as GLint,
+                           gl_internal_format as GLint,
                            size.x() as GLsizei,
                            size.y() as GLsizei,
                            0,
                            gl::RGBA,
-                           gl::UNSIGNED_BYTE,
-                           data.as_ptr() as *const GLvoid); ck();
+                           gl_type,
+                           data_ptr); ck();
         }
 
         self.set_texture_parameters(texture);
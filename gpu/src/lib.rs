@@ -73,7 +73,7 @@ pub trait Device {
                           mode: BufferUploadMode);
     fn framebuffer_texture<'f>(&self, framebuffer: &'f Self::Framebuffer) -> &'f Self::Texture;
     fn texture_size(&self, texture: &Self::Texture) -> Point2DI32;
-    fn upload_to_texture(&self, texture: &Self::Texture, size: Point2DI32, data: &[u8]);
+    fn upload_to_texture(&self, texture: &Self::Texture, size: Point2DI32, data: TextureData);
     fn read_pixels_from_default_framebuffer(&self, size: Point2DI32) -> Vec<u8>;
     // TODO(pcwalton): Switch to `ColorF`!
     fn clear(&self, color: Option<F32x4>, depth: Option<f32>, stencil: Option<u8>);
@@ -116,6 +116,7 @@ pub trait Device {
             |_| load_shader_include(resources, "tile_monochrome");
         let mut load_include_tile_multicolor =
             |_| load_shader_include(resources, "tile_multicolor");
+        let mut load_include_tile_paint = |_| load_shader_include(resources, "tile_paint");
         let mut load_include_tile_solid_vertex =
             |_| load_shader_include(resources, "tile_solid_vertex");
         let mut load_include_post_convolve = |_| load_shader_include(resources, "post_convolve");
@@ -128,6 +129,7 @@ pub trait Device {
                                              &mut load_include_tile_monochrome)
                               .insert_lambda("include_tile_multicolor",
                                              &mut load_include_tile_multicolor)
+                              .insert_lambda("include_tile_paint", &mut load_include_tile_paint)
                               .insert_lambda("include_tile_solid_vertex",
                                              &mut load_include_tile_solid_vertex)
                               .insert_lambda("include_post_convolve",
@@ -161,6 +163,7 @@ pub enum TextureFormat {
     R8,
     R16F,
     RGBA8,
+    RGBA32F,
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -178,6 +181,13 @@ pub enum BufferData<'a, T> {
     Memory(&'a [T]),
 }
 
+/// RGBA texel data, with four components per texel.
+#[derive(Clone, Copy, Debug)]
+pub enum TextureData<'a> {
+    U8(&'a [u8]),
+    F32(&'a [f32]),
+}
+
 #[derive(Clone, Copy, Debug)]
 pub enum BufferTarget {
     Vertex,
@@ -199,6 +209,7 @@ pub enum ShaderKind {
 #[derive(Clone, Copy)]
 pub enum UniformData {
     Int(i32),
+    Float(f32),
     Mat4([F32x4; 4]),
     Vec2(F32x4),
     Vec4(F32x4),
@@ -226,6 +237,7 @@ pub enum BlendState {
     RGBOneAlphaOne,
     RGBOneAlphaOneMinusSrcAlpha,
     RGBSrcAlphaAlphaOneMinusSrcAlpha,
+    RGBDstColorAlphaZero,
 }
 
 #[derive(Clone, Copy, Default, Debug)]
@@ -10,11 +10,14 @@
 
 //! Packs data onto the GPU.
 
-use crate::gpu_data::{AlphaTileBatchPrimitive, RenderCommand};
+use crate::clip::{self, ClipTileMap};
+use crate::gpu_data::{AlphaTileBatchPrimitive, ClipTileBatchPrimitive, LayerTileBatchPrimitive};
+use crate::gpu_data::{RenderCommand, SolidTileBatchPrimitive};
 use crate::scene::Scene;
-use crate::tiles::Tiler;
+use crate::tile_map::DenseTileMap;
+use crate::tiles::{self, Tiler};
 use crate::z_buffer::ZBuffer;
-use pathfinder_geometry::basic::point::{Point2DF32, Point3DF32};
+use pathfinder_geometry::basic::point::{Point2DF32, Point2DI32, Point3DF32};
 use pathfinder_geometry::basic::rect::RectF32;
 use pathfinder_geometry::basic::transform2d::Transform2DF32;
 use pathfinder_geometry::basic::transform3d::Perspective;
@@ -37,6 +40,9 @@ pub struct SceneBuilder<'a> {
 
     pub(crate) next_alpha_tile_index: AtomicUsize,
     pub(crate) z_buffer: ZBuffer,
+    pub(crate) layer_z_buffers: Vec<ZBuffer>,
+    pub(crate) mask_z_buffers: Vec<Option<ZBuffer>>,
+    pub(crate) clip_tile_maps: Vec<ClipTileMap>,
     pub(crate) listener: Box<dyn RenderCommandListener>,
 }
 
@@ -46,18 +52,28 @@ impl<'a> SceneBuilder<'a> {
                listener: Box<dyn RenderCommandListener>)
                -> SceneBuilder<'a> {
         let effective_view_box = scene.effective_view_box(built_options);
+        let layer_z_buffers =
+            scene.layers.iter().map(|_| ZBuffer::new(effective_view_box)).collect();
+        let mask_z_buffers = scene.layers.iter().map(|layer| {
+            layer.mask.as_ref().map(|_| ZBuffer::new(effective_view_box))
+        }).collect();
         SceneBuilder {
             scene,
             built_options,
 
             next_alpha_tile_index: AtomicUsize::new(0),
             z_buffer: ZBuffer::new(effective_view_box),
+            layer_z_buffers,
+            mask_z_buffers,
+            clip_tile_maps: vec![],
             listener,
         }
     }
 
     pub fn build_sequentially(&mut self) {
         let effective_view_box = self.scene.effective_view_box(self.built_options);
+        let clip_tiles = self.build_clip_paths(effective_view_box);
+
         let object_count = self.scene.objects.len();
         let alpha_tiles: Vec<_> = (0..object_count).into_iter().flat_map(|object_index| {
             self.build_object(object_index,
@@ -66,11 +82,13 @@ impl<'a> SceneBuilder<'a> {
                               &self.scene)
         }).collect();
 
-        self.finish_building(alpha_tiles)
+        self.finish_building(clip_tiles, alpha_tiles)
     }
 
     pub fn build_in_parallel(&mut self) {
         let effective_view_box = self.scene.effective_view_box(self.built_options);
+        let clip_tiles = self.build_clip_paths(effective_view_box);
+
         let object_count = self.scene.objects.len();
         let alpha_tiles: Vec<_> = (0..object_count).into_par_iter().flat_map(|object_index| {
             self.build_object(object_index,
@@ -79,7 +97,28 @@ impl<'a> SceneBuilder<'a> {
                               &self.scene)
         }).collect();
 
-        self.finish_building(alpha_tiles)
+        self.finish_building(clip_tiles, alpha_tiles)
+    }
+
+    // Clip paths are few, so we tile them sequentially before any objects that use them.
+    fn build_clip_paths(&mut self, view_box: RectF32) -> Vec<ClipTileBatchPrimitive> {
+        if self.scene.clip_paths.is_empty() {
+            return vec![];
+        }
+
+        let mut masks = Vec::with_capacity(self.scene.clip_paths.len());
+        for clip_path in &self.scene.clip_paths {
+            let outline = self.scene.apply_render_options(&clip_path.outline, self.built_options);
+            let mut tiler = Tiler::new(self, &outline, clip_path.fill_rule, view_box, 0, None);
+            tiler.generate_strips();
+            self.listener.send(RenderCommand::AddFills(tiler.built_object.fills));
+            masks.push(tiler.built_object.tiles);
+        }
+
+        let (clip_tile_maps, clip_tiles) = clip::resolve_clip_paths(&self.scene.clip_paths,
+                                                                    &masks);
+        self.clip_tile_maps = clip_tile_maps;
+        clip_tiles
     }
 
     fn build_object(&self,
@@ -90,18 +129,41 @@ impl<'a> SceneBuilder<'a> {
                     -> Vec<AlphaTileBatchPrimitive> {
         let object = &scene.objects[object_index];
         let outline = scene.apply_render_options(object.outline(), built_options);
-
-        let mut tiler = Tiler::new(self, &outline, view_box, object_index as u16);
+        let clip = object.clip_path().map(|clip_path| &self.clip_tile_maps[clip_path.0 as usize]);
+
+        let mut tiler = Tiler::new(self,
+                                   &outline,
+                                   object.fill_rule(),
+                                   view_box,
+                                   object_index as u16,
+                                   clip);
         tiler.generate_tiles();
 
         self.listener.send(RenderCommand::AddFills(tiler.built_object.fills));
         tiler.built_object.alpha_tiles
     }
 
+    /// Objects in a layer or a mask only occlude other objects in the same layer or mask.
+    pub(crate) fn z_buffer_for_object(&self, object_index: u16) -> &ZBuffer {
+        if let Some(layer_id) = self.scene.layer_of_object(object_index as u32) {
+            return &self.layer_z_buffers[layer_id.0 as usize];
+        }
+        match self.scene.masked_layer_of_object(object_index as u32) {
+            None => &self.z_buffer,
+            Some(layer_id) => self.mask_z_buffers[layer_id.0 as usize].as_ref().unwrap(),
+        }
+    }
+
+    #[inline]
+    pub(crate) fn object_is_opaque(&self, object_index: u16) -> bool {
+        self.scene.paint_is_opaque(self.scene.objects[object_index as usize].paint())
+    }
+
     fn cull_alpha_tiles(&self, alpha_tiles: &mut Vec<AlphaTileBatchPrimitive>) {
         for alpha_tile in alpha_tiles {
             let alpha_tile_coords = alpha_tile.tile_coords();
-            if self.z_buffer.test(alpha_tile_coords, alpha_tile.object_index as u32) {
+            let z_buffer = self.z_buffer_for_object(alpha_tile.object_index);
+            if z_buffer.test(alpha_tile_coords, alpha_tile.object_index as u32) {
                 continue;
             }
 
@@ -118,18 +180,131 @@ impl<'a> SceneBuilder<'a> {
         if !solid_tiles.is_empty() {
             self.listener.send(RenderCommand::SolidTile(solid_tiles));
         }
+
+        // Alpha tiles are in object order, so each layer's tiles, followed by those of its mask,
+        // form a contiguous run that must be drawn between the tiles of the objects below and
+        // above it.
+        let mut next_alpha_tile_index = 0;
+        for (layer_index, layer) in self.scene.layers.iter().enumerate() {
+            let start = next_alpha_tile_index +
+                count_alpha_tiles_before(&alpha_tiles[next_alpha_tile_index..],
+                                         layer.objects.start);
+            let end = start + count_alpha_tiles_before(&alpha_tiles[start..], layer.objects.end);
+            let mask_end = end + count_alpha_tiles_before(&alpha_tiles[end..],
+                                                          layer.all_objects().end);
+            self.send_alpha_tiles(&alpha_tiles[next_alpha_tile_index..start]);
+            next_alpha_tile_index = mask_end;
+
+            let layer_solid_tiles =
+                self.layer_z_buffers[layer_index].build_solid_tiles(layer.objects.clone());
+            let layer_alpha_tiles = &alpha_tiles[start..end];
+            let mut covered = self.build_tile_coverage(&layer_solid_tiles, layer_alpha_tiles);
+
+            // Outside its mask, the layer is fully transparent.
+            let mut mask_solid_tiles = vec![];
+            let mask_alpha_tiles = &alpha_tiles[end..mask_end];
+            if let Some(ref mask) = layer.mask {
+                let mask_z_buffer = self.mask_z_buffers[layer_index].as_ref().unwrap();
+                mask_solid_tiles = mask_z_buffer.build_solid_tiles(mask.objects.clone());
+                let mask_covered = self.build_tile_coverage(&mask_solid_tiles, mask_alpha_tiles);
+                for (tile_covered, mask_tile_covered) in covered.data
+                                                                .iter_mut()
+                                                                .zip(mask_covered.data.iter()) {
+                    *tile_covered = *tile_covered && *mask_tile_covered;
+                }
+            }
+
+            let composite_tiles = self.build_layer_composite_tiles(layer.objects.end, &covered);
+            if composite_tiles.is_empty() {
+                continue;
+            }
+
+            self.listener.send(RenderCommand::BeginLayer);
+            if !layer_solid_tiles.is_empty() {
+                self.listener.send(RenderCommand::SolidTile(layer_solid_tiles));
+            }
+            self.send_alpha_tiles(layer_alpha_tiles);
+
+            if layer.mask.is_some() {
+                self.listener.send(RenderCommand::BeginMask);
+                if !mask_solid_tiles.is_empty() {
+                    self.listener.send(RenderCommand::SolidTile(mask_solid_tiles));
+                }
+                self.send_alpha_tiles(mask_alpha_tiles);
+            }
+
+            self.listener.send(RenderCommand::CompositeLayer {
+                opacity: layer.opacity,
+                mask: layer.mask.as_ref().map(|mask| mask.kind),
+                tiles: composite_tiles,
+            });
+        }
+
+        self.send_alpha_tiles(&alpha_tiles[next_alpha_tile_index..]);
+    }
+
+    fn send_alpha_tiles(&self, alpha_tiles: &[AlphaTileBatchPrimitive]) {
         if !alpha_tiles.is_empty() {
-            self.listener.send(RenderCommand::AlphaTile(alpha_tiles));
+            self.listener.send(RenderCommand::AlphaTile(alpha_tiles.to_vec()));
+        }
+    }
+
+    // Returns a map of the tiles that any of the given tiles touch.
+    fn build_tile_coverage(&self,
+                           solid_tiles: &[SolidTileBatchPrimitive],
+                           alpha_tiles: &[AlphaTileBatchPrimitive])
+                           -> DenseTileMap<bool> {
+        let effective_view_box = self.scene.effective_view_box(self.built_options);
+        let tile_rect = tiles::round_rect_out_to_tile_bounds(effective_view_box);
+        let mut covered: DenseTileMap<bool> = DenseTileMap::new(tile_rect);
+
+        let solid_tile_coords = solid_tiles.iter().map(|tile| {
+            Point2DI32::new(tile.tile_x as i32, tile.tile_y as i32)
+        });
+        let alpha_tile_coords = alpha_tiles.iter().map(|tile| tile.tile_coords());
+        for tile_coords in solid_tile_coords.chain(alpha_tile_coords) {
+            // Culled alpha tiles have out-of-range coordinates and are skipped here.
+            if let Some(tile_index) = covered.coords_to_index(tile_coords) {
+                covered.data[tile_index] = true;
+            }
         }
+        covered
     }
 
-    fn finish_building(&mut self, mut alpha_tiles: Vec<AlphaTileBatchPrimitive>) {
+    // Returns the covered tiles of the layer, minus those hidden beneath solid tiles of objects
+    // above the layer.
+    fn build_layer_composite_tiles(&self, layer_objects_end: u32, covered: &DenseTileMap<bool>)
+                                   -> Vec<LayerTileBatchPrimitive> {
+        let mut composite_tiles = vec![];
+        for (tile_index, &tile_covered) in covered.data.iter().enumerate() {
+            let tile_coords = covered.index_to_coords(tile_index);
+            if tile_covered && self.z_buffer.test(tile_coords, layer_objects_end - 1) {
+                composite_tiles.push(LayerTileBatchPrimitive {
+                    tile_x: tile_coords.x() as i16,
+                    tile_y: tile_coords.y() as i16,
+                });
+            }
+        }
+        composite_tiles
+    }
+
+    fn finish_building(&mut self,
+                       clip_tiles: Vec<ClipTileBatchPrimitive>,
+                       mut alpha_tiles: Vec<AlphaTileBatchPrimitive>) {
         self.listener.send(RenderCommand::FlushFills);
+        if !clip_tiles.is_empty() {
+            self.listener.send(RenderCommand::ClipTiles(clip_tiles));
+        }
         self.cull_alpha_tiles(&mut alpha_tiles);
         self.pack_alpha_tiles(alpha_tiles);
     }
 }
 
+// Returns the number of leading tiles that belong to objects below `object_index`.
+fn count_alpha_tiles_before(alpha_tiles: &[AlphaTileBatchPrimitive], object_index: u32) -> usize {
+    alpha_tiles.iter().take_while(|tile| (tile.object_index as u32) < object_index).count()
+}
+
 #[derive(Clone, Default)]
 pub struct RenderOptions {
     pub transform: RenderTransform,
@@ -225,6 +400,21 @@ impl PreparedRenderOptions {
             _ => [Point3DF32::default(); 4],
         }
     }
+
+    /// The 2D transform from scene space to tile space, including the horizontal scale that
+    /// subpixel antialiasing needs. Not meaningful for perspective transforms.
+    pub(crate) fn tile_transform_2d(&self) -> Transform2DF32 {
+        let mut transform = match self.transform {
+            PreparedRenderTransform::Transform2D(transform) => transform,
+            PreparedRenderTransform::None | PreparedRenderTransform::Perspective { .. } => {
+                Transform2DF32::default()
+            }
+        };
+        if self.subpixel_aa_enabled {
+            transform = transform.post_mul(&Transform2DF32::from_scale(&Point2DF32::new(3.0, 1.0)))
+        }
+        transform
+    }
 }
 
 pub enum PreparedRenderTransform {
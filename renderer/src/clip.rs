@@ -0,0 +1,111 @@
+// pathfinder/renderer/src/clip.rs
+//
+// Copyright © 2019 The Pathfinder Project Developers.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Resolves clip stacks into per-tile coverage.
+//!
+//! Clip paths are tiled like any other path, which leaves their coverage in the mask framebuffer.
+//! For every tile that a clip stack covers only partially, we allocate a tile in the clip
+//! framebuffer and multiply into it the coverage of each partially covering clip path in the
+//! stack. Alpha tiles of clipped objects then sample that tile.
+
+use crate::gpu_data::{ClipTileBatchPrimitive, TileObjectPrimitive};
+use crate::scene::{ClipPath, ClipPathId};
+use crate::tile_map::DenseTileMap;
+use pathfinder_geometry::basic::point::Point2DI32;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum ClipTile {
+    /// Everything in this tile is clipped out.
+    Empty,
+    /// Nothing in this tile is clipped out.
+    Full,
+    /// Coverage is given by this tile of the clip framebuffer.
+    Partial(u16),
+}
+
+impl Default for ClipTile {
+    #[inline]
+    fn default() -> ClipTile {
+        ClipTile::Empty
+    }
+}
+
+pub(crate) type ClipTileMap = DenseTileMap<ClipTile>;
+
+impl DenseTileMap<ClipTile> {
+    #[inline]
+    pub(crate) fn clip_tile(&self, coords: Point2DI32) -> ClipTile {
+        match self.coords_to_index(coords) {
+            Some(index) => self.data[index],
+            None => ClipTile::Empty,
+        }
+    }
+}
+
+/// Computes the effective clip of every clip path, given the tiles of each clip path's own
+/// outline. Parents must precede their children.
+pub(crate) fn resolve_clip_paths(clip_paths: &[ClipPath],
+                                 masks: &[DenseTileMap<TileObjectPrimitive>])
+                                 -> (Vec<ClipTileMap>, Vec<ClipTileBatchPrimitive>) {
+    let mut clip_tile_maps: Vec<ClipTileMap> = Vec::with_capacity(clip_paths.len());
+    let mut clip_tiles = vec![];
+    let mut next_clip_tile_index = 0;
+
+    for (clip_path_index, clip_path) in clip_paths.iter().enumerate() {
+        let mask = &masks[clip_path_index];
+        let mut clip_tile_map = ClipTileMap::new(mask.rect);
+        for (tile_index, tile) in mask.data.iter().enumerate() {
+            let tile_coords = mask.index_to_coords(tile_index);
+            let parent_clip_tile = match clip_path.parent {
+                None => ClipTile::Full,
+                Some(parent) => clip_tile_maps[parent.0 as usize].clip_tile(tile_coords),
+            };
+
+            let tile_is_outside = tile.is_solid() &&
+                !clip_path.fill_rule.backdrop_is_inside(tile.backdrop);
+            clip_tile_map.data[tile_index] = if tile_is_outside ||
+                    parent_clip_tile == ClipTile::Empty {
+                ClipTile::Empty
+            } else if tile.is_solid() && parent_clip_tile == ClipTile::Full {
+                ClipTile::Full
+            } else {
+                // The shader reads clip tile indices as signed 16-bit integers, with negative
+                // values meaning "not clipped".
+                let dest_tile_index = next_clip_tile_index;
+                assert!(dest_tile_index < 0x8000, "too many partially clipped tiles");
+                next_clip_tile_index += 1;
+
+                // Walk up the stack, multiplying in every partially covering clip path.
+                let mut current = Some(ClipPathId(clip_path_index as u16));
+                while let Some(clip_path_id) = current {
+                    let mask = &masks[clip_path_id.0 as usize];
+                    let clip_path = &clip_paths[clip_path_id.0 as usize];
+                    if let Some(mask_tile_index) = mask.coords_to_index(tile_coords) {
+                        let mask_tile = &mask.data[mask_tile_index];
+                        if !mask_tile.is_solid() {
+                            clip_tiles.push(ClipTileBatchPrimitive {
+                                dest_tile_index,
+                                src_tile_index: mask_tile.alpha_tile_index,
+                                src_backdrop: mask_tile.backdrop,
+                                src_fill_rule: clip_path.fill_rule as u8,
+                            });
+                        }
+                    }
+                    current = clip_path.parent;
+                }
+
+                ClipTile::Partial(dest_tile_index)
+            };
+        }
+        clip_tile_maps.push(clip_tile_map);
+    }
+
+    (clip_tile_maps, clip_tiles)
+}
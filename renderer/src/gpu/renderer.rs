@@ -9,18 +9,21 @@
 // except according to those terms.
 
 use crate::gpu::debug::DebugUI;
-use crate::gpu_data::{AlphaTileBatchPrimitive, FillBatchPrimitive};
-use crate::gpu_data::{RenderCommand, SolidTileBatchPrimitive};
+use crate::gpu_data::{AlphaTileBatchPrimitive, ClipTileBatchPrimitive, FillBatchPrimitive};
+use crate::gpu_data::{LayerTileBatchPrimitive, RenderCommand, SolidTileBatchPrimitive};
+use crate::gradient::{gradient_ramp_texture_size, GradientId, GradientKind, SpreadMethod};
+use crate::gradient::GRADIENT_RAMP_LENGTH;
+use crate::image::{Image, ImageAtlas};
 use crate::post::DefringingKernel;
-use crate::scene::{ObjectShader, SceneDescriptor};
+use crate::scene::{MaskKind, ObjectShader, SceneDescriptor};
 use crate::tiles::{TILE_HEIGHT, TILE_WIDTH};
 use pathfinder_geometry::basic::point::{Point2DI32, Point3DF32};
 use pathfinder_geometry::basic::rect::RectI32;
-use pathfinder_geometry::color::ColorF;
+use pathfinder_geometry::color::{ColorF, ColorU};
 use pathfinder_gpu::resources::ResourceLoader;
 use pathfinder_gpu::{BlendState, BufferData, BufferTarget, BufferUploadMode, DepthFunc};
 use pathfinder_gpu::{DepthState, Device, Primitive, RenderState, StencilFunc, StencilState};
-use pathfinder_gpu::{TextureFormat, UniformData, VertexAttrType};
+use pathfinder_gpu::{TextureData, TextureFormat, UniformData, VertexAttrType};
 use pathfinder_simd::default::{F32x4, I32x4};
 use std::cmp;
 use std::collections::VecDeque;
@@ -37,11 +40,18 @@ const MASK_FRAMEBUFFER_HEIGHT: i32 = TILE_HEIGHT as i32 * 256;
 // TODO(pcwalton): Replace with `mem::size_of` calls?
 const FILL_INSTANCE_SIZE: usize = 8;
 const SOLID_TILE_INSTANCE_SIZE: usize = 6;
-const MASK_TILE_INSTANCE_SIZE: usize = 8;
+const MASK_TILE_INSTANCE_SIZE: usize = 12;
+const CLIP_TILE_INSTANCE_SIZE: usize = 6;
+const LAYER_TILE_INSTANCE_SIZE: usize = 4;
 
 const FILL_COLORS_TEXTURE_WIDTH: i32 = 256;
 const FILL_COLORS_TEXTURE_HEIGHT: i32 = 256;
 
+// Each object gets `PAINT_DATA_TEXELS_PER_OBJECT` RGBA32F texels describing its gradient or image,
+// if any.
+const PAINT_DATA_TEXTURE_WIDTH: i32 = 1024;
+const PAINT_DATA_TEXELS_PER_OBJECT: usize = 4;
+
 const MAX_FILLS_PER_BATCH: usize = 0x4000;
 
 pub struct Renderer<D> where D: Device {
@@ -64,6 +74,21 @@ pub struct Renderer<D> where D: Device {
     fill_vertex_array: FillVertexArray<D>,
     mask_framebuffer: D::Framebuffer,
     fill_colors_texture: D::Texture,
+    paint_data_texture: D::Texture,
+    gradient_ramps_texture: D::Texture,
+    image_atlas_texture: D::Texture,
+    image_atlas: ImageAtlas,
+
+    // Clip shader
+    clip_tile_program: ClipTileProgram<D>,
+    clip_tile_vertex_array: ClipTileVertexArray<D>,
+    clip_framebuffer: Option<D::Framebuffer>,
+
+    // Layer shader
+    layer_tile_program: LayerTileProgram<D>,
+    layer_tile_vertex_array: LayerTileVertexArray<D>,
+    layer_framebuffer: Option<D::Framebuffer>,
+    layer_mask_framebuffer: Option<D::Framebuffer>,
 
     // Postprocessing shader
     postprocess_source_framebuffer: Option<D::Framebuffer>,
@@ -77,6 +102,8 @@ pub struct Renderer<D> where D: Device {
 
     // Rendering state
     mask_framebuffer_cleared: bool,
+    clip_framebuffer_cleared: bool,
+    layer_target: Option<LayerTarget>,
     buffered_fills: Vec<FillBatchPrimitive>,
 
     // Debug
@@ -101,6 +128,8 @@ impl<D> Renderer<D> where D: Device {
         let solid_monochrome_tile_program = SolidTileMonochromeProgram::new(&device, resources);
         let alpha_monochrome_tile_program = AlphaTileMonochromeProgram::new(&device, resources);
 
+        let clip_tile_program = ClipTileProgram::new(&device, resources);
+        let layer_tile_program = LayerTileProgram::new(&device, resources);
         let postprocess_program = PostprocessProgram::new(&device, resources);
         let stencil_program = StencilProgram::new(&device, resources);
 
@@ -132,6 +161,12 @@ impl<D> Renderer<D> where D: Device {
             SolidTileVertexArray::new(&device,
                                       &solid_monochrome_tile_program.solid_tile_program,
                                       &quad_vertex_positions_buffer);
+        let clip_tile_vertex_array = ClipTileVertexArray::new(&device,
+                                                              &clip_tile_program,
+                                                              &quad_vertex_positions_buffer);
+        let layer_tile_vertex_array = LayerTileVertexArray::new(&device,
+                                                                &layer_tile_program,
+                                                                &quad_vertex_positions_buffer);
         let postprocess_vertex_array = PostprocessVertexArray::new(&device,
                                                                    &postprocess_program,
                                                                    &quad_vertex_positions_buffer);
@@ -147,6 +182,15 @@ impl<D> Renderer<D> where D: Device {
                                                FILL_COLORS_TEXTURE_HEIGHT);
         let fill_colors_texture = device.create_texture(TextureFormat::RGBA8, fill_colors_size);
 
+        // These are resized as needed in `upload_shaders()`.
+        let paint_data_texture = device.create_texture(TextureFormat::RGBA32F,
+                                                       Point2DI32::new(PAINT_DATA_TEXTURE_WIDTH,
+                                                                       1));
+        let gradient_ramps_texture = device.create_texture(TextureFormat::RGBA8,
+                                                           gradient_ramp_texture_size(0));
+        let image_atlas = ImageAtlas::new();
+        let image_atlas_texture = device.create_texture(TextureFormat::RGBA8, image_atlas.size);
+
         let debug_ui = DebugUI::new(&device, resources, dest_framebuffer.window_size(&device));
 
         Renderer {
@@ -167,6 +211,19 @@ impl<D> Renderer<D> where D: Device {
             fill_vertex_array,
             mask_framebuffer,
             fill_colors_texture,
+            paint_data_texture,
+            gradient_ramps_texture,
+            image_atlas_texture,
+            image_atlas,
+
+            clip_tile_program,
+            clip_tile_vertex_array,
+            clip_framebuffer: None,
+
+            layer_tile_program,
+            layer_tile_vertex_array,
+            layer_framebuffer: None,
+            layer_mask_framebuffer: None,
 
             postprocess_source_framebuffer: None,
             postprocess_program,
@@ -183,6 +240,8 @@ impl<D> Renderer<D> where D: Device {
             debug_ui,
 
             mask_framebuffer_cleared: false,
+            clip_framebuffer_cleared: false,
+            layer_target: None,
             buffered_fills: vec![],
 
             render_mode: RenderMode::default(),
@@ -199,13 +258,16 @@ impl<D> Renderer<D> where D: Device {
         self.device.begin_timer_query(&timer_query);
         self.current_timer_query = Some(timer_query);
 
+        self.upload_images(&scene.images);
         self.upload_shaders(&scene.shaders);
+        self.upload_gradient_ramps(&scene.gradient_ramps);
 
         if self.use_depth {
             self.draw_stencil(&scene.bounding_quad);
         }
 
         self.mask_framebuffer_cleared = false;
+        self.clip_framebuffer_cleared = false;
 
         self.stats = RenderStats { object_count: scene.object_count, ..RenderStats::default() };
     }
@@ -214,6 +276,17 @@ impl<D> Renderer<D> where D: Device {
         match *command {
             RenderCommand::AddFills(ref fills) => self.add_fills(fills),
             RenderCommand::FlushFills => self.draw_buffered_fills(),
+            RenderCommand::ClipTiles(ref clip_tiles) => {
+                self.draw_buffered_fills();
+                self.upload_clip_tiles(clip_tiles);
+                self.draw_clip_tiles(clip_tiles.len() as u32);
+            }
+            RenderCommand::BeginLayer => self.begin_layer(LayerTarget::Layer),
+            RenderCommand::BeginMask => self.begin_layer(LayerTarget::Mask),
+            RenderCommand::CompositeLayer { opacity, mask, ref tiles } => {
+                self.upload_layer_tiles(tiles);
+                self.composite_layer(opacity, mask, tiles.len() as u32);
+            }
             RenderCommand::SolidTile(ref solid_tiles) => {
                 let count = solid_tiles.len();
                 self.stats.solid_tile_count += count;
@@ -294,7 +367,104 @@ impl<D> Renderer<D> where D: Device {
             fill_colors[shader_index * 4 + 2] = shader.fill_color.b;
             fill_colors[shader_index * 4 + 3] = shader.fill_color.a;
         }
-        self.device.upload_to_texture(&self.fill_colors_texture, size, &fill_colors);
+        self.device.upload_to_texture(&self.fill_colors_texture,
+                                      size,
+                                      TextureData::U8(&fill_colors));
+
+        let texels_per_row = PAINT_DATA_TEXTURE_WIDTH as usize;
+        let texel_count = cmp::max(shaders.len() * PAINT_DATA_TEXELS_PER_OBJECT, 1);
+        let rows = (texel_count + texels_per_row - 1) / texels_per_row;
+        let size = Point2DI32::new(PAINT_DATA_TEXTURE_WIDTH, rows as i32);
+        let mut paint_data = vec![0.0; texels_per_row * rows * 4];
+        for (shader_index, shader) in shaders.iter().enumerate() {
+            let data = &mut paint_data[(shader_index * PAINT_DATA_TEXELS_PER_OBJECT * 4)..];
+
+            if let Some(ref image) = shader.image {
+                // Scale and offset the transform so that it yields texel coordinates in the
+                // atlas, and pass the image's rect in the atlas along so the shader can clamp or
+                // wrap to it.
+                let rect = self.image_atlas.rects[image.image.0 as usize];
+                let scale = self.image_atlas.scales[image.image.0 as usize];
+                let (origin_x, origin_y) = (rect.min_x() as f32, rect.min_y() as f32);
+                let (scale_x, scale_y) = (scale.x(), scale.y());
+                let t = &image.transform;
+                data[0..4].copy_from_slice(&[scale_x * t[0] + origin_x * t[6],
+                                             scale_x * t[1] + origin_x * t[7],
+                                             scale_x * t[2] + origin_x * t[8],
+                                             3.0]);
+                let spread = if image.repeat { 2.0 } else { 0.0 };
+                data[4..8].copy_from_slice(&[scale_y * t[3] + origin_y * t[6],
+                                             scale_y * t[4] + origin_y * t[7],
+                                             scale_y * t[5] + origin_y * t[8],
+                                             spread]);
+                data[8..12].copy_from_slice(&[t[6], t[7], t[8], 0.0]);
+                data[12..16].copy_from_slice(&[rect.min_x() as f32,
+                                               rect.min_y() as f32,
+                                               rect.max_x() as f32,
+                                               rect.max_y() as f32]);
+                continue;
+            }
+
+            let gradient = match shader.gradient {
+                None => continue,
+                Some(ref gradient) => gradient,
+            };
+            let kind = match gradient.kind {
+                GradientKind::Linear => 1.0,
+                GradientKind::Radial => 2.0,
+            };
+            let spread = match gradient.spread {
+                SpreadMethod::Pad => 0.0,
+                SpreadMethod::Reflect => 1.0,
+                SpreadMethod::Repeat => 2.0,
+            };
+            let transform = &gradient.transform;
+            let ramp_origin = gradient.ramp.ramp_origin();
+            let ramp_start = ramp_origin.x() as f32;
+            let ramp_end = ramp_start + (GRADIENT_RAMP_LENGTH - 1) as f32;
+            data[0..4].copy_from_slice(&[transform[0], transform[1], transform[2], kind]);
+            data[4..8].copy_from_slice(&[transform[3], transform[4], transform[5], spread]);
+            data[8..12].copy_from_slice(&[transform[6],
+                                          transform[7],
+                                          transform[8],
+                                          ramp_origin.y() as f32]);
+            data[12..16].copy_from_slice(&[gradient.focal.x(),
+                                           gradient.focal.y(),
+                                           ramp_start,
+                                           ramp_end]);
+        }
+        self.device.upload_to_texture(&self.paint_data_texture,
+                                      size,
+                                      TextureData::F32(&paint_data));
+    }
+
+    fn upload_images(&mut self, images: &[Image]) {
+        if self.image_atlas.contains_images(images) {
+            return;
+        }
+
+        let (image_atlas, texels) = ImageAtlas::build(images);
+        self.device.upload_to_texture(&self.image_atlas_texture,
+                                      image_atlas.size,
+                                      TextureData::U8(&texels));
+        self.image_atlas = image_atlas;
+    }
+
+    fn upload_gradient_ramps(&mut self, ramps: &[ColorU]) {
+        let size = gradient_ramp_texture_size(ramps.len() / GRADIENT_RAMP_LENGTH);
+        let mut texels = vec![0; size.x() as usize * size.y() as usize * 4];
+        for (ramp_index, ramp) in ramps.chunks(GRADIENT_RAMP_LENGTH).enumerate() {
+            let origin = GradientId(ramp_index as u16).ramp_origin();
+            let start = (origin.y() * size.x() + origin.x()) as usize;
+            for (color_index, color) in ramp.iter().enumerate() {
+                let texel_index = (start + color_index) * 4;
+                texels[texel_index..(texel_index + 4)]
+                    .copy_from_slice(&[color.r, color.g, color.b, color.a]);
+            }
+        }
+        self.device.upload_to_texture(&self.gradient_ramps_texture,
+                                      size,
+                                      TextureData::U8(&texels));
     }
 
     fn upload_solid_tiles(&mut self, solid_tiles: &[SolidTileBatchPrimitive]) {
@@ -304,6 +474,20 @@ impl<D> Renderer<D> where D: Device {
                                     BufferUploadMode::Dynamic);
     }
 
+    fn upload_clip_tiles(&mut self, clip_tiles: &[ClipTileBatchPrimitive]) {
+        self.device.allocate_buffer(&self.clip_tile_vertex_array.vertex_buffer,
+                                    BufferData::Memory(&clip_tiles),
+                                    BufferTarget::Vertex,
+                                    BufferUploadMode::Dynamic);
+    }
+
+    fn upload_layer_tiles(&mut self, layer_tiles: &[LayerTileBatchPrimitive]) {
+        self.device.allocate_buffer(&self.layer_tile_vertex_array.vertex_buffer,
+                                    BufferData::Memory(&layer_tiles),
+                                    BufferTarget::Vertex,
+                                    BufferUploadMode::Dynamic);
+    }
+
     fn upload_alpha_tiles(&mut self, alpha_tiles: &[AlphaTileBatchPrimitive]) {
         self.device.allocate_buffer(&self.alpha_tile_vertex_array().vertex_buffer,
                                     BufferData::Memory(&alpha_tiles),
@@ -380,6 +564,117 @@ impl<D> Renderer<D> where D: Device {
         self.buffered_fills.clear()
     }
 
+    fn init_clip_framebuffer(&mut self) {
+        if self.clip_framebuffer.is_none() {
+            let size = Point2DI32::new(MASK_FRAMEBUFFER_WIDTH, MASK_FRAMEBUFFER_HEIGHT);
+            let texture = self.device.create_texture(TextureFormat::R8, size);
+            self.clip_framebuffer = Some(self.device.create_framebuffer(texture));
+        }
+
+        self.device.bind_framebuffer(self.clip_framebuffer.as_ref().unwrap());
+        if !self.clip_framebuffer_cleared {
+            // Clip tiles start out fully covered and are multiplied down by each clip path.
+            self.device.clear(Some(F32x4::splat(1.0)), None, None);
+            self.clip_framebuffer_cleared = true;
+        }
+    }
+
+    fn draw_clip_tiles(&mut self, count: u32) {
+        self.init_clip_framebuffer();
+
+        self.device.bind_vertex_array(&self.clip_tile_vertex_array.vertex_array);
+        self.device.use_program(&self.clip_tile_program.program);
+        self.device.set_uniform(&self.clip_tile_program.framebuffer_size_uniform,
+                                UniformData::Vec2(I32x4::new(MASK_FRAMEBUFFER_WIDTH,
+                                                             MASK_FRAMEBUFFER_HEIGHT,
+                                                             0,
+                                                             0).to_f32x4()));
+        self.device.set_uniform(&self.clip_tile_program.tile_size_uniform,
+                                UniformData::Vec2(I32x4::new(TILE_WIDTH as i32,
+                                                             TILE_HEIGHT as i32,
+                                                             0,
+                                                             0).to_f32x4()));
+        self.device.bind_texture(self.device.framebuffer_texture(&self.mask_framebuffer), 0);
+        self.device.set_uniform(&self.clip_tile_program.src_texture_uniform,
+                                UniformData::TextureUnit(0));
+        self.device.set_uniform(&self.clip_tile_program.src_texture_size_uniform,
+                                UniformData::Vec2(I32x4::new(MASK_FRAMEBUFFER_WIDTH,
+                                                             MASK_FRAMEBUFFER_HEIGHT,
+                                                             0,
+                                                             0).to_f32x4()));
+        let render_state = RenderState {
+            blend: BlendState::RGBDstColorAlphaZero,
+            ..RenderState::default()
+        };
+        self.device.draw_arrays_instanced(Primitive::TriangleFan, 4, count, &render_state);
+    }
+
+    fn begin_layer(&mut self, target: LayerTarget) {
+        let layer_size = self.draw_viewport().size();
+        let framebuffer = match target {
+            LayerTarget::Layer => &mut self.layer_framebuffer,
+            LayerTarget::Mask => &mut self.layer_mask_framebuffer,
+        };
+        match *framebuffer {
+            Some(ref framebuffer) if
+                    self.device.texture_size(self.device.framebuffer_texture(framebuffer)) ==
+                    layer_size => {}
+            _ => {
+                let texture = self.device.create_texture(TextureFormat::RGBA8, layer_size);
+                *framebuffer = Some(self.device.create_framebuffer(texture))
+            }
+        }
+
+        // FIXME(pcwalton): Alpha accumulates additively in the layer, which overstates the
+        // opacity of overlapping translucent objects within it.
+        self.layer_target = Some(target);
+        self.bind_draw_framebuffer();
+        self.device.clear(Some(F32x4::default()), None, None);
+    }
+
+    fn composite_layer(&mut self, opacity: f32, mask: Option<MaskKind>, count: u32) {
+        self.layer_target = None;
+        self.bind_draw_framebuffer();
+
+        self.device.bind_vertex_array(&self.layer_tile_vertex_array.vertex_array);
+        self.device.use_program(&self.layer_tile_program.program);
+        self.device.set_uniform(&self.layer_tile_program.framebuffer_size_uniform,
+                                UniformData::Vec2(self.draw_viewport().size().to_f32().0));
+        self.device.set_uniform(&self.layer_tile_program.tile_size_uniform,
+                                UniformData::Vec2(I32x4::new(TILE_WIDTH as i32,
+                                                             TILE_HEIGHT as i32,
+                                                             0,
+                                                             0).to_f32x4()));
+        let layer_framebuffer = self.layer_framebuffer.as_ref().unwrap();
+        self.device.bind_texture(self.device.framebuffer_texture(layer_framebuffer), 0);
+        self.device.set_uniform(&self.layer_tile_program.layer_texture_uniform,
+                                UniformData::TextureUnit(0));
+        self.device.set_uniform(&self.layer_tile_program.opacity_uniform,
+                                UniformData::Float(opacity));
+
+        // The mask texture is left unbound, and unread, if the layer has no mask.
+        if mask.is_some() {
+            let layer_mask_framebuffer = self.layer_mask_framebuffer.as_ref().unwrap();
+            self.device.bind_texture(self.device.framebuffer_texture(layer_mask_framebuffer), 1);
+        }
+        self.device.set_uniform(&self.layer_tile_program.mask_texture_uniform,
+                                UniformData::TextureUnit(1));
+        self.device.set_uniform(&self.layer_tile_program.mask_kind_uniform,
+                                UniformData::Int(mask.map_or(0, |mask| mask as i32)));
+
+        // Layer tiles must land on the pixels that the layer's own tiles were drawn to, and those
+        // were drawn with a zero origin in `draw_alpha_tiles()` and `draw_solid_tiles()`. Tile
+        // coordinates are already in framebuffer space, so zero is also the right origin here.
+        self.device.set_uniform(&self.layer_tile_program.view_box_origin_uniform,
+                                UniformData::Vec2(F32x4::default()));
+        let render_state = RenderState {
+            blend: BlendState::RGBOneAlphaOneMinusSrcAlpha,
+            stencil: self.stencil_state(),
+            ..RenderState::default()
+        };
+        self.device.draw_arrays_instanced(Primitive::TriangleFan, 4, count, &render_state);
+    }
+
     fn draw_alpha_tiles(&mut self, count: u32) {
         self.bind_draw_framebuffer();
 
@@ -404,6 +699,12 @@ impl<D> Renderer<D> where D: Device {
                                                              0,
                                                              0).to_f32x4()));
 
+        // Unclipped scenes never sample the clip texture, but something must be bound.
+        let clip_framebuffer = self.clip_framebuffer.as_ref().unwrap_or(&self.mask_framebuffer);
+        self.device.bind_texture(self.device.framebuffer_texture(clip_framebuffer), 4);
+        self.device.set_uniform(&alpha_tile_program.clip_texture_uniform,
+                                UniformData::TextureUnit(4));
+
         match self.render_mode {
             RenderMode::Multicolor => {
                 self.device.bind_texture(&self.fill_colors_texture, 1);
@@ -416,6 +717,18 @@ impl<D> Renderer<D> where D: Device {
                                                                      FILL_COLORS_TEXTURE_HEIGHT,
                                                                      0,
                                                                      0).to_f32x4()));
+                self.device.bind_texture(&self.paint_data_texture, 2);
+                self.device.set_uniform(&self.alpha_multicolor_tile_program
+                                             .paint_data_texture_uniform,
+                                        UniformData::TextureUnit(2));
+                self.device.bind_texture(&self.gradient_ramps_texture, 3);
+                self.device.set_uniform(&self.alpha_multicolor_tile_program
+                                             .gradient_ramps_texture_uniform,
+                                        UniformData::TextureUnit(3));
+                self.device.bind_texture(&self.image_atlas_texture, 5);
+                self.device.set_uniform(&self.alpha_multicolor_tile_program
+                                             .image_atlas_texture_uniform,
+                                        UniformData::TextureUnit(5));
             }
             RenderMode::Monochrome { .. } if self.postprocessing_needed() => {
                 self.device.set_uniform(&self.alpha_monochrome_tile_program.fill_color_uniform,
@@ -466,6 +779,18 @@ impl<D> Renderer<D> where D: Device {
                                                                      FILL_COLORS_TEXTURE_HEIGHT,
                                                                      0,
                                                                      0).to_f32x4()));
+                self.device.bind_texture(&self.paint_data_texture, 1);
+                self.device.set_uniform(&self.solid_multicolor_tile_program
+                                             .paint_data_texture_uniform,
+                                        UniformData::TextureUnit(1));
+                self.device.bind_texture(&self.gradient_ramps_texture, 2);
+                self.device.set_uniform(&self.solid_multicolor_tile_program
+                                             .gradient_ramps_texture_uniform,
+                                        UniformData::TextureUnit(2));
+                self.device.bind_texture(&self.image_atlas_texture, 3);
+                self.device.set_uniform(&self.solid_multicolor_tile_program
+                                             .image_atlas_texture_uniform,
+                                        UniformData::TextureUnit(3));
             }
             RenderMode::Monochrome { .. } if self.postprocessing_needed() => {
                 self.device.set_uniform(&self.solid_monochrome_tile_program.fill_color_uniform,
@@ -597,7 +922,13 @@ impl<D> Renderer<D> where D: Device {
     }
 
     fn bind_draw_framebuffer(&self) {
-        if self.postprocessing_needed() {
+        if let Some(target) = self.layer_target {
+            let framebuffer = match target {
+                LayerTarget::Layer => &self.layer_framebuffer,
+                LayerTarget::Mask => &self.layer_mask_framebuffer,
+            };
+            self.device.bind_framebuffer(framebuffer.as_ref().unwrap());
+        } else if self.postprocessing_needed() {
             self.device.bind_framebuffer(self.postprocess_source_framebuffer.as_ref().unwrap());
         } else {
             self.bind_main_framebuffer();
@@ -766,6 +1097,9 @@ impl<D> AlphaTileVertexArray<D> where D: Device {
         let backdrop_attr = device.get_vertex_attr(&alpha_tile_program.program, "Backdrop");
         let object_attr = device.get_vertex_attr(&alpha_tile_program.program, "Object");
         let tile_index_attr = device.get_vertex_attr(&alpha_tile_program.program, "TileIndex");
+        let clip_tile_index_attr = device.get_vertex_attr(&alpha_tile_program.program,
+                                                          "ClipTileIndex");
+        let fill_rule_attr = device.get_vertex_attr(&alpha_tile_program.program, "FillRule");
 
         // NB: The object must be of type `I16`, not `U16`, to work around a macOS Radeon
         // driver bug.
@@ -804,11 +1138,123 @@ impl<D> AlphaTileVertexArray<D> where D: Device {
                                          MASK_TILE_INSTANCE_SIZE,
                                          6,
                                          1);
+        device.configure_int_vertex_attr(&clip_tile_index_attr,
+                                         1,
+                                         VertexAttrType::I16,
+                                         MASK_TILE_INSTANCE_SIZE,
+                                         8,
+                                         1);
+        device.configure_int_vertex_attr(&fill_rule_attr,
+                                         1,
+                                         VertexAttrType::U8,
+                                         MASK_TILE_INSTANCE_SIZE,
+                                         10,
+                                         1);
 
         AlphaTileVertexArray { vertex_array, vertex_buffer }
     }
 }
 
+struct ClipTileVertexArray<D> where D: Device {
+    vertex_array: D::VertexArray,
+    vertex_buffer: D::Buffer,
+}
+
+impl<D> ClipTileVertexArray<D> where D: Device {
+    fn new(device: &D,
+           clip_tile_program: &ClipTileProgram<D>,
+           quad_vertex_positions_buffer: &D::Buffer)
+           -> ClipTileVertexArray<D> {
+        let (vertex_array, vertex_buffer) = (device.create_vertex_array(), device.create_buffer());
+
+        let tess_coord_attr = device.get_vertex_attr(&clip_tile_program.program, "TessCoord");
+        let dest_tile_index_attr = device.get_vertex_attr(&clip_tile_program.program,
+                                                          "DestTileIndex");
+        let src_tile_index_attr = device.get_vertex_attr(&clip_tile_program.program,
+                                                         "SrcTileIndex");
+        let src_backdrop_attr = device.get_vertex_attr(&clip_tile_program.program,
+                                                       "SrcBackdrop");
+        let src_fill_rule_attr = device.get_vertex_attr(&clip_tile_program.program,
+                                                        "SrcFillRule");
+
+        device.bind_vertex_array(&vertex_array);
+        device.use_program(&clip_tile_program.program);
+        device.bind_buffer(quad_vertex_positions_buffer, BufferTarget::Vertex);
+        device.configure_float_vertex_attr(&tess_coord_attr,
+                                           2,
+                                           VertexAttrType::U8,
+                                           false,
+                                           0,
+                                           0,
+                                           0);
+        device.bind_buffer(&vertex_buffer, BufferTarget::Vertex);
+        device.configure_int_vertex_attr(&dest_tile_index_attr,
+                                         1,
+                                         VertexAttrType::I16,
+                                         CLIP_TILE_INSTANCE_SIZE,
+                                         0,
+                                         1);
+        device.configure_int_vertex_attr(&src_tile_index_attr,
+                                         1,
+                                         VertexAttrType::I16,
+                                         CLIP_TILE_INSTANCE_SIZE,
+                                         2,
+                                         1);
+        device.configure_int_vertex_attr(&src_backdrop_attr,
+                                         1,
+                                         VertexAttrType::I8,
+                                         CLIP_TILE_INSTANCE_SIZE,
+                                         4,
+                                         1);
+        device.configure_int_vertex_attr(&src_fill_rule_attr,
+                                         1,
+                                         VertexAttrType::U8,
+                                         CLIP_TILE_INSTANCE_SIZE,
+                                         5,
+                                         1);
+
+        ClipTileVertexArray { vertex_array, vertex_buffer }
+    }
+}
+
+struct LayerTileVertexArray<D> where D: Device {
+    vertex_array: D::VertexArray,
+    vertex_buffer: D::Buffer,
+}
+
+impl<D> LayerTileVertexArray<D> where D: Device {
+    fn new(device: &D,
+           layer_tile_program: &LayerTileProgram<D>,
+           quad_vertex_positions_buffer: &D::Buffer)
+           -> LayerTileVertexArray<D> {
+        let (vertex_array, vertex_buffer) = (device.create_vertex_array(), device.create_buffer());
+
+        let tess_coord_attr = device.get_vertex_attr(&layer_tile_program.program, "TessCoord");
+        let tile_origin_attr = device.get_vertex_attr(&layer_tile_program.program, "TileOrigin");
+
+        device.bind_vertex_array(&vertex_array);
+        device.use_program(&layer_tile_program.program);
+        device.bind_buffer(quad_vertex_positions_buffer, BufferTarget::Vertex);
+        device.configure_float_vertex_attr(&tess_coord_attr,
+                                           2,
+                                           VertexAttrType::U8,
+                                           false,
+                                           0,
+                                           0,
+                                           0);
+        device.bind_buffer(&vertex_buffer, BufferTarget::Vertex);
+        device.configure_float_vertex_attr(&tile_origin_attr,
+                                           2,
+                                           VertexAttrType::I16,
+                                           false,
+                                           LAYER_TILE_INSTANCE_SIZE,
+                                           0,
+                                           1);
+
+        LayerTileVertexArray { vertex_array, vertex_buffer }
+    }
+}
+
 struct SolidTileVertexArray<D> where D: Device {
     vertex_array: D::VertexArray,
     vertex_buffer: D::Buffer,
@@ -873,6 +1319,65 @@ impl<D> FillProgram<D> where D: Device {
     }
 }
 
+struct ClipTileProgram<D> where D: Device {
+    program: D::Program,
+    framebuffer_size_uniform: D::Uniform,
+    tile_size_uniform: D::Uniform,
+    src_texture_uniform: D::Uniform,
+    src_texture_size_uniform: D::Uniform,
+}
+
+impl<D> ClipTileProgram<D> where D: Device {
+    fn new(device: &D, resources: &dyn ResourceLoader) -> ClipTileProgram<D> {
+        let program = device.create_program(resources, "tile_clip");
+        let framebuffer_size_uniform = device.get_uniform(&program, "FramebufferSize");
+        let tile_size_uniform = device.get_uniform(&program, "TileSize");
+        let src_texture_uniform = device.get_uniform(&program, "SrcTexture");
+        let src_texture_size_uniform = device.get_uniform(&program, "SrcTextureSize");
+        ClipTileProgram {
+            program,
+            framebuffer_size_uniform,
+            tile_size_uniform,
+            src_texture_uniform,
+            src_texture_size_uniform,
+        }
+    }
+}
+
+struct LayerTileProgram<D> where D: Device {
+    program: D::Program,
+    framebuffer_size_uniform: D::Uniform,
+    tile_size_uniform: D::Uniform,
+    view_box_origin_uniform: D::Uniform,
+    layer_texture_uniform: D::Uniform,
+    opacity_uniform: D::Uniform,
+    mask_texture_uniform: D::Uniform,
+    mask_kind_uniform: D::Uniform,
+}
+
+impl<D> LayerTileProgram<D> where D: Device {
+    fn new(device: &D, resources: &dyn ResourceLoader) -> LayerTileProgram<D> {
+        let program = device.create_program(resources, "tile_layer");
+        let framebuffer_size_uniform = device.get_uniform(&program, "FramebufferSize");
+        let tile_size_uniform = device.get_uniform(&program, "TileSize");
+        let view_box_origin_uniform = device.get_uniform(&program, "ViewBoxOrigin");
+        let layer_texture_uniform = device.get_uniform(&program, "LayerTexture");
+        let opacity_uniform = device.get_uniform(&program, "Opacity");
+        let mask_texture_uniform = device.get_uniform(&program, "MaskTexture");
+        let mask_kind_uniform = device.get_uniform(&program, "MaskKind");
+        LayerTileProgram {
+            program,
+            framebuffer_size_uniform,
+            tile_size_uniform,
+            view_box_origin_uniform,
+            layer_texture_uniform,
+            opacity_uniform,
+            mask_texture_uniform,
+            mask_kind_uniform,
+        }
+    }
+}
+
 struct SolidTileProgram<D> where D: Device {
     program: D::Program,
     framebuffer_size_uniform: D::Uniform,
@@ -902,6 +1407,9 @@ struct SolidTileMulticolorProgram<D> where D: Device {
     solid_tile_program: SolidTileProgram<D>,
     fill_colors_texture_uniform: D::Uniform,
     fill_colors_texture_size_uniform: D::Uniform,
+    paint_data_texture_uniform: D::Uniform,
+    gradient_ramps_texture_uniform: D::Uniform,
+    image_atlas_texture_uniform: D::Uniform,
 }
 
 impl<D> SolidTileMulticolorProgram<D> where D: Device {
@@ -911,10 +1419,19 @@ impl<D> SolidTileMulticolorProgram<D> where D: Device {
                                                              "FillColorsTexture");
         let fill_colors_texture_size_uniform = device.get_uniform(&solid_tile_program.program,
                                                                   "FillColorsTextureSize");
+        let paint_data_texture_uniform = device.get_uniform(&solid_tile_program.program,
+                                                            "PaintDataTexture");
+        let gradient_ramps_texture_uniform = device.get_uniform(&solid_tile_program.program,
+                                                                "GradientRampsTexture");
+        let image_atlas_texture_uniform = device.get_uniform(&solid_tile_program.program,
+                                                             "ImageAtlasTexture");
         SolidTileMulticolorProgram {
             solid_tile_program,
             fill_colors_texture_uniform,
             fill_colors_texture_size_uniform,
+            paint_data_texture_uniform,
+            gradient_ramps_texture_uniform,
+            image_atlas_texture_uniform,
         }
     }
 }
@@ -938,6 +1455,7 @@ struct AlphaTileProgram<D> where D: Device {
     tile_size_uniform: D::Uniform,
     stencil_texture_uniform: D::Uniform,
     stencil_texture_size_uniform: D::Uniform,
+    clip_texture_uniform: D::Uniform,
     view_box_origin_uniform: D::Uniform,
 }
 
@@ -951,6 +1469,7 @@ impl<D> AlphaTileProgram<D> where D: Device {
         let tile_size_uniform = device.get_uniform(&program, "TileSize");
         let stencil_texture_uniform = device.get_uniform(&program, "StencilTexture");
         let stencil_texture_size_uniform = device.get_uniform(&program, "StencilTextureSize");
+        let clip_texture_uniform = device.get_uniform(&program, "ClipTexture");
         let view_box_origin_uniform = device.get_uniform(&program, "ViewBoxOrigin");
         AlphaTileProgram {
             program,
@@ -958,6 +1477,7 @@ impl<D> AlphaTileProgram<D> where D: Device {
             tile_size_uniform,
             stencil_texture_uniform,
             stencil_texture_size_uniform,
+            clip_texture_uniform,
             view_box_origin_uniform,
         }
     }
@@ -967,6 +1487,9 @@ struct AlphaTileMulticolorProgram<D> where D: Device {
     alpha_tile_program: AlphaTileProgram<D>,
     fill_colors_texture_uniform: D::Uniform,
     fill_colors_texture_size_uniform: D::Uniform,
+    paint_data_texture_uniform: D::Uniform,
+    gradient_ramps_texture_uniform: D::Uniform,
+    image_atlas_texture_uniform: D::Uniform,
 }
 
 impl<D> AlphaTileMulticolorProgram<D> where D: Device {
@@ -976,10 +1499,19 @@ impl<D> AlphaTileMulticolorProgram<D> where D: Device {
                                                              "FillColorsTexture");
         let fill_colors_texture_size_uniform = device.get_uniform(&alpha_tile_program.program,
                                                                   "FillColorsTextureSize");
+        let paint_data_texture_uniform = device.get_uniform(&alpha_tile_program.program,
+                                                            "PaintDataTexture");
+        let gradient_ramps_texture_uniform = device.get_uniform(&alpha_tile_program.program,
+                                                                "GradientRampsTexture");
+        let image_atlas_texture_uniform = device.get_uniform(&alpha_tile_program.program,
+                                                             "ImageAtlasTexture");
         AlphaTileMulticolorProgram {
             alpha_tile_program,
             fill_colors_texture_uniform,
             fill_colors_texture_size_uniform,
+            paint_data_texture_uniform,
+            gradient_ramps_texture_uniform,
+            image_atlas_texture_uniform,
         }
     }
 }
@@ -1134,6 +1666,14 @@ impl Default for RenderMode {
     }
 }
 
+// The offscreen framebuffer that tiles are drawn to between `BeginLayer` or `BeginMask` and
+// `CompositeLayer`.
+#[derive(Clone, Copy, Debug)]
+enum LayerTarget {
+    Layer,
+    Mask,
+}
+
 #[derive(Clone, Copy, Debug, Default)]
 pub struct RenderStats {
     pub object_count: usize,
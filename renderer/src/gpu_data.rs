@@ -11,6 +11,7 @@
 //! Packed data ready to be sent to the GPU.
 
 use crate::builder::SceneBuilder;
+use crate::scene::{FillRule, MaskKind};
 use crate::tile_map::DenseTileMap;
 use crate::tiles::{self, TILE_HEIGHT, TILE_WIDTH};
 use pathfinder_geometry::basic::line_segment::{LineSegmentF32, LineSegmentU4, LineSegmentU8};
@@ -32,6 +33,14 @@ pub(crate) struct BuiltObject {
 pub enum RenderCommand {
     AddFills(Vec<FillBatchPrimitive>),
     FlushFills,
+    ClipTiles(Vec<ClipTileBatchPrimitive>),
+    /// Redirects subsequent tiles to a cleared offscreen layer.
+    BeginLayer,
+    /// Redirects subsequent tiles to the cleared mask of the current layer.
+    BeginMask,
+    /// Composites the current layer onto the destination, multiplied by its mask if `mask` is
+    /// set, and ends it.
+    CompositeLayer { opacity: f32, mask: Option<MaskKind>, tiles: Vec<LayerTileBatchPrimitive> },
     AlphaTile(Vec<AlphaTileBatchPrimitive>),
     SolidTile(Vec<SolidTileBatchPrimitive>),
 }
@@ -78,6 +87,31 @@ pub struct AlphaTileBatchPrimitive {
     pub backdrop: i8,
     pub object_index: u16,
     pub tile_index: u16,
+    /// If `u16::MAX`, then this tile is not clipped. Otherwise, this is below 0x8000, because the
+    /// shader reads it as signed.
+    pub clip_tile_index: u16,
+    /// A `FillRule`.
+    pub fill_rule: u8,
+    pub pad: u8,
+}
+
+/// Multiplies a tile of the clip framebuffer by the coverage of a tile in the mask framebuffer.
+#[derive(Clone, Copy, Debug, Default)]
+#[repr(C)]
+pub struct ClipTileBatchPrimitive {
+    pub dest_tile_index: u16,
+    pub src_tile_index: u16,
+    pub src_backdrop: i8,
+    /// The `FillRule` of the source clip path.
+    pub src_fill_rule: u8,
+}
+
+/// A tile of an offscreen layer to be composited onto the destination.
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+pub struct LayerTileBatchPrimitive {
+    pub tile_x: i16,
+    pub tile_y: i16,
 }
 
 // Utilities for built objects
@@ -136,8 +170,10 @@ impl BuiltObject {
         self.fills.push(FillBatchPrimitive { px, subpx, alpha_tile_index });
     }
 
-    fn get_or_allocate_alpha_tile_index(&mut self, builder: &SceneBuilder, tile_coords: Point2DI32)
-                                        -> u16 {
+    pub(crate) fn get_or_allocate_alpha_tile_index(&mut self,
+                                                   builder: &SceneBuilder,
+                                                   tile_coords: Point2DI32)
+                                                   -> u16 {
         let local_tile_index = self.tiles.coords_to_index_unchecked(tile_coords);
         let alpha_tile_index = self.tiles.data[local_tile_index].alpha_tile_index;
         if alpha_tile_index != !0 {
@@ -256,7 +292,12 @@ impl TileObjectPrimitive {
 
 impl AlphaTileBatchPrimitive {
     #[inline]
-    pub fn new(tile_coords: Point2DI32, backdrop: i8, object_index: u16, tile_index: u16)
+    pub fn new(tile_coords: Point2DI32,
+               backdrop: i8,
+               object_index: u16,
+               tile_index: u16,
+               clip_tile_index: u16,
+               fill_rule: FillRule)
                -> AlphaTileBatchPrimitive {
         AlphaTileBatchPrimitive {
             tile_x_lo: (tile_coords.x() & 0xff) as u8,
@@ -265,6 +306,9 @@ impl AlphaTileBatchPrimitive {
             backdrop,
             object_index,
             tile_index,
+            clip_tile_index,
+            fill_rule: fill_rule as u8,
+            pad: 0,
         }
     }
 
@@ -280,6 +324,12 @@ impl Debug for RenderCommand {
         match *self {
             RenderCommand::AddFills(ref fills) => write!(formatter, "AddFills(x{})", fills.len()),
             RenderCommand::FlushFills => write!(formatter, "FlushFills"),
+            RenderCommand::ClipTiles(ref tiles) => write!(formatter, "ClipTiles(x{})", tiles.len()),
+            RenderCommand::BeginLayer => write!(formatter, "BeginLayer"),
+            RenderCommand::BeginMask => write!(formatter, "BeginMask"),
+            RenderCommand::CompositeLayer { opacity, mask, ref tiles } => {
+                write!(formatter, "CompositeLayer({}, {:?}, x{})", opacity, mask, tiles.len())
+            }
             RenderCommand::AlphaTile(ref tiles) => {
                 write!(formatter, "AlphaTile(x{})", tiles.len())
             }
@@ -0,0 +1,325 @@
+// pathfinder/renderer/src/gradient.rs
+//
+// Copyright © 2019 The Pathfinder Project Developers.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Linear and radial gradient paints.
+//!
+//! Each gradient is drawn from a color ramp, sampled on the CPU, plus a projective transform that
+//! maps device pixels in tile space to a normalized gradient space. In that space a linear
+//! gradient runs from `x = 0` to `x = 1`, and a radial gradient's end circle is the unit circle.
+
+use crate::builder::{PreparedRenderOptions, PreparedRenderTransform};
+use pathfinder_geometry::basic::point::{Point2DF32, Point2DI32};
+use pathfinder_geometry::basic::transform2d::Transform2DF32;
+use pathfinder_geometry::basic::transform3d::Perspective;
+use pathfinder_geometry::color::ColorU;
+use std::cmp;
+
+/// The number of texels in each gradient's color ramp.
+pub const GRADIENT_RAMP_LENGTH: usize = 256;
+
+// Ramps are packed side by side in rows of the ramp texture. With this many per row, the texture
+// stays within 4096 texels in each dimension for every possible `GradientId`.
+const GRADIENT_RAMPS_PER_ROW: usize = 16;
+
+// SVG places the focal point on the end circle if it lies outside it; we pull it very slightly
+// inside to keep the shader's quadratic well-conditioned.
+const MAX_FOCAL_DISTANCE: f32 = 0.999;
+
+#[derive(Clone, Debug)]
+pub struct Gradient {
+    pub geometry: GradientGeometry,
+    pub stops: Vec<GradientStop>,
+    pub spread: SpreadMethod,
+    /// Maps gradient space to scene space.
+    pub transform: Transform2DF32,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum GradientGeometry {
+    Linear { from: Point2DF32, to: Point2DF32 },
+    Radial { center: Point2DF32, focal: Point2DF32, radius: f32 },
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct GradientStop {
+    pub offset: f32,
+    pub color: ColorU,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SpreadMethod {
+    Pad,
+    Reflect,
+    Repeat,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct GradientId(pub u16);
+
+impl GradientId {
+    /// The texel of the ramp texture at which the color ramp of this gradient starts.
+    #[inline]
+    pub fn ramp_origin(self) -> Point2DI32 {
+        let index = self.0 as usize;
+        Point2DI32::new(((index % GRADIENT_RAMPS_PER_ROW) * GRADIENT_RAMP_LENGTH) as i32,
+                        (index / GRADIENT_RAMPS_PER_ROW) as i32)
+    }
+}
+
+/// The size of a ramp texture that holds `ramp_count` color ramps. It's never empty.
+pub fn gradient_ramp_texture_size(ramp_count: usize) -> Point2DI32 {
+    let ramp_count = cmp::max(ramp_count, 1);
+    let columns = cmp::min(ramp_count, GRADIENT_RAMPS_PER_ROW);
+    let rows = (ramp_count + GRADIENT_RAMPS_PER_ROW - 1) / GRADIENT_RAMPS_PER_ROW;
+    Point2DI32::new((columns * GRADIENT_RAMP_LENGTH) as i32, rows as i32)
+}
+
+/// The per-object data that the tile shaders need to evaluate a gradient.
+#[derive(Clone, Copy, Debug)]
+pub struct GradientShader {
+    pub kind: GradientKind,
+    pub spread: SpreadMethod,
+    /// Row-major projective transform from tile space to normalized gradient space.
+    pub transform: [f32; 9],
+    /// The focal point of a radial gradient, in normalized gradient space.
+    pub focal: Point2DF32,
+    pub ramp: GradientId,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GradientKind {
+    Linear,
+    Radial,
+}
+
+impl Gradient {
+    /// Samples the stops into `GRADIENT_RAMP_LENGTH` evenly spaced colors.
+    pub fn build_ramp(&self, ramp: &mut Vec<ColorU>) {
+        for index in 0..GRADIENT_RAMP_LENGTH {
+            let t = index as f32 / (GRADIENT_RAMP_LENGTH - 1) as f32;
+            ramp.push(self.sample(t));
+        }
+    }
+
+    fn sample(&self, t: f32) -> ColorU {
+        let (first, last) = match (self.stops.first(), self.stops.last()) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return ColorU::default(),
+        };
+        if t <= first.offset {
+            return first.color;
+        }
+        for window in self.stops.windows(2) {
+            let (prev, next) = (&window[0], &window[1]);
+            if t <= next.offset {
+                let span = next.offset - prev.offset;
+                let fraction = if span > 0.0 { (t - prev.offset) / span } else { 1.0 };
+                return lerp_color(prev.color, next.color, fraction);
+            }
+        }
+        last.color
+    }
+
+    pub(crate) fn build_shader(&self, ramp: GradientId, options: &PreparedRenderOptions)
+                               -> GradientShader {
+        // Map normalized gradient space to gradient space; the shader needs the inverse.
+        let (kind, normalize, focal) = match self.geometry {
+            GradientGeometry::Linear { from, to } => {
+                let vector = to - from;
+                (GradientKind::Linear,
+                 Matrix3x3::from_basis(from, vector, Point2DF32::new(-vector.y(), vector.x())),
+                 Point2DF32::default())
+            }
+            GradientGeometry::Radial { center, focal, radius } => {
+                let mut focal = (focal - center).scale(1.0 / radius);
+                let distance = focal.length();
+                if distance > MAX_FOCAL_DISTANCE {
+                    focal = focal.scale(MAX_FOCAL_DISTANCE / distance);
+                }
+                (GradientKind::Radial,
+                 Matrix3x3::from_basis(center,
+                                       Point2DF32::new(radius, 0.0),
+                                       Point2DF32::new(0.0, radius)),
+                 focal)
+            }
+        };
+
+        let forward = tile_transform(options).mul(&Matrix3x3::from_transform_2d(&self.transform))
+                                             .mul(&normalize);
+        let transform = match forward.inverse() {
+            Some(inverse) => inverse.0,
+            None => {
+                // Degenerate gradients (zero length or zero radius) are painted with the color of
+                // the last stop.
+                return GradientShader {
+                    kind: GradientKind::Linear,
+                    spread: SpreadMethod::Pad,
+                    transform: [0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0],
+                    focal: Point2DF32::default(),
+                    ramp,
+                };
+            }
+        };
+
+        GradientShader { kind, spread: self.spread, transform, focal, ramp }
+    }
+}
+
+/// The transform from scene space to tile space that `Scene::apply_render_options` applies to
+/// outlines, ignoring barrel distortion.
+pub(crate) fn tile_transform(options: &PreparedRenderOptions) -> Matrix3x3 {
+    match options.transform {
+        PreparedRenderTransform::Perspective { ref perspective, .. } => {
+            Matrix3x3::from_perspective(perspective)
+        }
+        _ => Matrix3x3::from_transform_2d(&options.tile_transform_2d()),
+    }
+}
+
+/// A row-major 3x3 matrix acting on homogeneous 2D points.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Matrix3x3(pub(crate) [f32; 9]);
+
+impl Matrix3x3 {
+    fn from_basis(origin: Point2DF32, x_axis: Point2DF32, y_axis: Point2DF32) -> Matrix3x3 {
+        Matrix3x3([
+            x_axis.x(), y_axis.x(), origin.x(),
+            x_axis.y(), y_axis.y(), origin.y(),
+            0.0,        0.0,        1.0,
+        ])
+    }
+
+    pub(crate) fn from_transform_2d(transform: &Transform2DF32) -> Matrix3x3 {
+        let origin = transform.transform_point(&Point2DF32::default());
+        let x_axis = transform.transform_point(&Point2DF32::new(1.0, 0.0)) - origin;
+        let y_axis = transform.transform_point(&Point2DF32::new(0.0, 1.0)) - origin;
+        Matrix3x3::from_basis(origin, x_axis, y_axis)
+    }
+
+    // Mirrors `Perspective::transform_point_2d` for points on the `z = 0` plane.
+    fn from_perspective(perspective: &Perspective) -> Matrix3x3 {
+        let m = &perspective.transform;
+        let clip = Matrix3x3([
+            m.c0[0], m.c1[0], m.c3[0],
+            m.c0[1], m.c1[1], m.c3[1],
+            m.c0[3], m.c1[3], m.c3[3],
+        ]);
+        let window_size = perspective.window_size.to_f32().scale(0.5);
+        let (half_width, half_height) = (window_size.x(), window_size.y());
+        let viewport = Matrix3x3([
+            half_width, 0.0,          half_width,
+            0.0,        -half_height, half_height,
+            0.0,        0.0,          1.0,
+        ]);
+        viewport.mul(&clip)
+    }
+
+    pub(crate) fn mul(&self, other: &Matrix3x3) -> Matrix3x3 {
+        let (a, b) = (&self.0, &other.0);
+        let mut result = [0.0; 9];
+        for row in 0..3 {
+            for column in 0..3 {
+                result[row * 3 + column] = a[row * 3] * b[column] +
+                                           a[row * 3 + 1] * b[3 + column] +
+                                           a[row * 3 + 2] * b[6 + column];
+            }
+        }
+        Matrix3x3(result)
+    }
+
+    pub(crate) fn inverse(&self) -> Option<Matrix3x3> {
+        let m = &self.0;
+        let cofactors = [
+            m[4] * m[8] - m[5] * m[7],
+            m[5] * m[6] - m[3] * m[8],
+            m[3] * m[7] - m[4] * m[6],
+        ];
+        let det = m[0] * cofactors[0] + m[1] * cofactors[1] + m[2] * cofactors[2];
+        if det.abs() < 1e-12 || !det.is_finite() {
+            return None;
+        }
+        let inv_det = 1.0 / det;
+        Some(Matrix3x3([
+            cofactors[0] * inv_det,
+            (m[2] * m[7] - m[1] * m[8]) * inv_det,
+            (m[1] * m[5] - m[2] * m[4]) * inv_det,
+            cofactors[1] * inv_det,
+            (m[0] * m[8] - m[2] * m[6]) * inv_det,
+            (m[2] * m[3] - m[0] * m[5]) * inv_det,
+            cofactors[2] * inv_det,
+            (m[1] * m[6] - m[0] * m[7]) * inv_det,
+            (m[0] * m[4] - m[1] * m[3]) * inv_det,
+        ]))
+    }
+}
+
+fn lerp_color(a: ColorU, b: ColorU, t: f32) -> ColorU {
+    let lerp = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * t).round() as u8;
+    ColorU { r: lerp(a.r, b.r), g: lerp(a.g, b.g), b: lerp(a.b, b.b), a: lerp(a.a, b.a) }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Gradient, GradientGeometry, GradientId, GradientStop, Matrix3x3, SpreadMethod};
+    use super::{gradient_ramp_texture_size, GRADIENT_RAMP_LENGTH};
+    use pathfinder_geometry::basic::point::{Point2DF32, Point2DI32};
+    use pathfinder_geometry::basic::transform2d::Transform2DF32;
+    use pathfinder_geometry::color::ColorU;
+
+    #[test]
+    fn test_ramp_endpoints() {
+        let red = ColorU { r: 255, g: 0, b: 0, a: 255 };
+        let blue = ColorU { r: 0, g: 0, b: 255, a: 255 };
+        let gradient = Gradient {
+            geometry: GradientGeometry::Linear {
+                from: Point2DF32::default(),
+                to: Point2DF32::new(1.0, 0.0),
+            },
+            stops: vec![
+                GradientStop { offset: 0.25, color: red },
+                GradientStop { offset: 0.75, color: blue },
+            ],
+            spread: SpreadMethod::Pad,
+            transform: Transform2DF32::default(),
+        };
+        let mut ramp = vec![];
+        gradient.build_ramp(&mut ramp);
+        assert_eq!(ramp.len(), GRADIENT_RAMP_LENGTH);
+        assert_eq!(ramp[0], red);
+        assert_eq!(ramp[ramp.len() - 1], blue);
+        assert_eq!(ramp[63], red);
+        assert_eq!(ramp[192], blue);
+    }
+
+    #[test]
+    fn test_ramp_texture_layout() {
+        let length = GRADIENT_RAMP_LENGTH as i32;
+        assert_eq!(gradient_ramp_texture_size(0), Point2DI32::new(length, 1));
+        assert_eq!(gradient_ramp_texture_size(3), Point2DI32::new(length * 3, 1));
+        assert_eq!(gradient_ramp_texture_size(17), Point2DI32::new(length * 16, 2));
+        assert_eq!(GradientId(17).ramp_origin(), Point2DI32::new(length, 1));
+
+        // Even the most gradients that a scene can have fit in a 4096x4096 texture.
+        let size = gradient_ramp_texture_size(u16::max_value() as usize + 1);
+        assert!(size.x() <= 4096 && size.y() <= 4096);
+        let last = GradientId(u16::max_value()).ramp_origin();
+        assert!(last.x() + length <= size.x() && last.y() < size.y());
+    }
+
+    #[test]
+    fn test_inverse() {
+        let matrix = Matrix3x3([2.0, 1.0, 3.0, 0.0, 4.0, -1.0, 0.5, 0.0, 1.0]);
+        let product = matrix.mul(&matrix.inverse().unwrap());
+        for (index, &value) in product.0.iter().enumerate() {
+            let expected = if index % 4 == 0 { 1.0 } else { 0.0 };
+            assert!((value - expected).abs() < 1e-5);
+        }
+    }
+}
@@ -0,0 +1,274 @@
+// pathfinder/renderer/src/image.rs
+//
+// Copyright © 2019 The Pathfinder Project Developers.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Raster image paints.
+//!
+//! The renderer packs every image in the scene into one atlas texture, scaling them down if they
+//! don't fit. As with gradients, each
+//! image carries a transform from image space, in pixels, to scene space, and the tile shaders
+//! map device pixels back through it to find the texel to sample.
+
+use crate::builder::PreparedRenderOptions;
+use crate::gradient::{self, Matrix3x3};
+use pathfinder_geometry::basic::point::{Point2DF32, Point2DI32};
+use pathfinder_geometry::basic::rect::RectI32;
+use pathfinder_geometry::basic::transform2d::Transform2DF32;
+use pathfinder_geometry::color::ColorU;
+use std::cmp;
+use std::sync::Arc;
+
+// The minimum width of the image atlas. It grows wider only to fit wider images.
+const MIN_ATLAS_WIDTH: i32 = 1024;
+// The maximum width and height of the image atlas, which every GPU we target supports.
+const MAX_ATLAS_SIZE: i32 = 4096;
+
+#[derive(Clone, Debug)]
+pub struct Image {
+    pub size: Point2DI32,
+    /// Non-premultiplied pixels, in rows from top to bottom.
+    pub pixels: Arc<Vec<ColorU>>,
+    /// Maps image space, where each pixel is one unit square, to scene space.
+    pub transform: Transform2DF32,
+    /// If true, the image tiles the plane, as SVG patterns do. Otherwise, its edge pixels extend
+    /// outward.
+    pub repeat: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ImageId(pub u16);
+
+/// The per-object data that the tile shaders need to sample an image.
+#[derive(Clone, Copy, Debug)]
+pub struct ImageShader {
+    pub image: ImageId,
+    /// Row-major projective transform from tile space to image space.
+    pub transform: [f32; 9],
+    pub repeat: bool,
+}
+
+impl Image {
+    #[inline]
+    pub fn is_opaque(&self) -> bool {
+        self.pixels.iter().all(|pixel| pixel.a == 255)
+    }
+
+    pub(crate) fn build_shader(&self, image: ImageId, options: &PreparedRenderOptions)
+                               -> ImageShader {
+        let forward = gradient::tile_transform(options)
+            .mul(&Matrix3x3::from_transform_2d(&self.transform));
+        // A degenerate transform flattens the image to nothing, so nothing samples it.
+        let transform = match forward.inverse() {
+            Some(inverse) => inverse.0,
+            None => [0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0],
+        };
+        ImageShader { image, transform, repeat: self.repeat }
+    }
+}
+
+/// The layout of the images of a scene in the renderer's atlas texture.
+///
+/// Images are sorted by height and packed left to right into shelves. Images larger than the
+/// maximum atlas size are scaled down to fit on their own, and if the shelves still don't fit,
+/// every image is scaled down by half until they do.
+pub(crate) struct ImageAtlas {
+    pub(crate) size: Point2DI32,
+    /// The location of each image in the atlas, indexed by `ImageId`.
+    pub(crate) rects: Vec<RectI32>,
+    /// The number of atlas texels per image pixel along each axis, indexed by `ImageId`. This is
+    /// less than one for images that were scaled down.
+    pub(crate) scales: Vec<Point2DF32>,
+    // The pixels the atlas was built from, so that unchanged scenes aren't uploaded again.
+    sources: Vec<Arc<Vec<ColorU>>>,
+}
+
+impl ImageAtlas {
+    #[inline]
+    pub(crate) fn new() -> ImageAtlas {
+        ImageAtlas { size: Point2DI32::new(1, 1), rects: vec![], scales: vec![], sources: vec![] }
+    }
+
+    /// Returns true if this atlas was built from exactly these images.
+    pub(crate) fn contains_images(&self, images: &[Image]) -> bool {
+        self.sources.len() == images.len() &&
+            self.sources.iter().zip(images.iter()).all(|(source, image)| {
+                Arc::ptr_eq(source, &image.pixels)
+            })
+    }
+
+    /// Packs the images, returning the atlas along with its texels in RGBA8 format.
+    pub(crate) fn build(images: &[Image]) -> (ImageAtlas, Vec<u8>) {
+        let mut global_scale = 1.0;
+        let (sizes, rects, size) = loop {
+            let sizes: Vec<_> = images.iter().map(|image| {
+                let longest = cmp::max(image.size.x(), image.size.y());
+                let scale = f32::min(1.0, MAX_ATLAS_SIZE as f32 / longest as f32) * global_scale;
+                Point2DI32::new(scaled_length(image.size.x(), scale),
+                                scaled_length(image.size.y(), scale))
+            }).collect();
+            let (rects, size) = pack_shelves(&sizes);
+            let smallest = sizes.iter().all(|size| size.x() <= 1 && size.y() <= 1);
+            if size.y() <= MAX_ATLAS_SIZE || smallest {
+                break (sizes, rects, size);
+            }
+            global_scale *= 0.5;
+        };
+
+        let mut texels = vec![0; size.x() as usize * size.y() as usize * 4];
+        for ((image, rect), &image_size) in images.iter().zip(rects.iter()).zip(sizes.iter()) {
+            let resampled;
+            let pixels = if image_size == image.size {
+                &image.pixels[..]
+            } else {
+                resampled = resample(image, image_size);
+                &resampled[..]
+            };
+            for y in 0..image_size.y() {
+                let src_start = (y * image_size.x()) as usize;
+                let dest_start = ((rect.min_y() + y) * size.x() + rect.min_x()) as usize * 4;
+                let row = &pixels[src_start..(src_start + image_size.x() as usize)];
+                for (x, pixel) in row.iter().enumerate() {
+                    let dest = dest_start + x * 4;
+                    texels[dest..(dest + 4)].copy_from_slice(&[pixel.r, pixel.g, pixel.b, pixel.a]);
+                }
+            }
+        }
+
+        let scales = images.iter().zip(sizes.iter()).map(|(image, size)| {
+            Point2DF32::new(size.x() as f32 / cmp::max(image.size.x(), 1) as f32,
+                            size.y() as f32 / cmp::max(image.size.y(), 1) as f32)
+        }).collect();
+        let sources = images.iter().map(|image| image.pixels.clone()).collect();
+        (ImageAtlas { size, rects, scales, sources }, texels)
+    }
+}
+
+// Returns the location of each image of the given size in the atlas, and the size of the atlas.
+fn pack_shelves(sizes: &[Point2DI32]) -> (Vec<RectI32>, Point2DI32) {
+    let width = sizes.iter().fold(MIN_ATLAS_WIDTH, |width, size| cmp::max(width, size.x()));
+
+    let mut indices: Vec<_> = (0..sizes.len()).collect();
+    indices.sort_by_key(|&index| cmp::Reverse(sizes[index].y()));
+
+    let mut rects = vec![RectI32::default(); sizes.len()];
+    let (mut shelf_origin, mut shelf_height) = (Point2DI32::default(), 0);
+    for index in indices {
+        let size = sizes[index];
+        if shelf_origin.x() + size.x() > width {
+            shelf_origin = Point2DI32::new(0, shelf_origin.y() + shelf_height);
+            shelf_height = 0;
+        }
+        rects[index] = RectI32::new(shelf_origin, size);
+        shelf_origin = shelf_origin + Point2DI32::new(size.x(), 0);
+        shelf_height = cmp::max(shelf_height, size.y());
+    }
+
+    (rects, Point2DI32::new(width, cmp::max(shelf_origin.y() + shelf_height, 1)))
+}
+
+#[inline]
+fn scaled_length(length: i32, scale: f32) -> i32 {
+    if length == 0 {
+        0
+    } else {
+        cmp::max((length as f32 * scale).ceil() as i32, 1)
+    }
+}
+
+// Scales the image down to `size` by averaging the pixels that each new pixel covers, weighted by
+// alpha.
+fn resample(image: &Image, size: Point2DI32) -> Vec<ColorU> {
+    let mut pixels = Vec::with_capacity(size.x() as usize * size.y() as usize);
+    for y in 0..size.y() {
+        let (src_min_y, src_max_y) = source_range(y, size.y(), image.size.y());
+        for x in 0..size.x() {
+            let (src_min_x, src_max_x) = source_range(x, size.x(), image.size.x());
+            let (mut sum, mut alpha_sum, mut count) = ([0u32; 3], 0u32, 0u32);
+            for src_y in src_min_y..src_max_y {
+                let row_start = (src_y * image.size.x()) as usize;
+                for src_x in src_min_x..src_max_x {
+                    let pixel = image.pixels[row_start + src_x as usize];
+                    let alpha = pixel.a as u32;
+                    sum[0] += pixel.r as u32 * alpha;
+                    sum[1] += pixel.g as u32 * alpha;
+                    sum[2] += pixel.b as u32 * alpha;
+                    alpha_sum += alpha;
+                    count += 1;
+                }
+            }
+            pixels.push(if alpha_sum == 0 {
+                ColorU { r: 0, g: 0, b: 0, a: 0 }
+            } else {
+                ColorU {
+                    r: (sum[0] / alpha_sum) as u8,
+                    g: (sum[1] / alpha_sum) as u8,
+                    b: (sum[2] / alpha_sum) as u8,
+                    a: (alpha_sum / count) as u8,
+                }
+            });
+        }
+    }
+    pixels
+}
+
+// The range of source pixels along one axis that the destination pixel `index` covers.
+#[inline]
+fn source_range(index: i32, dest_length: i32, src_length: i32) -> (i32, i32) {
+    let min = (index as i64 * src_length as i64 / dest_length as i64) as i32;
+    let max = ((index as i64 + 1) * src_length as i64 + dest_length as i64 - 1) /
+        dest_length as i64;
+    (min, cmp::max(max as i32, min + 1))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Image, ImageAtlas, MAX_ATLAS_SIZE};
+    use pathfinder_geometry::basic::point::Point2DI32;
+    use pathfinder_geometry::basic::rect::RectI32;
+    use pathfinder_geometry::basic::transform2d::Transform2DF32;
+    use pathfinder_geometry::color::ColorU;
+    use std::sync::Arc;
+
+    fn image(width: i32, height: i32) -> Image {
+        let pixels = vec![ColorU { r: 255, g: 0, b: 0, a: 255 }; (width * height) as usize];
+        Image {
+            size: Point2DI32::new(width, height),
+            pixels: Arc::new(pixels),
+            transform: Transform2DF32::default(),
+            repeat: false,
+        }
+    }
+
+    #[test]
+    fn test_shelf_packing() {
+        let (atlas, texels) = ImageAtlas::build(&[image(600, 10), image(600, 20), image(100, 30)]);
+        // The tallest image starts the first shelf, and the shortest doesn't fit beside the rest.
+        assert_eq!(atlas.rects[2], RectI32::new(Point2DI32::new(0, 0), Point2DI32::new(100, 30)));
+        assert_eq!(atlas.rects[1],
+                   RectI32::new(Point2DI32::new(100, 0), Point2DI32::new(600, 20)));
+        assert_eq!(atlas.rects[0], RectI32::new(Point2DI32::new(0, 30), Point2DI32::new(600, 10)));
+        assert_eq!(atlas.size, Point2DI32::new(1024, 40));
+        assert_eq!(texels.len(), 1024 * 40 * 4);
+        assert_eq!(&texels[0..4], &[255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn test_oversized_images_are_scaled_down() {
+        let (atlas, _) = ImageAtlas::build(&[image(MAX_ATLAS_SIZE * 2, 2)]);
+        assert_eq!(atlas.size, Point2DI32::new(MAX_ATLAS_SIZE, 1));
+        assert_eq!(atlas.rects[0].size(), Point2DI32::new(MAX_ATLAS_SIZE, 1));
+        assert_eq!(atlas.scales[0].x(), 0.5);
+
+        // Two images that would stack too high are halved to fit side by side.
+        let (atlas, _) = ImageAtlas::build(&[image(600, 2100), image(600, 2100)]);
+        assert!(atlas.size.y() <= MAX_ATLAS_SIZE);
+        assert_eq!(atlas.rects[1].size(), Point2DI32::new(300, 1050));
+        assert_eq!(atlas.scales[1].y(), 0.5);
+    }
+}
@@ -13,10 +13,13 @@
 pub mod builder;
 pub mod gpu;
 pub mod gpu_data;
+pub mod gradient;
+pub mod image;
 pub mod post;
 pub mod scene;
 pub mod tiles;
 
+mod clip;
 mod sorted_vector;
 mod tile_map;
 mod z_buffer;
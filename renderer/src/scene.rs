@@ -11,19 +11,27 @@
 //! A set of paths to be rendered.
 
 use crate::builder::{PreparedRenderOptions, PreparedRenderTransform};
+use crate::gradient::{Gradient, GradientId, GradientShader, GRADIENT_RAMP_LENGTH};
+use crate::image::{Image, ImageId, ImageShader};
 use hashbrown::HashMap;
 use pathfinder_geometry::basic::point::{Point2DF32, Point3DF32};
 use pathfinder_geometry::basic::rect::RectF32;
-use pathfinder_geometry::basic::transform2d::Transform2DF32;
 use pathfinder_geometry::color::ColorU;
 use pathfinder_geometry::outline::Outline;
 use std::fmt::{self, Debug, Formatter};
+use std::ops::Range;
+
+pub mod svg;
 
 #[derive(Clone)]
 pub struct Scene {
     pub objects: Vec<PathObject>,
     pub paints: Vec<Paint>,
     pub paint_cache: HashMap<Paint, PaintId>,
+    pub gradients: Vec<Gradient>,
+    pub images: Vec<Image>,
+    pub clip_paths: Vec<ClipPath>,
+    pub layers: Vec<Layer>,
     pub bounds: RectF32,
     pub view_box: RectF32,
 }
@@ -35,6 +43,10 @@ impl Scene {
             objects: vec![],
             paints: vec![],
             paint_cache: HashMap::new(),
+            gradients: vec![],
+            images: vec![],
+            clip_paths: vec![],
+            layers: vec![],
             bounds: RectF32::default(),
             view_box: RectF32::default(),
         }
@@ -52,21 +64,119 @@ impl Scene {
         paint_id
     }
 
+    pub fn push_gradient(&mut self, gradient: Gradient) -> GradientId {
+        assert!(self.gradients.len() <= u16::max_value() as usize,
+                "too many gradients in the scene");
+        let gradient_id = GradientId(self.gradients.len() as u16);
+        self.gradients.push(gradient);
+        gradient_id
+    }
+
+    pub fn push_image(&mut self, image: Image) -> ImageId {
+        assert!(self.images.len() <= u16::max_value() as usize, "too many images in the scene");
+        let image_id = ImageId(self.images.len() as u16);
+        self.images.push(image);
+        image_id
+    }
+
+    pub fn push_clip_path(&mut self, clip_path: ClipPath) -> ClipPathId {
+        assert!(self.clip_paths.len() <= u16::max_value() as usize,
+                "too many clip paths in the scene");
+        let clip_path_id = ClipPathId(self.clip_paths.len() as u16);
+        debug_assert!(clip_path.parent.map_or(true, |parent| parent.0 < clip_path_id.0));
+        self.clip_paths.push(clip_path);
+        clip_path_id
+    }
+
+    pub fn push_layer(&mut self, layer: Layer) -> LayerId {
+        assert!(self.layers.len() <= u16::max_value() as usize, "too many layers in the scene");
+        let layer_id = LayerId(self.layers.len() as u16);
+        debug_assert!(layer.objects.start <= layer.objects.end);
+        debug_assert!(layer.mask.as_ref().map_or(true, |mask| {
+            mask.objects.start == layer.objects.end && mask.objects.start <= mask.objects.end
+        }));
+        debug_assert!(self.layers.last().map_or(true, |last| {
+            last.all_objects().end <= layer.objects.start
+        }));
+        self.layers.push(layer);
+        layer_id
+    }
+
+    pub fn layer_of_object(&self, object_index: u32) -> Option<LayerId> {
+        self.layers.iter().position(|layer| {
+            object_index >= layer.objects.start && object_index < layer.objects.end
+        }).map(|index| LayerId(index as u16))
+    }
+
+    /// Returns the layer whose mask contains the given object, if any.
+    pub fn masked_layer_of_object(&self, object_index: u32) -> Option<LayerId> {
+        self.layers.iter().position(|layer| {
+            layer.mask.as_ref().map_or(false, |mask| {
+                object_index >= mask.objects.start && object_index < mask.objects.end
+            })
+        }).map(|index| LayerId(index as u16))
+    }
+
+    /// Returns true if everything painted with this paint is fully opaque, so that solid tiles
+    /// filled with it hide whatever is beneath them.
+    pub fn paint_is_opaque(&self, paint_id: PaintId) -> bool {
+        let paint = &self.paints[paint_id.0 as usize];
+        if paint.color.a != 255 {
+            return false;
+        }
+        if let Some(image_id) = paint.image {
+            return self.images[image_id.0 as usize].is_opaque();
+        }
+        match paint.gradient {
+            None => true,
+            Some(gradient_id) => {
+                let gradient = &self.gradients[gradient_id.0 as usize];
+                gradient.stops.iter().all(|stop| stop.color.a == 255)
+            }
+        }
+    }
+
     pub fn build_descriptor(&self, built_options: &PreparedRenderOptions) -> SceneDescriptor {
         SceneDescriptor {
-            shaders: self.build_shaders(),
+            shaders: self.build_shaders(built_options),
+            gradient_ramps: self.build_gradient_ramps(),
+            images: self.images.clone(),
             bounding_quad: built_options.bounding_quad(),
             object_count: self.objects.len(),
         }
     }
 
-    fn build_shaders(&self) -> Vec<ObjectShader> {
+    fn build_shaders(&self, built_options: &PreparedRenderOptions) -> Vec<ObjectShader> {
+        // Gradient transforms depend on the render transform, so build them once per paint.
+        let gradient_shaders: Vec<_> = self.paints.iter().map(|paint| {
+            paint.gradient.map(|gradient_id| {
+                self.gradients[gradient_id.0 as usize].build_shader(gradient_id, built_options)
+            })
+        }).collect();
+        let image_shaders: Vec<_> = self.paints.iter().map(|paint| {
+            paint.image.map(|image_id| {
+                self.images[image_id.0 as usize].build_shader(image_id, built_options)
+            })
+        }).collect();
+
         self.objects.iter().map(|object| {
             let paint = &self.paints[object.paint.0 as usize];
-            ObjectShader { fill_color: paint.color }
+            ObjectShader {
+                fill_color: paint.color,
+                gradient: gradient_shaders[object.paint.0 as usize],
+                image: image_shaders[object.paint.0 as usize],
+            }
         }).collect()
     }
 
+    fn build_gradient_ramps(&self) -> Vec<ColorU> {
+        let mut ramps = Vec::with_capacity(self.gradients.len() * GRADIENT_RAMP_LENGTH);
+        for gradient in &self.gradients {
+            gradient.build_ramp(&mut ramps);
+        }
+        ramps
+    }
+
     pub(crate) fn apply_render_options(&self,
                                        original_outline: &Outline,
                                        options: &PreparedRenderOptions)
@@ -95,16 +205,7 @@ impl Scene {
                 // TODO(pcwalton): Short circuit.
                 outline = (*original_outline).clone();
                 if options.transform.is_2d() || options.subpixel_aa_enabled {
-                    let mut transform = match options.transform {
-                        PreparedRenderTransform::Transform2D(transform) => transform,
-                        PreparedRenderTransform::None => Transform2DF32::default(),
-                        PreparedRenderTransform::Perspective { .. } => unreachable!(),
-                    };
-                    if options.subpixel_aa_enabled {
-                        transform = transform.post_mul(&Transform2DF32::from_scale(
-                            &Point2DF32::new(3.0, 1.0)))
-                    }
-                    outline.transform(&transform);
+                    outline.transform(&options.tile_transform_2d());
                 }
                 outline.clip_against_rect(effective_view_box);
             }
@@ -128,7 +229,11 @@ impl Scene {
         if self.objects.iter().skip(1).any(|object| object.paint != first_paint_id) {
             return None;
         }
-        Some(self.paints[first_paint_id.0 as usize].color)
+        let paint = &self.paints[first_paint_id.0 as usize];
+        if paint.gradient.is_some() || paint.image.is_some() {
+            return None;
+        }
+        Some(paint.color)
     }
 
     #[inline]
@@ -141,30 +246,22 @@ impl Scene {
     }
 }
 
+// Scenes print as SVG.
 impl Debug for Scene {
     fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
-        writeln!(formatter,
-                 "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"{} {} {} {}\">",
-                 self.view_box.origin().x(),
-                 self.view_box.origin().y(),
-                 self.view_box.size().x(),
-                 self.view_box.size().y())?;
-        for object in &self.objects {
-            let paint = &self.paints[object.paint.0 as usize];
-            write!(formatter, "    <path")?;
-            if !object.name.is_empty() {
-                write!(formatter, " id=\"{}\"", object.name)?;
-            }
-            writeln!(formatter, " fill=\"{:?}\" d=\"{:?}\" />", paint.color, object.outline)?;
-        }
-        writeln!(formatter, "</svg>")?;
-        Ok(())
+        let mut svg = vec![];
+        self::svg::write_svg(self, &mut svg).map_err(|_| fmt::Error)?;
+        formatter.write_str(&String::from_utf8_lossy(&svg))
     }
 }
 
 #[derive(Clone, Debug)]
 pub struct SceneDescriptor {
     pub shaders: Vec<ObjectShader>,
+    /// `GRADIENT_RAMP_LENGTH` colors per gradient, indexed by `GradientId`.
+    pub gradient_ramps: Vec<ColorU>,
+    /// Indexed by `ImageId`.
+    pub images: Vec<Image>,
     pub bounding_quad: [Point3DF32; 4],
     pub object_count: usize,
 }
@@ -175,6 +272,8 @@ pub struct PathObject {
     paint: PaintId,
     name: String,
     kind: PathObjectKind,
+    fill_rule: FillRule,
+    clip_path: Option<ClipPathId>,
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -185,20 +284,142 @@ pub enum PathObjectKind {
 
 impl PathObject {
     #[inline]
-    pub fn new(outline: Outline, paint: PaintId, name: String, kind: PathObjectKind)
+    pub fn new(outline: Outline,
+               paint: PaintId,
+               name: String,
+               kind: PathObjectKind,
+               fill_rule: FillRule,
+               clip_path: Option<ClipPathId>)
                -> PathObject {
-        PathObject { outline, paint, name, kind }
+        PathObject { outline, paint, name, kind, fill_rule, clip_path }
     }
 
     #[inline]
     pub fn outline(&self) -> &Outline {
         &self.outline
     }
+
+    #[inline]
+    pub fn outline_mut(&mut self) -> &mut Outline {
+        &mut self.outline
+    }
+
+    #[inline]
+    pub fn paint(&self) -> PaintId {
+        self.paint
+    }
+
+    #[inline]
+    pub fn set_paint(&mut self, paint: PaintId) {
+        self.paint = paint;
+    }
+
+    #[inline]
+    pub fn fill_rule(&self) -> FillRule {
+        self.fill_rule
+    }
+
+    #[inline]
+    pub fn clip_path(&self) -> Option<ClipPathId> {
+        self.clip_path
+    }
+}
+
+/// How the winding number of a point determines whether the point is inside a path.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FillRule {
+    /// Points with a nonzero winding number are inside.
+    Winding = 0,
+    /// Points with an odd winding number are inside.
+    EvenOdd = 1,
+}
+
+impl Default for FillRule {
+    #[inline]
+    fn default() -> FillRule {
+        FillRule::Winding
+    }
+}
+
+impl FillRule {
+    /// Whether the area of a tile that no edge passes through is inside the path, given the
+    /// winding number of the tile.
+    #[inline]
+    pub fn backdrop_is_inside(self, backdrop: i8) -> bool {
+        match self {
+            FillRule::Winding => backdrop != 0,
+            FillRule::EvenOdd => backdrop % 2 != 0,
+        }
+    }
+}
+
+/// A region that objects are clipped to. The effective clip is the intersection of this outline
+/// with the effective clip of `parent`, if any, so a chain of parents forms a clip stack.
+#[derive(Clone, Debug)]
+pub struct ClipPath {
+    pub outline: Outline,
+    pub fill_rule: FillRule,
+    pub parent: Option<ClipPathId>,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct ClipPathId(pub u16);
+
+/// A contiguous run of objects that are rendered offscreen and then composited together with the
+/// given opacity, as for an SVG group with `opacity` or `mask`. Layers don't nest.
+#[derive(Clone, Debug)]
+pub struct Layer {
+    pub objects: Range<u32>,
+    pub opacity: f32,
+    /// If present, the layer is multiplied by this mask as it's composited.
+    pub mask: Option<Mask>,
 }
 
+impl Layer {
+    /// The objects of the layer followed by those of its mask, if any.
+    #[inline]
+    pub fn all_objects(&self) -> Range<u32> {
+        match self.mask {
+            None => self.objects.clone(),
+            Some(ref mask) => self.objects.start..mask.objects.end,
+        }
+    }
+}
+
+/// The objects that make up the mask of a layer. They're rendered into a separate offscreen
+/// buffer instead of the destination, and must immediately follow the objects of the layer.
+#[derive(Clone, Debug)]
+pub struct Mask {
+    pub objects: Range<u32>,
+    pub kind: MaskKind,
+}
+
+/// How the color of a mask is converted into coverage.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MaskKind {
+    /// The luminance of the mask, multiplied by its alpha, as for SVG `mask-type="luminance"`.
+    Luminance = 1,
+    /// The alpha of the mask alone.
+    Alpha = 2,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct LayerId(pub u16);
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub struct Paint {
+    /// The color of the paint, or the color to multiply the gradient or image by.
     pub color: ColorU,
+    pub gradient: Option<GradientId>,
+    /// If set, takes precedence over `gradient`.
+    pub image: Option<ImageId>,
+}
+
+impl Paint {
+    #[inline]
+    pub fn from_color(color: ColorU) -> Paint {
+        Paint { color, gradient: None, image: None }
+    }
 }
 
 #[derive(Clone, Copy, PartialEq, Debug)]
@@ -207,4 +428,26 @@ pub struct PaintId(pub u16);
 #[derive(Clone, Copy, Debug, Default)]
 pub struct ObjectShader {
     pub fill_color: ColorU,
+    pub gradient: Option<GradientShader>,
+    pub image: Option<ImageShader>,
+}
+
+#[cfg(test)]
+mod test {
+    use super::FillRule;
+
+    #[test]
+    fn test_backdrop_is_inside() {
+        assert!(!FillRule::Winding.backdrop_is_inside(0));
+        assert!(FillRule::Winding.backdrop_is_inside(1));
+        assert!(FillRule::Winding.backdrop_is_inside(-1));
+        assert!(FillRule::Winding.backdrop_is_inside(2));
+
+        assert!(!FillRule::EvenOdd.backdrop_is_inside(0));
+        assert!(FillRule::EvenOdd.backdrop_is_inside(1));
+        assert!(FillRule::EvenOdd.backdrop_is_inside(-1));
+        assert!(!FillRule::EvenOdd.backdrop_is_inside(2));
+        assert!(!FillRule::EvenOdd.backdrop_is_inside(-2));
+        assert!(FillRule::EvenOdd.backdrop_is_inside(-3));
+    }
 }
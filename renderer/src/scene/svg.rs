@@ -0,0 +1,461 @@
+// pathfinder/renderer/src/scene/svg.rs
+//
+// Copyright © 2019 The Pathfinder Project Developers.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Writes scenes out as SVG, for inspection in other tools.
+//!
+//! Every object becomes a filled `<path>` in scene space. Strokes have already been converted to
+//! fills, so they're written as fills too, marked with the `pathfinder-stroke` class. Paints,
+//! clip paths, layers, and masks map onto their SVG equivalents.
+
+use crate::gradient::{Gradient, GradientGeometry, SpreadMethod};
+use crate::image::Image;
+use crate::scene::{FillRule, MaskKind, Paint, PathObjectKind, Scene};
+use pathfinder_geometry::basic::rect::RectF32;
+use pathfinder_geometry::basic::transform2d::Transform2DF32;
+use pathfinder_geometry::color::ColorU;
+use pathfinder_geometry::outline::Outline;
+use std::collections::HashSet;
+use std::io::{self, Write};
+
+/// Writes `scene` as a standalone SVG document.
+pub fn write_svg<W>(scene: &Scene, writer: &mut W) -> io::Result<()> where W: Write {
+    let view_box = scene.view_box;
+    writeln!(writer,
+             "<svg xmlns=\"http://www.w3.org/2000/svg\" \
+              xmlns:xlink=\"http://www.w3.org/1999/xlink\" \
+              width=\"{}\" height=\"{}\" viewBox=\"{} {} {} {}\">",
+             view_box.size().x(),
+             view_box.size().y(),
+             view_box.origin().x(),
+             view_box.origin().y(),
+             view_box.size().x(),
+             view_box.size().y())?;
+
+    // Generated IDs are reserved first, so that object names that collide with them get
+    // renamed instead.
+    let mut ids = HashSet::new();
+    ids.extend((0..scene.clip_paths.len()).map(|index| format!("clip{}", index)));
+    ids.extend((0..scene.gradients.len()).map(|index| format!("gradient{}", index)));
+    ids.extend((0..scene.images.len()).map(|index| format!("image{}", index)));
+    ids.extend((0..scene.layers.len()).map(|index| format!("mask{}", index)));
+
+    writeln!(writer, "    <defs>")?;
+    for (clip_path_index, clip_path) in scene.clip_paths.iter().enumerate() {
+        write!(writer, "        <clipPath id=\"clip{}\" clipPathUnits=\"userSpaceOnUse\"",
+               clip_path_index)?;
+        if let Some(parent) = clip_path.parent {
+            write!(writer, " clip-path=\"url(#clip{})\"", parent.0)?;
+        }
+        write!(writer, "><path")?;
+        if clip_path.fill_rule == FillRule::EvenOdd {
+            write!(writer, " clip-rule=\"evenodd\"")?;
+        }
+        writeln!(writer, " d=\"{}\"/></clipPath>", path_data(&clip_path.outline))?;
+    }
+    for (gradient_index, gradient) in scene.gradients.iter().enumerate() {
+        write_gradient(writer, gradient_index, gradient)?;
+    }
+    for (image_index, image) in scene.images.iter().enumerate() {
+        write_image(writer, image_index, image)?;
+    }
+    writeln!(writer, "    </defs>")?;
+
+    // Masks show only what's inside them, so give them a region that covers everything.
+    let mask_region = scene.bounds.union_rect(view_box);
+
+    for (object_index, object) in scene.objects.iter().enumerate() {
+        let object_index = object_index as u32;
+        for (layer_index, layer) in scene.layers.iter().enumerate() {
+            if layer.objects.start == object_index && layer.objects.end > object_index {
+                write!(writer, "    <g opacity=\"{}\"", layer.opacity)?;
+                if layer.mask.is_some() {
+                    write!(writer, " mask=\"url(#mask{})\"", layer_index)?;
+                }
+                writeln!(writer, ">")?;
+            }
+            if let Some(ref mask) = layer.mask {
+                if mask.objects.start == object_index && mask.objects.end > object_index {
+                    write_mask_start(writer, layer_index, mask.kind, mask_region)?;
+                }
+            }
+        }
+
+        write!(writer, "        <path")?;
+        if !object.name.is_empty() {
+            // The renamed ID may itself be taken, by a generated ID or another object's name.
+            let mut id = object.name.clone();
+            let mut attempt = 0;
+            while ids.contains(&id) {
+                id = match attempt {
+                    0 => format!("{}-{}", object.name, object_index),
+                    _ => format!("{}-{}-{}", object.name, object_index, attempt),
+                };
+                attempt += 1;
+            }
+            write!(writer, " id=\"{}\"", escape(&id))?;
+            ids.insert(id);
+        }
+        if let PathObjectKind::Stroke = object.kind {
+            write!(writer, " class=\"pathfinder-stroke\"")?;
+        }
+        if let Some(clip_path_id) = object.clip_path {
+            write!(writer, " clip-path=\"url(#clip{})\"", clip_path_id.0)?;
+        }
+        write_fill(writer, &scene.paints[object.paint.0 as usize])?;
+        if object.fill_rule == FillRule::EvenOdd {
+            write!(writer, " fill-rule=\"evenodd\"")?;
+        }
+        writeln!(writer, " d=\"{}\"/>", path_data(&object.outline))?;
+
+        for layer in &scene.layers {
+            if layer.objects.end == object_index + 1 {
+                writeln!(writer, "    </g>")?;
+            }
+            if layer.mask.as_ref().map_or(false, |mask| mask.objects.end == object_index + 1) {
+                writeln!(writer, "    </mask>")?;
+            }
+        }
+    }
+
+    writeln!(writer, "</svg>")
+}
+
+fn write_fill<W>(writer: &mut W, paint: &Paint) -> io::Result<()> where W: Write {
+    match (paint.gradient, paint.image) {
+        (_, Some(image_id)) => write!(writer, " fill=\"url(#image{})\"", image_id.0)?,
+        (Some(gradient_id), None) => write!(writer, " fill=\"url(#gradient{})\"", gradient_id.0)?,
+        (None, None) => write!(writer, " fill=\"{}\"", color_rgb(paint.color))?,
+    }
+    // FIXME(pcwalton): The color of gradient and image paints is assumed to be white, so only
+    // its alpha is written.
+    if paint.color.a < 255 {
+        write!(writer, " fill-opacity=\"{}\"", alpha(paint.color))?;
+    }
+    Ok(())
+}
+
+fn write_gradient<W>(writer: &mut W, gradient_index: usize, gradient: &Gradient)
+                     -> io::Result<()> where W: Write {
+    let element = match gradient.geometry {
+        GradientGeometry::Linear { from, to } => {
+            write!(writer,
+                   "        <linearGradient id=\"gradient{}\" x1=\"{}\" y1=\"{}\" x2=\"{}\" \
+                    y2=\"{}\"",
+                   gradient_index,
+                   from.x(),
+                   from.y(),
+                   to.x(),
+                   to.y())?;
+            "linearGradient"
+        }
+        GradientGeometry::Radial { center, focal, radius } => {
+            write!(writer,
+                   "        <radialGradient id=\"gradient{}\" cx=\"{}\" cy=\"{}\" r=\"{}\" \
+                    fx=\"{}\" fy=\"{}\"",
+                   gradient_index,
+                   center.x(),
+                   center.y(),
+                   radius,
+                   focal.x(),
+                   focal.y())?;
+            "radialGradient"
+        }
+    };
+    let spread = match gradient.spread {
+        SpreadMethod::Pad => "pad",
+        SpreadMethod::Reflect => "reflect",
+        SpreadMethod::Repeat => "repeat",
+    };
+    writeln!(writer,
+             " gradientUnits=\"userSpaceOnUse\" gradientTransform=\"{}\" spreadMethod=\"{}\">",
+             matrix(&gradient.transform),
+             spread)?;
+    for stop in &gradient.stops {
+        write!(writer,
+               "            <stop offset=\"{}\" stop-color=\"{}\"",
+               stop.offset,
+               color_rgb(stop.color))?;
+        if stop.color.a < 255 {
+            write!(writer, " stop-opacity=\"{}\"", alpha(stop.color))?;
+        }
+        writeln!(writer, "/>")?;
+    }
+    writeln!(writer, "        </{}>", element)
+}
+
+// Images are written as patterns holding a PNG.
+//
+// FIXME(pcwalton): Images that don't repeat extend their edge pixels outward in Pathfinder, but
+// tile here. They're normally clipped to their own bounds, so this rarely shows.
+fn write_image<W>(writer: &mut W, image_index: usize, image: &Image) -> io::Result<()>
+                  where W: Write {
+    let (width, height) = (image.size.x(), image.size.y());
+    writeln!(writer,
+             "        <pattern id=\"image{}\" patternUnits=\"userSpaceOnUse\" width=\"{}\" \
+              height=\"{}\" patternTransform=\"{}\">",
+             image_index,
+             width,
+             height,
+             matrix(&image.transform))?;
+    writeln!(writer,
+             "            <image width=\"{}\" height=\"{}\" preserveAspectRatio=\"none\" \
+              xlink:href=\"data:image/png;base64,{}\"/>",
+             width,
+             height,
+             base64(&encode_png(image)))?;
+    writeln!(writer, "        </pattern>")
+}
+
+fn write_mask_start<W>(writer: &mut W, layer_index: usize, kind: MaskKind, region: RectF32)
+                       -> io::Result<()> where W: Write {
+    write!(writer,
+           "    <mask id=\"mask{}\" maskUnits=\"userSpaceOnUse\" x=\"{}\" y=\"{}\" width=\"{}\" \
+            height=\"{}\"",
+           layer_index,
+           region.origin().x(),
+           region.origin().y(),
+           region.size().x(),
+           region.size().y())?;
+    if kind == MaskKind::Alpha {
+        write!(writer, " mask-type=\"alpha\"")?;
+    }
+    writeln!(writer, ">")
+}
+
+fn path_data(outline: &Outline) -> String {
+    let mut data = String::new();
+    for contour in &outline.contours {
+        if contour.is_empty() {
+            continue;
+        }
+        if !data.is_empty() {
+            data.push(' ');
+        }
+
+        let first = contour.position_of(0);
+        data.push_str(&format!("M {} {}", first.x(), first.y()));
+
+        // The last segment of a closed contour is the closing line, which `Z` draws.
+        let mut segment_count = contour.iter().count();
+        if contour.is_closed() {
+            segment_count -= 1;
+        }
+        for segment in contour.iter().take(segment_count) {
+            let to = segment.baseline.to();
+            if segment.is_line() {
+                data.push_str(&format!(" L {} {}", to.x(), to.y()));
+            } else if segment.is_quadratic() {
+                let ctrl = segment.ctrl.from();
+                data.push_str(&format!(" Q {} {} {} {}", ctrl.x(), ctrl.y(), to.x(), to.y()));
+            } else {
+                let (ctrl0, ctrl1) = (segment.ctrl.from(), segment.ctrl.to());
+                data.push_str(&format!(" C {} {} {} {} {} {}",
+                                       ctrl0.x(),
+                                       ctrl0.y(),
+                                       ctrl1.x(),
+                                       ctrl1.y(),
+                                       to.x(),
+                                       to.y()));
+            }
+        }
+        if contour.is_closed() {
+            data.push_str(" Z");
+        }
+    }
+    data
+}
+
+fn matrix(transform: &Transform2DF32) -> String {
+    let translation = transform.translation();
+    format!("matrix({} {} {} {} {} {})",
+            transform.m11(),
+            transform.m21(),
+            transform.m12(),
+            transform.m22(),
+            translation.x(),
+            translation.y())
+}
+
+fn color_rgb(color: ColorU) -> String {
+    format!("#{:02x}{:02x}{:02x}", color.r, color.g, color.b)
+}
+
+fn alpha(color: ColorU) -> f32 {
+    color.a as f32 / 255.0
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// Encodes an RGBA PNG without compressing it, which keeps this free of dependencies.
+fn encode_png(image: &Image) -> Vec<u8> {
+    let (width, height) = (image.size.x() as u32, image.size.y() as u32);
+
+    // Each row starts with a filter type of zero, for none.
+    let mut raw = Vec::with_capacity((width as usize * 4 + 1) * height as usize);
+    for row in image.pixels.chunks(width as usize) {
+        raw.push(0);
+        for pixel in row {
+            raw.extend_from_slice(&[pixel.r, pixel.g, pixel.b, pixel.a]);
+        }
+    }
+
+    // A zlib stream made of stored deflate blocks.
+    let mut zlib = vec![0x78, 0x01];
+    let mut blocks = raw.chunks(0xffff).peekable();
+    if blocks.peek().is_none() {
+        zlib.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next() {
+        let length = block.len() as u16;
+        zlib.push(if blocks.peek().is_none() { 1 } else { 0 });
+        zlib.extend_from_slice(&length.to_le_bytes());
+        zlib.extend_from_slice(&(!length).to_le_bytes());
+        zlib.extend_from_slice(block);
+    }
+    zlib.extend_from_slice(&adler32(&raw).to_be_bytes());
+
+    let mut header = vec![];
+    header.extend_from_slice(&width.to_be_bytes());
+    header.extend_from_slice(&height.to_be_bytes());
+    // 8 bits per channel, RGBA, and the default compression, filter, and interlace methods.
+    header.extend_from_slice(&[8, 6, 0, 0, 0]);
+
+    let mut png = vec![0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a];
+    push_png_chunk(&mut png, b"IHDR", &header);
+    push_png_chunk(&mut png, b"IDAT", &zlib);
+    push_png_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+fn push_png_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let crc_start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[crc_start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { 0xedb8_8320 ^ (crc >> 1) } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] =
+        b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity((data.len() + 2) / 3 * 4);
+    for chunk in data.chunks(3) {
+        let bytes = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let bits = (bytes[0] as u32) << 16 | (bytes[1] as u32) << 8 | bytes[2] as u32;
+        for index in 0..4 {
+            if index <= chunk.len() {
+                encoded.push(ALPHABET[(bits >> (18 - index * 6)) as usize & 0x3f] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod test {
+    use super::{adler32, base64, crc32, write_svg};
+    use crate::gradient::{Gradient, GradientGeometry, GradientStop, SpreadMethod};
+    use crate::scene::{FillRule, Paint, PathObject, PathObjectKind, Scene};
+    use pathfinder_geometry::basic::point::Point2DF32;
+    use pathfinder_geometry::basic::transform2d::Transform2DF32;
+    use pathfinder_geometry::color::ColorU;
+    use pathfinder_geometry::outline::Outline;
+
+    fn push_object(scene: &mut Scene, name: &str, paint: &Paint, fill_rule: FillRule) {
+        let paint = scene.push_paint(paint);
+        scene.objects.push(PathObject::new(Outline::new(),
+                                           paint,
+                                           name.to_string(),
+                                           PathObjectKind::Fill,
+                                           fill_rule,
+                                           None));
+    }
+
+    #[test]
+    fn test_write_svg_paints_and_ids() {
+        let mut scene = Scene::new();
+        let white = ColorU { r: 255, g: 255, b: 255, a: 255 };
+        let gradient = scene.push_gradient(Gradient {
+            geometry: GradientGeometry::Linear {
+                from: Point2DF32::new(0.0, 0.0),
+                to: Point2DF32::new(1.0, 0.0),
+            },
+            stops: vec![GradientStop { offset: 0.0, color: white },
+                        GradientStop { offset: 1.0, color: white }],
+            spread: SpreadMethod::Pad,
+            transform: Transform2DF32::default(),
+        });
+
+        let red = Paint::from_color(ColorU { r: 255, g: 0, b: 0, a: 51 });
+        push_object(&mut scene, "gradient0-1", &red, FillRule::EvenOdd);
+        let gradient_paint = Paint { color: white, gradient: Some(gradient), image: None };
+        push_object(&mut scene, "gradient0", &gradient_paint, FillRule::Winding);
+
+        let mut output = vec![];
+        write_svg(&scene, &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        assert!(output.contains("<linearGradient id=\"gradient0\""));
+        assert!(output.contains("<path id=\"gradient0-1\" fill=\"#ff0000\" \
+                                 fill-opacity=\"0.2\" fill-rule=\"evenodd\" d=\"\"/>"));
+        // Both `gradient0` and `gradient0-1` are taken, so the second object gets a further
+        // suffix.
+        assert!(output.contains("<path id=\"gradient0-1-1\" fill=\"url(#gradient0)\" \
+                                 d=\"\"/>"));
+    }
+
+    #[test]
+    fn test_png_checksums() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+    }
+
+    #[test]
+    fn test_base64_padding() {
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64(b"foob"), "Zm9vYg==");
+        assert_eq!(base64(b"fooba"), "Zm9vYmE=");
+    }
+}
@@ -9,7 +9,9 @@
 // except according to those terms.
 
 use crate::builder::SceneBuilder;
+use crate::clip::{ClipTile, ClipTileMap};
 use crate::gpu_data::{AlphaTileBatchPrimitive, BuiltObject};
+use crate::scene::FillRule;
 use crate::sorted_vector::SortedVector;
 use pathfinder_geometry::basic::line_segment::LineSegmentF32;
 use pathfinder_geometry::basic::point::{Point2DF32, Point2DI32};
@@ -28,8 +30,10 @@ pub const TILE_HEIGHT: u32 = 16;
 pub(crate) struct Tiler<'a> {
     builder: &'a SceneBuilder<'a>,
     outline: &'a Outline,
+    fill_rule: FillRule,
     pub built_object: BuiltObject,
     object_index: u16,
+    clip: Option<&'a ClipTileMap>,
 
     point_queue: SortedVector<QueuedEndpoint>,
     active_edges: SortedVector<ActiveEdge>,
@@ -40,8 +44,10 @@ impl<'a> Tiler<'a> {
     #[allow(clippy::or_fun_call)]
     pub(crate) fn new(builder: &'a SceneBuilder<'a>,
                       outline: &'a Outline,
+                      fill_rule: FillRule,
                       view_box: RectF32,
-                      object_index: u16)
+                      object_index: u16,
+                      clip: Option<&'a ClipTileMap>)
                       -> Tiler<'a> {
         let bounds = outline.bounds().intersection(view_box).unwrap_or(RectF32::default());
         let built_object = BuiltObject::new(bounds);
@@ -49,8 +55,10 @@ impl<'a> Tiler<'a> {
         Tiler {
             builder,
             outline,
+            fill_rule,
             built_object,
             object_index,
+            clip,
 
             point_queue: SortedVector::new(),
             active_edges: SortedVector::new(),
@@ -59,6 +67,15 @@ impl<'a> Tiler<'a> {
     }
 
     pub(crate) fn generate_tiles(&mut self) {
+        self.generate_strips();
+
+        // Pack and cull.
+        self.pack_and_cull();
+        //println!("{:#?}", self.built_object);
+    }
+
+    /// Computes fills and backdrops without emitting any tiles. Used for clip paths.
+    pub(crate) fn generate_strips(&mut self) {
         // Initialize the point queue.
         self.init_point_queue();
 
@@ -71,10 +88,6 @@ impl<'a> Tiler<'a> {
         for strip_origin_y in tile_rect.min_y()..tile_rect.max_y() {
             self.generate_strip(strip_origin_y);
         }
-
-        // Pack and cull.
-        self.pack_and_cull();
-        //println!("{:#?}", self.built_object);
     }
 
     fn generate_strip(&mut self, strip_origin_y: i32) {
@@ -97,19 +110,47 @@ impl<'a> Tiler<'a> {
     }
 
     fn pack_and_cull(&mut self) {
-        for (tile_index, tile) in self.built_object.tiles.data.iter().enumerate() {
+        // Translucent objects can't occlude anything, so all their tiles are blended.
+        let z_buffer = if self.builder.object_is_opaque(self.object_index) {
+            Some(self.builder.z_buffer_for_object(self.object_index))
+        } else {
+            None
+        };
+
+        for tile_index in 0..self.built_object.tiles.data.len() {
+            let tile = self.built_object.tiles.data[tile_index];
             let tile_coords = self.built_object.local_tile_index_to_coords(tile_index as u32);
+            let clip_tile = match self.clip {
+                None => ClipTile::Full,
+                Some(clip) => clip.clip_tile(tile_coords),
+            };
+
+            let clip_tile_index = match clip_tile {
+                ClipTile::Empty => continue,
+                ClipTile::Full => !0,
+                ClipTile::Partial(clip_tile_index) => clip_tile_index,
+            };
+
             if tile.is_solid() {
-                if tile.backdrop != 0 {
-                    self.builder.z_buffer.update(tile_coords, self.object_index);
+                if !self.fill_rule.backdrop_is_inside(tile.backdrop) {
+                    continue;
+                }
+                if let (ClipTile::Full, Some(z_buffer)) = (clip_tile, z_buffer) {
+                    z_buffer.update(tile_coords, self.object_index);
+                    continue;
                 }
-                continue;
             }
 
+            // Solid tiles that are partially clipped or translucent get an empty mask tile, so
+            // that their coverage comes from the backdrop alone.
+            let alpha_tile_index =
+                self.built_object.get_or_allocate_alpha_tile_index(self.builder, tile_coords);
             let alpha_tile = AlphaTileBatchPrimitive::new(tile_coords,
                                                           tile.backdrop,
                                                           self.object_index,
-                                                          tile.alpha_tile_index as u16);
+                                                          alpha_tile_index,
+                                                          clip_tile_index,
+                                                          self.fill_rule);
             self.built_object.alpha_tiles.push(alpha_tile);
         }
     }
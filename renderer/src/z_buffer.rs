@@ -34,6 +34,8 @@ impl ZBuffer {
         existing_depth < object_index as usize + 1
     }
 
+    /// Records that the given object covers this tile. Only fully opaque tiles may be recorded,
+    /// since they hide everything beneath them.
     pub fn update(&self, coords: Point2DI32, object_index: u16) {
         let tile_index = self.buffer.coords_to_index_unchecked(coords);
         let mut old_depth = self.buffer.data[tile_index].load(AtomicOrdering::SeqCst);
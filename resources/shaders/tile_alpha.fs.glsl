@@ -13,14 +13,25 @@
 precision highp float;
 
 uniform sampler2D uStencilTexture;
+uniform sampler2D uClipTexture;
 
 in vec2 vTexCoord;
+in vec2 vClipTexCoord;
+flat in float vClipEnabled;
+flat in float vEvenOdd;
 in float vBackdrop;
 in vec4 vColor;
 
 out vec4 oFragColor;
 
+{{{include_tile_paint}}}
+
 void main() {
-    float coverage = abs(texture(uStencilTexture, vTexCoord).r + vBackdrop);
-    oFragColor = vec4(vColor.rgb, vColor.a * coverage);
+    float winding = abs(texture(uStencilTexture, vTexCoord).r + vBackdrop);
+    // Under the even-odd rule, coverage is a triangle wave that peaks at odd winding numbers.
+    float coverage = vEvenOdd > 0.5 ? 1.0 - abs(1.0 - mod(winding, 2.0)) : min(winding, 1.0);
+    if (vClipEnabled > 0.5)
+        coverage *= texture(uClipTexture, vClipTexCoord).r;
+    vec4 color = computePaintColor(vColor);
+    oFragColor = vec4(color.rgb, color.a * coverage);
 }
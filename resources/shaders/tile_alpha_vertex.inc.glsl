@@ -18,12 +18,21 @@ in uvec3 aTileOrigin;
 in int aBackdrop;
 in uint aObject;
 in uint aTileIndex;
+in int aClipTileIndex;
+in uint aFillRule;
 
 out vec2 vTexCoord;
+out vec2 vClipTexCoord;
+flat out float vClipEnabled;
+flat out float vEvenOdd;
 out float vBackdrop;
 out vec4 vColor;
+out vec3 vPaintPosition;
+flat out vec4 vPaintInfo;
+flat out vec4 vPaintParams;
 
 vec4 getFillColor(uint object);
+void computePaintVaryings(uint object, vec2 pixelPosition);
 
 vec2 computeTileOffset(uint tileIndex, float stencilTextureWidth) {
     uint tilesPerRow = uint(stencilTextureWidth / uTileSize.x);
@@ -38,8 +47,21 @@ void computeVaryings() {
     vec2 texCoord = computeTileOffset(aTileIndex, uStencilTextureSize.x) + aTessCoord * uTileSize;
 
     vTexCoord = texCoord / uStencilTextureSize;
+
+    // Clip tiles are laid out in the clip texture just as alpha tiles are in the stencil texture.
+    if (aClipTileIndex >= 0) {
+        vec2 clipTexCoord = computeTileOffset(uint(aClipTileIndex), uStencilTextureSize.x) +
+            aTessCoord * uTileSize;
+        vClipTexCoord = clipTexCoord / uStencilTextureSize;
+        vClipEnabled = 1.0;
+    } else {
+        vClipTexCoord = vec2(0.0);
+        vClipEnabled = 0.0;
+    }
+    vEvenOdd = float(aFillRule);
     vBackdrop = float(aBackdrop);
     vColor = getFillColor(aObject);
+    computePaintVaryings(aObject, pixelPosition);
     gl_Position = vec4(position, 0.0, 1.0);
 }
 
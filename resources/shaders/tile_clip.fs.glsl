@@ -0,0 +1,28 @@
+#version {{version}}
+
+// pathfinder/resources/shaders/tile_clip.fs.glsl
+//
+// Copyright © 2019 The Pathfinder Project Developers.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+precision highp float;
+
+uniform sampler2D uSrcTexture;
+
+in vec2 vTexCoord;
+in float vBackdrop;
+flat in float vEvenOdd;
+
+out vec4 oFragColor;
+
+void main() {
+    // The destination is multiplied by this, so that nested clip paths intersect.
+    float winding = abs(texture(uSrcTexture, vTexCoord).r + vBackdrop);
+    float coverage = vEvenOdd > 0.5 ? 1.0 - abs(1.0 - mod(winding, 2.0)) : min(winding, 1.0);
+    oFragColor = vec4(coverage);
+}
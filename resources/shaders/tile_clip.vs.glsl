@@ -0,0 +1,45 @@
+#version {{version}}
+
+// pathfinder/resources/shaders/tile_clip.vs.glsl
+//
+// Copyright © 2019 The Pathfinder Project Developers.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+precision highp float;
+
+uniform vec2 uFramebufferSize;
+uniform vec2 uTileSize;
+uniform vec2 uSrcTextureSize;
+
+in vec2 aTessCoord;
+in uint aDestTileIndex;
+in uint aSrcTileIndex;
+in int aSrcBackdrop;
+in uint aSrcFillRule;
+
+out vec2 vTexCoord;
+out float vBackdrop;
+flat out float vEvenOdd;
+
+vec2 computeTileOffset(uint tileIndex, float textureWidth) {
+    uint tilesPerRow = uint(textureWidth / uTileSize.x);
+    uvec2 tileOffset = uvec2(tileIndex % tilesPerRow, tileIndex / tilesPerRow);
+    return vec2(tileOffset) * uTileSize;
+}
+
+void main() {
+    vec2 destPosition = computeTileOffset(aDestTileIndex, uFramebufferSize.x) +
+        aTessCoord * uTileSize;
+    vec2 srcPosition = computeTileOffset(aSrcTileIndex, uSrcTextureSize.x) +
+        aTessCoord * uTileSize;
+
+    vTexCoord = srcPosition / uSrcTextureSize;
+    vBackdrop = float(aSrcBackdrop);
+    vEvenOdd = float(aSrcFillRule);
+    gl_Position = vec4(destPosition / uFramebufferSize * 2.0 - 1.0, 0.0, 1.0);
+}
@@ -0,0 +1,43 @@
+#version {{version}}
+
+// pathfinder/resources/shaders/tile_layer.fs.glsl
+//
+// Copyright © 2019 The Pathfinder Project Developers.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+precision highp float;
+
+// Must match `MaskKind` in `scene.rs`.
+#define MASK_KIND_NONE          0
+#define MASK_KIND_LUMINANCE     1
+#define MASK_KIND_ALPHA         2
+
+uniform sampler2D uLayerTexture;
+uniform sampler2D uMaskTexture;
+uniform float uOpacity;
+uniform int uMaskKind;
+
+in vec2 vTexCoord;
+
+out vec4 oFragColor;
+
+float maskCoverage() {
+    if (uMaskKind == MASK_KIND_NONE)
+        return 1.0;
+
+    // The mask is premultiplied too, so its luminance is already multiplied by its alpha.
+    vec4 mask = texture(uMaskTexture, vTexCoord);
+    if (uMaskKind == MASK_KIND_ALPHA)
+        return mask.a;
+    return dot(mask.rgb, vec3(0.2125, 0.7154, 0.0721));
+}
+
+void main() {
+    // Tiles are blended into the layer over transparent black, so its color is premultiplied.
+    oFragColor = texture(uLayerTexture, vTexCoord) * (uOpacity * maskCoverage());
+}
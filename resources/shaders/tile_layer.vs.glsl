@@ -0,0 +1,31 @@
+#version {{version}}
+
+// pathfinder/resources/shaders/tile_layer.vs.glsl
+//
+// Copyright © 2019 The Pathfinder Project Developers.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+precision highp float;
+
+uniform vec2 uFramebufferSize;
+uniform vec2 uTileSize;
+uniform vec2 uViewBoxOrigin;
+
+in vec2 aTessCoord;
+in vec2 aTileOrigin;
+
+out vec2 vTexCoord;
+
+void main() {
+    vec2 pixelPosition = (aTileOrigin + aTessCoord) * uTileSize + uViewBoxOrigin;
+    vec2 position = (pixelPosition / uFramebufferSize * 2.0 - 1.0) * vec2(1.0, -1.0);
+
+    // The layer has the same size as the destination, but its rows run bottom to top.
+    vTexCoord = vec2(pixelPosition.x, uFramebufferSize.y - pixelPosition.y) / uFramebufferSize;
+    gl_Position = vec4(position, 0.0, 1.0);
+}
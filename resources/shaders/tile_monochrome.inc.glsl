@@ -13,3 +13,9 @@ uniform vec4 uFillColor;
 vec4 getFillColor(uint object) {
     return uFillColor;
 }
+
+void computePaintVaryings(uint object, vec2 pixelPosition) {
+    vPaintPosition = vec3(0.0);
+    vPaintInfo = vec4(0.0);
+    vPaintParams = vec4(0.0);
+}
@@ -10,6 +10,7 @@
 
 uniform sampler2D uFillColorsTexture;
 uniform vec2 uFillColorsTextureSize;
+uniform sampler2D uPaintDataTexture;
 
 vec2 computeFillColorTexCoord(uint object, vec2 textureSize) {
     uint width = uint(textureSize.x);
@@ -20,3 +21,21 @@ vec4 getFillColor(uint object) {
     vec2 colorTexCoord = computeFillColorTexCoord(object, uFillColorsTextureSize);
     return texture(uFillColorsTexture, colorTexCoord);
 }
+
+// See `Renderer::upload_shaders()` for the layout of the paint data.
+void computePaintVaryings(uint object, vec2 pixelPosition) {
+    int width = textureSize(uPaintDataTexture, 0).x;
+    int texelIndex = int(object) * 4;
+    ivec2 texelCoord = ivec2(texelIndex % width, texelIndex / width);
+    vec4 row0 = texelFetch(uPaintDataTexture, texelCoord, 0);
+    vec4 row1 = texelFetch(uPaintDataTexture, texelCoord + ivec2(1, 0), 0);
+    vec4 row2 = texelFetch(uPaintDataTexture, texelCoord + ivec2(2, 0), 0);
+    vec4 params = texelFetch(uPaintDataTexture, texelCoord + ivec2(3, 0), 0);
+
+    vec3 position = vec3(pixelPosition, 1.0);
+    vPaintPosition = vec3(dot(row0.xyz, position),
+                          dot(row1.xyz, position),
+                          dot(row2.xyz, position));
+    vPaintInfo = vec4(row0.w, row1.w, row2.w, 0.0);
+    vPaintParams = params;
+}
@@ -0,0 +1,79 @@
+// pathfinder/resources/shaders/tile_paint.inc.glsl
+//
+// Copyright © 2019 The Pathfinder Project Developers.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Evaluates gradient and image paints. For gradients, the vertex shader supplies the position in
+// normalized gradient space, where a linear gradient runs from x = 0 to x = 1 and a radial
+// gradient ends at the unit circle. `vPaintInfo` holds the gradient kind, spread method and color
+// ramp row, `vPaintParams.xy` holds the focal point of a radial gradient, and `vPaintParams.zw`
+// hold the columns of the first and last texels of the ramp within its row. For images, the
+// position is in texels of the image atlas, `vPaintParams` holds the image's rect there, and the
+// spread method is either pad or repeat.
+
+#define PAINT_KIND_COLOR        0.0
+#define PAINT_KIND_LINEAR       1.0
+#define PAINT_KIND_IMAGE        3.0
+
+#define SPREAD_METHOD_PAD       0.0
+#define SPREAD_METHOD_REFLECT   1.0
+#define SPREAD_METHOD_REPEAT    2.0
+
+uniform sampler2D uGradientRampsTexture;
+uniform sampler2D uImageAtlasTexture;
+
+in vec3 vPaintPosition;
+flat in vec4 vPaintInfo;
+flat in vec4 vPaintParams;
+
+float computeRadialGradientT(vec2 position, vec2 focal) {
+    // Solve |position - focal * (1 - t)| = t for the circle passing through `position`.
+    vec2 fromFocal = position - focal;
+    float a = dot(focal, focal) - 1.0;
+    float b = -dot(fromFocal, focal);
+    float c = dot(fromFocal, fromFocal);
+    if (abs(a) < 0.0001)
+        return c / (2.0 * b);
+    return (b - sqrt(max(b * b - a * c, 0.0))) / a;
+}
+
+vec4 computePaintColor(vec4 color) {
+    if (vPaintInfo.x == PAINT_KIND_COLOR)
+        return color;
+
+    vec2 position = vPaintPosition.xy / vPaintPosition.z;
+    if (vPaintInfo.x == PAINT_KIND_IMAGE) {
+        if (vPaintInfo.y == SPREAD_METHOD_REPEAT) {
+            vec2 imageSize = vPaintParams.zw - vPaintParams.xy;
+            position = vPaintParams.xy + mod(position - vPaintParams.xy, imageSize);
+        }
+
+        // Clamp to the centers of the edge texels so as not to bleed into neighboring images.
+        position = clamp(position, vPaintParams.xy + 0.5, vPaintParams.zw - 0.5);
+        return texture(uImageAtlasTexture, position / vec2(textureSize(uImageAtlasTexture, 0))) *
+            color;
+    }
+
+    float t;
+    if (vPaintInfo.x == PAINT_KIND_LINEAR)
+        t = position.x;
+    else
+        t = computeRadialGradientT(position, vPaintParams.xy);
+
+    if (vPaintInfo.y == SPREAD_METHOD_PAD)
+        t = clamp(t, 0.0, 1.0);
+    else if (vPaintInfo.y == SPREAD_METHOD_REFLECT)
+        t = 1.0 - abs(mod(t, 2.0) - 1.0);
+    else
+        t = fract(t);
+
+    vec2 rampSize = vec2(textureSize(uGradientRampsTexture, 0));
+    vec2 rampTexCoord = vec2(mix(vPaintParams.z, vPaintParams.w, t) + 0.5, vPaintInfo.z + 0.5) /
+        rampSize;
+    return texture(uGradientRampsTexture, rampTexCoord) * color;
+}
@@ -16,6 +16,8 @@ in vec4 vColor;
 
 out vec4 oFragColor;
 
+{{{include_tile_paint}}}
+
 void main() {
-    oFragColor = vColor;
+    oFragColor = computePaintColor(vColor);
 }
@@ -17,13 +17,18 @@ in vec2 aTileOrigin;
 in uint aObject;
 
 out vec4 vColor;
+out vec3 vPaintPosition;
+flat out vec4 vPaintInfo;
+flat out vec4 vPaintParams;
 
 vec4 getFillColor(uint object);
+void computePaintVaryings(uint object, vec2 pixelPosition);
 
 void computeVaryings() {
     vec2 pixelPosition = (aTileOrigin + aTessCoord) * uTileSize + uViewBoxOrigin;
     vec2 position = (pixelPosition / uFramebufferSize * 2.0 - 1.0) * vec2(1.0, -1.0);
 
     vColor = getFillColor(aObject);
+    computePaintVaryings(aObject, pixelPosition);
     gl_Position = vec4(position, 0.0, 1.0);
 }
@@ -0,0 +1,11 @@
+# Traces a square with absolute moves, which draw while the pen is down.
+import turtle
+
+t = turtle.Turtle()
+t.pensize(2)
+t.goto(10, 0)
+t.sety(10)
+t.setx(0)
+t.home()
+t.penup()
+t.setpos(20, 20)
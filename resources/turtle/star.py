@@ -0,0 +1,19 @@
+# A five-pointed star, written against Python's `turtle` module.
+import turtle
+
+t = turtle.Turtle()
+t.pensize(2)
+t.pencolor("orange")
+t.penup()
+t.goto(-50, 20)
+t.pendown()
+t.forward(100)
+t.right(144)
+t.forward(100)
+t.right(144)
+t.forward(100)
+t.right(144)
+t.forward(100)
+t.right(144)
+t.forward(100)
+turtle.done()
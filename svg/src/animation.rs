@@ -0,0 +1,902 @@
+// pathfinder/svg/src/animation.rs
+//
+// Copyright © 2019 The Pathfinder Project Developers.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Plays back `<animate>`, `<animateTransform>`, and CSS keyframe animations.
+//!
+//! usvg drops animations when it simplifies the tree, so they're read from the source document
+//! separately and matched to the nodes of the scene graph by element ID. Animated transforms and
+//! opacities are then applied to a copy of the built scene, without building it again.
+//!
+//! Only elements with an ID can be animated, and only `transform` and `opacity` are supported.
+
+use crate::concat_transforms;
+use crate::graph::{GraphNodeId, SceneGraph};
+use pathfinder_geometry::basic::point::Point2DF32;
+use pathfinder_geometry::basic::transform2d::Transform2DF32;
+use pathfinder_renderer::scene::Scene;
+use roxmltree::{Document, Node as XmlNode};
+use std::cmp::{self, Ordering};
+use std::collections::HashMap;
+use std::f32::consts::PI;
+use std::ops::Range;
+use std::str;
+
+const XLINK_NAMESPACE: &str = "http://www.w3.org/1999/xlink";
+
+/// The animations in a document.
+#[derive(Clone, Debug, Default)]
+pub struct Animations {
+    animations: Vec<Animation>,
+}
+
+#[derive(Clone, Debug)]
+struct Animation {
+    // The ID of the animated element.
+    target: String,
+    keyframes: Vec<Keyframe>,
+    timing: Timing,
+    // Whether the value is concatenated onto the underlying transform instead of replacing it.
+    additive: bool,
+    // Whether values jump from keyframe to keyframe instead of being interpolated.
+    discrete: bool,
+}
+
+#[derive(Clone, Debug)]
+struct Keyframe {
+    // Between 0 and 1.
+    offset: f32,
+    value: Value,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Value {
+    Transform(Vec<TransformFunction>),
+    Opacity(f32),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum TransformFunction {
+    Translate(f32, f32),
+    Scale(f32, f32),
+    // The angle, in radians, and the center.
+    Rotate(f32, f32, f32),
+    SkewX(f32),
+    SkewY(f32),
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Timing {
+    // In seconds.
+    begin: f32,
+    duration: f32,
+    // `None` repeats forever.
+    iterations: Option<f32>,
+    // Whether the last value is kept once the animation ends.
+    freeze: bool,
+}
+
+impl Animations {
+    /// Finds the animations in an SVG document. Animations that can't be parsed are skipped.
+    pub fn from_data(data: &[u8]) -> Animations {
+        let mut animations = Animations::default();
+        let text = match str::from_utf8(data) {
+            Ok(text) => text,
+            Err(_) => return animations,
+        };
+        let document = match Document::parse(text) {
+            Ok(document) => document,
+            Err(_) => return animations,
+        };
+
+        let mut style_sheets = vec![];
+        for node in document.descendants().filter(|node| node.is_element()) {
+            match node.tag_name().name() {
+                "animate" | "animateTransform" => {
+                    if let Some(animation) = parse_smil_animation(&node) {
+                        animations.animations.push(animation);
+                    }
+                }
+                "style" => style_sheets.extend(node.text()),
+                _ => {}
+            }
+        }
+        for style_sheet in style_sheets {
+            parse_css_animations(style_sheet, &mut animations.animations);
+        }
+
+        animations
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.animations.is_empty()
+    }
+
+    /// Returns the time, in seconds, after which nothing changes, or `None` if some animation
+    /// repeats forever.
+    pub fn duration(&self) -> Option<f32> {
+        let mut duration = 0.0;
+        for animation in &self.animations {
+            let timing = &animation.timing;
+            duration = f32::max(duration, timing.begin + timing.duration * timing.iterations?);
+        }
+        Some(duration)
+    }
+
+    /// Returns a copy of `scene`, which must have been built along with `graph`, with the
+    /// animations evaluated at `time` seconds.
+    pub fn apply(&self, scene: &Scene, graph: &SceneGraph, time: f32) -> Scene {
+        let mut scene = scene.clone();
+        if graph.nodes.is_empty() {
+            return scene;
+        }
+
+        let mut state = AnimatedState {
+            transforms: graph.nodes.iter().map(|node| node.transform).collect(),
+            opacities: vec![1.0; graph.nodes.len()],
+            animated: vec![false; graph.nodes.len()],
+        };
+
+        for animation in &self.animations {
+            let value = match animation.timing.progress(time) {
+                None => continue,
+                Some(progress) => animation.value_at(progress),
+            };
+            for (index, node) in graph.nodes.iter().enumerate() {
+                if node.id != animation.target {
+                    continue;
+                }
+                state.animated[index] = true;
+                match value {
+                    Value::Transform(ref functions) => {
+                        let transform = transform_functions_to_transform(functions);
+                        // The transform of the root includes the global transform of the scene.
+                        state.transforms[index] = if animation.additive || index == 0 {
+                            concat_transforms(&state.transforms[index], &transform)
+                        } else {
+                            transform
+                        };
+                    }
+                    Value::Opacity(opacity) => state.opacities[index] = opacity,
+                }
+            }
+        }
+
+        let identity = Transform2DF32::default();
+        state.apply_to_node(&mut scene, graph, graph.root(), &identity, &identity, 1.0, false);
+        scene
+    }
+}
+
+struct AnimatedState {
+    transforms: Vec<Transform2DF32>,
+    opacities: Vec<f32>,
+    animated: Vec<bool>,
+}
+
+impl AnimatedState {
+    // The objects of each node are moved by the difference between its animated transform to
+    // scene space and the one it was built with.
+    #[allow(clippy::too_many_arguments)]
+    fn apply_to_node(&self,
+                     scene: &mut Scene,
+                     graph: &SceneGraph,
+                     node: GraphNodeId,
+                     base_parent_transform: &Transform2DF32,
+                     animated_parent_transform: &Transform2DF32,
+                     opacity: f32,
+                     animated: bool) {
+        let (graph_node, index) = (graph.get(node), node.0 as usize);
+        let base_transform = concat_transforms(base_parent_transform, &graph_node.transform);
+        let animated_transform = concat_transforms(animated_parent_transform,
+                                                   &self.transforms[index]);
+        let opacity = opacity * self.opacities[index];
+        let animated = animated || self.animated[index];
+
+        if animated {
+            let delta = if base_transform.min_scale_factor() > 0.0 {
+                concat_transforms(&animated_transform, &base_transform.inverse())
+            } else {
+                Transform2DF32::default()
+            };
+
+            // Objects that belong to no child, like the contents of masks, belong to this node.
+            let mut object_index = graph_node.objects.start;
+            for &kid in &graph_node.children {
+                let kid_objects = &graph.get(kid).objects;
+                apply_to_objects(scene, object_index..kid_objects.start, &delta, opacity);
+                object_index = kid_objects.end;
+            }
+            apply_to_objects(scene, object_index..graph_node.objects.end, &delta, opacity);
+        }
+
+        for &kid in &graph_node.children {
+            self.apply_to_node(scene,
+                               graph,
+                               kid,
+                               &base_transform,
+                               &animated_transform,
+                               opacity,
+                               animated);
+        }
+    }
+}
+
+// FIXME(pcwalton): Animated opacity multiplies the opacity that the scene was built with instead
+// of replacing it, and strokes and paints aren't rebuilt under animated transforms.
+fn apply_to_objects(scene: &mut Scene,
+                    objects: Range<u32>,
+                    transform: &Transform2DF32,
+                    opacity: f32) {
+    for object_index in objects {
+        let object_index = object_index as usize;
+        if !transform.is_identity() {
+            let object = &mut scene.objects[object_index];
+            object.outline_mut().transform(transform);
+            scene.bounds = scene.bounds.union_rect(object.outline().bounds());
+        }
+        if opacity < 1.0 {
+            let mut paint = scene.paints[scene.objects[object_index].paint().0 as usize];
+            paint.color.a = (paint.color.a as f32 * f32::max(opacity, 0.0)).round() as u8;
+            let paint = scene.push_paint(&paint);
+            scene.objects[object_index].set_paint(paint);
+        }
+    }
+}
+
+impl Animation {
+    fn value_at(&self, progress: f32) -> Value {
+        let keyframes = &self.keyframes;
+        let next_index = keyframes.iter()
+                                  .position(|keyframe| keyframe.offset > progress)
+                                  .unwrap_or(keyframes.len());
+        if next_index == 0 {
+            return keyframes[0].value.clone();
+        }
+        let prev = &keyframes[next_index - 1];
+        if self.discrete || next_index == keyframes.len() {
+            return prev.value.clone();
+        }
+
+        let next = &keyframes[next_index];
+        let t = (progress - prev.offset) / (next.offset - prev.offset);
+        prev.value.lerp(&next.value, t)
+    }
+}
+
+impl Timing {
+    // Returns how far through the current iteration the animation is, or `None` if it isn't
+    // in effect.
+    fn progress(&self, time: f32) -> Option<f32> {
+        let elapsed = time - self.begin;
+        if elapsed < 0.0 || !(self.duration > 0.0) {
+            return None;
+        }
+
+        let iteration = elapsed / self.duration;
+        match self.iterations {
+            Some(iterations) if iteration >= iterations => {
+                if !self.freeze {
+                    return None;
+                }
+                let fract = iterations.fract();
+                Some(if fract == 0.0 { 1.0 } else { fract })
+            }
+            _ => Some(iteration.fract()),
+        }
+    }
+}
+
+impl Value {
+    fn lerp(&self, other: &Value, t: f32) -> Value {
+        match (self, other) {
+            (&Value::Opacity(a), &Value::Opacity(b)) => Value::Opacity(lerp(a, b, t)),
+            (&Value::Transform(ref a), &Value::Transform(ref b)) if a.len() == b.len() => {
+                let mut functions = Vec::with_capacity(a.len());
+                for (a, b) in a.iter().zip(b.iter()) {
+                    match a.lerp(b, t) {
+                        Some(function) => functions.push(function),
+                        None => return if t < 0.5 { self.clone() } else { other.clone() },
+                    }
+                }
+                Value::Transform(functions)
+            }
+            _ => if t < 0.5 { self.clone() } else { other.clone() },
+        }
+    }
+}
+
+impl TransformFunction {
+    // Returns `None` if the functions are of different kinds.
+    fn lerp(&self, other: &TransformFunction, t: f32) -> Option<TransformFunction> {
+        use self::TransformFunction::*;
+        match (*self, *other) {
+            (Translate(x0, y0), Translate(x1, y1)) => {
+                Some(Translate(lerp(x0, x1, t), lerp(y0, y1, t)))
+            }
+            (Scale(x0, y0), Scale(x1, y1)) => Some(Scale(lerp(x0, x1, t), lerp(y0, y1, t))),
+            (Rotate(a0, x0, y0), Rotate(a1, x1, y1)) => {
+                Some(Rotate(lerp(a0, a1, t), lerp(x0, x1, t), lerp(y0, y1, t)))
+            }
+            (SkewX(a0), SkewX(a1)) => Some(SkewX(lerp(a0, a1, t))),
+            (SkewY(a0), SkewY(a1)) => Some(SkewY(lerp(a0, a1, t))),
+            _ => None,
+        }
+    }
+
+    fn to_transform(&self) -> Transform2DF32 {
+        match *self {
+            TransformFunction::Translate(x, y) => {
+                Transform2DF32::from_translation(&Point2DF32::new(x, y))
+            }
+            TransformFunction::Scale(x, y) => Transform2DF32::from_scale(&Point2DF32::new(x, y)),
+            TransformFunction::Rotate(angle, x, y) => {
+                let (sin, cos) = angle.sin_cos();
+                let rotation = Transform2DF32::row_major(cos, -sin, sin, cos, 0.0, 0.0);
+                let center = Point2DF32::new(x, y);
+                concat_transforms(&Transform2DF32::from_translation(&center),
+                                  &concat_transforms(&rotation,
+                                                     &Transform2DF32::from_translation(&-center)))
+            }
+            TransformFunction::SkewX(angle) => {
+                Transform2DF32::row_major(1.0, angle.tan(), 0.0, 1.0, 0.0, 0.0)
+            }
+            TransformFunction::SkewY(angle) => {
+                Transform2DF32::row_major(1.0, 0.0, angle.tan(), 1.0, 0.0, 0.0)
+            }
+        }
+    }
+}
+
+// As in the `transform` attribute, the first function is the outermost.
+fn transform_functions_to_transform(functions: &[TransformFunction]) -> Transform2DF32 {
+    functions.iter().fold(Transform2DF32::default(), |transform, function| {
+        concat_transforms(&transform, &function.to_transform())
+    })
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+// SMIL
+
+fn parse_smil_animation(node: &XmlNode) -> Option<Animation> {
+    let target = match node.attribute((XLINK_NAMESPACE, "href")).or(node.attribute("href")) {
+        Some(href) if href.starts_with('#') => href[1..].to_string(),
+        Some(_) => return None,
+        None => node.parent_element()?.attribute("id")?.to_string(),
+    };
+
+    let parse_value: Box<dyn Fn(&str) -> Option<Value>> = match node.tag_name().name() {
+        "animateTransform" => {
+            let kind = node.attribute("type").unwrap_or("translate").to_string();
+            Box::new(move |value| {
+                parse_smil_transform(&kind, value).map(|function| Value::Transform(vec![function]))
+            })
+        }
+        _ => {
+            if node.attribute("attributeName") != Some("opacity") {
+                return None;
+            }
+            Box::new(|value| parse_number(value).map(Value::Opacity))
+        }
+    };
+
+    // FIXME(pcwalton): Support `by` and `to` animations without `from`.
+    let values: Vec<Value> = match (node.attribute("values"), node.attribute("from"),
+                                    node.attribute("to")) {
+        (Some(values), _, _) => {
+            values.split(';')
+                  .filter(|value| !value.trim().is_empty())
+                  .map(|value| parse_value(value))
+                  .collect::<Option<_>>()?
+        }
+        (None, Some(from), Some(to)) => vec![parse_value(from)?, parse_value(to)?],
+        _ => return None,
+    };
+    if values.is_empty() {
+        return None;
+    }
+
+    // FIXME(pcwalton): `spline` and `paced` are interpolated linearly.
+    let discrete = node.attribute("calcMode") == Some("discrete");
+    let offsets: Vec<f32> = match node.attribute("keyTimes") {
+        Some(key_times) => {
+            let key_times: Vec<f32> = key_times.split(';')
+                                               .map(parse_number)
+                                               .collect::<Option<_>>()?;
+            if key_times.len() != values.len() {
+                return None;
+            }
+            key_times
+        }
+        None if discrete => {
+            (0..values.len()).map(|index| index as f32 / values.len() as f32).collect()
+        }
+        None => {
+            let last = cmp::max(values.len() - 1, 1) as f32;
+            (0..values.len()).map(|index| index as f32 / last).collect()
+        }
+    };
+
+    let keyframes = offsets.into_iter().zip(values.into_iter()).map(|(offset, value)| {
+        Keyframe { offset, value }
+    }).collect();
+
+    // FIXME(pcwalton): Event-based `begin` values and `repeatDur` aren't supported.
+    let begin = match node.attribute("begin") {
+        None => 0.0,
+        Some(begin) => begin.split(';').filter_map(parse_clock_value).next()?,
+    };
+    let iterations = match node.attribute("repeatCount") {
+        None => Some(1.0),
+        Some("indefinite") => None,
+        Some(count) => Some(parse_number(count)?),
+    };
+    let timing = Timing {
+        begin,
+        duration: parse_clock_value(node.attribute("dur")?)?,
+        iterations,
+        freeze: node.attribute("fill") == Some("freeze"),
+    };
+
+    Some(Animation {
+        target,
+        keyframes,
+        timing,
+        additive: node.attribute("additive") == Some("sum"),
+        discrete,
+    })
+}
+
+fn parse_smil_transform(kind: &str, value: &str) -> Option<TransformFunction> {
+    let numbers = parse_number_list(value)?;
+    let number = |index: usize| numbers.get(index).cloned();
+    match kind {
+        "translate" => Some(TransformFunction::Translate(number(0)?, number(1).unwrap_or(0.0))),
+        "scale" => {
+            let x = number(0)?;
+            Some(TransformFunction::Scale(x, number(1).unwrap_or(x)))
+        }
+        "rotate" => {
+            Some(TransformFunction::Rotate(number(0)?.to_radians(),
+                                           number(1).unwrap_or(0.0),
+                                           number(2).unwrap_or(0.0)))
+        }
+        "skewX" => Some(TransformFunction::SkewX(number(0)?.to_radians())),
+        "skewY" => Some(TransformFunction::SkewY(number(0)?.to_radians())),
+        _ => None,
+    }
+}
+
+// Accepts `2s`, `500ms`, `1.5min`, `1h`, and plain seconds.
+fn parse_clock_value(value: &str) -> Option<f32> {
+    let value = value.trim();
+    let (number, scale) = if value.ends_with("ms") {
+        (&value[..value.len() - 2], 0.001)
+    } else if value.ends_with("min") {
+        (&value[..value.len() - 3], 60.0)
+    } else if value.ends_with('h') {
+        (&value[..value.len() - 1], 3600.0)
+    } else if value.ends_with('s') {
+        (&value[..value.len() - 1], 1.0)
+    } else {
+        (value, 1.0)
+    };
+    parse_number(number).map(|number| number * scale)
+}
+
+// CSS
+
+// An `animation` declaration, before its keyframes are looked up.
+struct CssAnimation {
+    name: String,
+    timing: Timing,
+}
+
+impl Default for CssAnimation {
+    fn default() -> CssAnimation {
+        CssAnimation {
+            name: String::new(),
+            timing: Timing { begin: 0.0, duration: 0.0, iterations: Some(1.0), freeze: false },
+        }
+    }
+}
+
+// FIXME(pcwalton): Only `#id` selectors are matched, every timing function is linear, and
+// `animation-direction` is ignored.
+fn parse_css_animations(style_sheet: &str, animations: &mut Vec<Animation>) {
+    let style_sheet = strip_css_comments(style_sheet);
+
+    let mut keyframes: HashMap<String, Vec<(f32, Vec<(String, String)>)>> = HashMap::new();
+    let mut rules = vec![];
+    for (prelude, body) in css_blocks(&style_sheet) {
+        if prelude.starts_with("@keyframes") || prelude.starts_with("@-webkit-keyframes") {
+            let name = prelude.splitn(2, char::is_whitespace).nth(1).unwrap_or("").trim();
+            let mut frames = vec![];
+            for (selectors, declarations) in css_blocks(body) {
+                let declarations = css_declarations(declarations);
+                for selector in selectors.split(',') {
+                    let offset = match selector.trim() {
+                        "from" => Some(0.0),
+                        "to" => Some(1.0),
+                        selector if selector.ends_with('%') => {
+                            parse_number(&selector[..selector.len() - 1]).map(|p| p / 100.0)
+                        }
+                        _ => None,
+                    };
+                    if let Some(offset) = offset {
+                        frames.push((offset, declarations.clone()));
+                    }
+                }
+            }
+            frames.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal));
+            keyframes.insert(name.to_string(), frames);
+        } else if !prelude.starts_with('@') {
+            let ids: Vec<String> = prelude.split(',').filter_map(|selector| {
+                let selector = selector.trim();
+                if selector.starts_with('#') && selector[1..].chars().all(is_css_name_char) {
+                    Some(selector[1..].to_string())
+                } else {
+                    None
+                }
+            }).collect();
+            if !ids.is_empty() {
+                rules.push((ids, css_declarations(body)));
+            }
+        }
+    }
+
+    for (ids, declarations) in rules {
+        for css_animation in parse_css_animation_declarations(&declarations) {
+            let frames = match keyframes.get(&css_animation.name) {
+                None => continue,
+                Some(frames) => frames,
+            };
+
+            // Each property gets an animation of its own, since keyframes may leave some out.
+            for &property in &["transform", "opacity"] {
+                let property_keyframes: Vec<Keyframe> = frames.iter().filter_map(|frame| {
+                    let value = frame.1.iter().rev().find(|declaration| {
+                        declaration.0 == property
+                    })?;
+                    let value = match property {
+                        "transform" => Value::Transform(parse_css_transform(&value.1)?),
+                        _ => Value::Opacity(parse_number(&value.1)?),
+                    };
+                    Some(Keyframe { offset: frame.0, value })
+                }).collect();
+                if property_keyframes.is_empty() {
+                    continue;
+                }
+
+                for id in &ids {
+                    animations.push(Animation {
+                        target: id.clone(),
+                        keyframes: property_keyframes.clone(),
+                        timing: css_animation.timing,
+                        additive: false,
+                        discrete: false,
+                    });
+                }
+            }
+        }
+    }
+}
+
+fn parse_css_animation_declarations(declarations: &[(String, String)]) -> Vec<CssAnimation> {
+    let mut css_animations: Vec<CssAnimation> = vec![];
+    for &(ref property, ref value) in declarations {
+        match &**property {
+            "animation" => {
+                css_animations = value.split(',').map(parse_css_animation_shorthand).collect();
+            }
+            _ if property.starts_with("animation-") => {
+                // Longhands apply to the first animation only.
+                if css_animations.is_empty() {
+                    css_animations.push(CssAnimation::default());
+                }
+                let css_animation = &mut css_animations[0];
+                let value = value.trim();
+                match &property["animation-".len()..] {
+                    "name" => css_animation.name = value.to_string(),
+                    "duration" => {
+                        css_animation.timing.duration = parse_clock_value(value).unwrap_or(0.0)
+                    }
+                    "delay" => css_animation.timing.begin = parse_clock_value(value).unwrap_or(0.0),
+                    "iteration-count" => {
+                        css_animation.timing.iterations = match value {
+                            "infinite" => None,
+                            _ => Some(parse_number(value).unwrap_or(1.0)),
+                        }
+                    }
+                    "fill-mode" => {
+                        css_animation.timing.freeze = value == "forwards" || value == "both"
+                    }
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+    css_animations
+}
+
+fn parse_css_animation_shorthand(value: &str) -> CssAnimation {
+    let mut css_animation = CssAnimation::default();
+    let mut times_seen = 0;
+    for token in value.split_whitespace() {
+        if let Some(time) = parse_css_time(token) {
+            match times_seen {
+                0 => css_animation.timing.duration = time,
+                _ => css_animation.timing.begin = time,
+            }
+            times_seen += 1;
+            continue;
+        }
+        match token {
+            "infinite" => css_animation.timing.iterations = None,
+            "forwards" | "both" => css_animation.timing.freeze = true,
+            "backwards" | "none" | "normal" | "reverse" | "alternate" | "alternate-reverse" |
+            "running" | "paused" | "linear" | "ease" | "ease-in" | "ease-out" | "ease-in-out" |
+            "step-start" | "step-end" => {}
+            _ => match parse_number(token) {
+                Some(iterations) => css_animation.timing.iterations = Some(iterations),
+                None => css_animation.name = token.to_string(),
+            },
+        }
+    }
+    css_animation
+}
+
+// Unlike SMIL clock values, CSS times must have units.
+fn parse_css_time(value: &str) -> Option<f32> {
+    if value.ends_with("ms") || value.ends_with('s') {
+        parse_clock_value(value)
+    } else {
+        None
+    }
+}
+
+fn parse_css_transform(value: &str) -> Option<Vec<TransformFunction>> {
+    let mut functions = vec![];
+    let mut rest = value.trim();
+    if rest == "none" {
+        return Some(functions);
+    }
+
+    while !rest.is_empty() {
+        let open = rest.find('(')?;
+        let close = rest.find(')')?;
+        let name = rest[..open].trim();
+        let args: Vec<&str> = rest[(open + 1)..close].split(|c: char| {
+            c == ',' || c.is_whitespace()
+        }).filter(|arg| !arg.is_empty()).collect();
+        rest = rest[(close + 1)..].trim_start();
+
+        let length = |index: usize| args.get(index).and_then(|arg| parse_css_length(arg));
+        let angle = |index: usize| args.get(index).and_then(|arg| parse_css_angle(arg));
+        functions.push(match name {
+            "translate" => TransformFunction::Translate(length(0)?, length(1).unwrap_or(0.0)),
+            "translateX" => TransformFunction::Translate(length(0)?, 0.0),
+            "translateY" => TransformFunction::Translate(0.0, length(0)?),
+            "scale" => {
+                let x = length(0)?;
+                TransformFunction::Scale(x, length(1).unwrap_or(x))
+            }
+            "scaleX" => TransformFunction::Scale(length(0)?, 1.0),
+            "scaleY" => TransformFunction::Scale(1.0, length(0)?),
+            "rotate" => TransformFunction::Rotate(angle(0)?, 0.0, 0.0),
+            "skewX" => TransformFunction::SkewX(angle(0)?),
+            "skewY" => TransformFunction::SkewY(angle(0)?),
+            _ => return None,
+        });
+    }
+    Some(functions)
+}
+
+fn parse_css_length(value: &str) -> Option<f32> {
+    parse_number(value.trim_end_matches("px"))
+}
+
+// Returns radians.
+fn parse_css_angle(value: &str) -> Option<f32> {
+    if value.ends_with("deg") {
+        parse_number(&value[..value.len() - 3]).map(f32::to_radians)
+    } else if value.ends_with("grad") {
+        parse_number(&value[..value.len() - 4]).map(|grad| grad * PI / 200.0)
+    } else if value.ends_with("rad") {
+        parse_number(&value[..value.len() - 3])
+    } else if value.ends_with("turn") {
+        parse_number(&value[..value.len() - 4]).map(|turns| turns * 2.0 * PI)
+    } else {
+        // Only zero may be unitless, but be lenient.
+        parse_number(value).map(f32::to_radians)
+    }
+}
+
+fn strip_css_comments(style_sheet: &str) -> String {
+    let mut result = String::with_capacity(style_sheet.len());
+    let mut rest = style_sheet;
+    while let Some(start) = rest.find("/*") {
+        result.push_str(&rest[..start]);
+        rest = match rest[(start + 2)..].find("*/") {
+            Some(end) => &rest[(start + 2 + end + 2)..],
+            None => "",
+        };
+    }
+    result.push_str(rest);
+    result
+}
+
+// Splits a style sheet into top-level `prelude { body }` blocks. Statements ending with `;`
+// outside of blocks, like `@import`, are skipped.
+fn css_blocks(text: &str) -> Vec<(&str, &str)> {
+    let mut blocks = vec![];
+    let (mut depth, mut prelude_start, mut body_start) = (0, 0, 0);
+    for (index, c) in text.char_indices() {
+        match c {
+            '{' => {
+                if depth == 0 {
+                    body_start = index + 1;
+                }
+                depth += 1;
+            }
+            '}' if depth > 0 => {
+                depth -= 1;
+                if depth == 0 {
+                    let prelude = text[prelude_start..(body_start - 1)].trim();
+                    blocks.push((prelude, &text[body_start..index]));
+                    prelude_start = index + 1;
+                }
+            }
+            ';' if depth == 0 => prelude_start = index + 1,
+            _ => {}
+        }
+    }
+    blocks
+}
+
+fn css_declarations(body: &str) -> Vec<(String, String)> {
+    body.split(';').filter_map(|declaration| {
+        let colon = declaration.find(':')?;
+        let property = declaration[..colon].trim().to_lowercase();
+        let value = declaration[(colon + 1)..].trim().trim_end_matches("!important").trim();
+        Some((property, value.to_string()))
+    }).collect()
+}
+
+fn is_css_name_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '-' || c == '_'
+}
+
+// Rejects `NaN` and infinities, which `f32::from_str` accepts.
+fn parse_number(value: &str) -> Option<f32> {
+    value.trim().parse().ok().filter(|number: &f32| number.is_finite())
+}
+
+fn parse_number_list(value: &str) -> Option<Vec<f32>> {
+    value.split(|c: char| c == ',' || c.is_whitespace())
+         .filter(|number| !number.is_empty())
+         .map(parse_number)
+         .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Animation, Keyframe, Timing, TransformFunction, Value, parse_clock_value};
+    use super::{parse_css_animation_shorthand, parse_css_animations, parse_css_transform};
+
+    fn opacity_animation(offsets: &[f32], discrete: bool) -> Animation {
+        let keyframes = offsets.iter().enumerate().map(|(index, &offset)| {
+            Keyframe { offset, value: Value::Opacity(index as f32 * 0.5) }
+        }).collect();
+        Animation {
+            target: "a".to_string(),
+            keyframes,
+            timing: Timing { begin: 0.0, duration: 1.0, iterations: Some(1.0), freeze: false },
+            additive: false,
+            discrete,
+        }
+    }
+
+    #[test]
+    fn test_clock_values() {
+        assert_eq!(parse_clock_value("2s"), Some(2.0));
+        assert_eq!(parse_clock_value(" 500ms "), Some(0.5));
+        assert_eq!(parse_clock_value("1.5min"), Some(90.0));
+        assert_eq!(parse_clock_value("1h"), Some(3600.0));
+        assert_eq!(parse_clock_value("3"), Some(3.0));
+        assert_eq!(parse_clock_value("NaNs"), None);
+        assert_eq!(parse_clock_value("inf"), None);
+        assert_eq!(parse_clock_value("soon"), None);
+    }
+
+    #[test]
+    fn test_css_animation_shorthand() {
+        let css_animation = parse_css_animation_shorthand("spin 2s linear 1s infinite forwards");
+        assert_eq!(css_animation.name, "spin");
+        assert_eq!(css_animation.timing.duration, 2.0);
+        assert_eq!(css_animation.timing.begin, 1.0);
+        assert_eq!(css_animation.timing.iterations, None);
+        assert!(css_animation.timing.freeze);
+
+        let css_animation = parse_css_animation_shorthand("fade 500ms 3");
+        assert_eq!(css_animation.name, "fade");
+        assert_eq!(css_animation.timing.duration, 0.5);
+        assert_eq!(css_animation.timing.begin, 0.0);
+        assert_eq!(css_animation.timing.iterations, Some(3.0));
+        assert!(!css_animation.timing.freeze);
+    }
+
+    #[test]
+    fn test_css_transform() {
+        assert_eq!(parse_css_transform("translate(10px, 20px) rotate(90deg) scale(2)"),
+                   Some(vec![TransformFunction::Translate(10.0, 20.0),
+                             TransformFunction::Rotate(90.0f32.to_radians(), 0.0, 0.0),
+                             TransformFunction::Scale(2.0, 2.0)]));
+        assert_eq!(parse_css_transform("scaleY(3) skewX(0.5rad)"),
+                   Some(vec![TransformFunction::Scale(1.0, 3.0),
+                             TransformFunction::SkewX(0.5)]));
+        assert_eq!(parse_css_transform("none"), Some(vec![]));
+        assert_eq!(parse_css_transform("perspective(10px)"), None);
+        assert_eq!(parse_css_transform("translate(NaN)"), None);
+    }
+
+    #[test]
+    fn test_keyframe_selectors_that_are_not_numbers_are_skipped() {
+        let mut animations = vec![];
+        parse_css_animations("@keyframes fade { NaN% { opacity: 0 } 50% { opacity: 0.5 } \
+                              to { opacity: 1 } } #a { animation: fade 1s }",
+                             &mut animations);
+        assert_eq!(animations.len(), 1);
+        let offsets: Vec<f32> = animations[0].keyframes.iter().map(|frame| frame.offset).collect();
+        assert_eq!(offsets, vec![0.5, 1.0]);
+    }
+
+    #[test]
+    fn test_progress() {
+        let mut timing = Timing { begin: 1.0, duration: 2.0, iterations: Some(1.5), freeze: false };
+        assert_eq!(timing.progress(0.5), None);
+        assert_eq!(timing.progress(2.0), Some(0.5));
+        assert_eq!(timing.progress(4.0), None);
+
+        timing.freeze = true;
+        assert_eq!(timing.progress(4.0), Some(0.5));
+        timing.iterations = Some(1.0);
+        assert_eq!(timing.progress(10.0), Some(1.0));
+
+        timing.iterations = None;
+        assert_eq!(timing.progress(6.0), Some(0.5));
+        assert_eq!(timing.progress(101.0), Some(0.0));
+
+        timing.duration = 0.0;
+        assert_eq!(timing.progress(2.0), None);
+    }
+
+    #[test]
+    fn test_value_at_key_times() {
+        let animation = opacity_animation(&[0.0, 0.25, 1.0], false);
+        assert_eq!(animation.value_at(0.0), Value::Opacity(0.0));
+        assert_eq!(animation.value_at(0.125), Value::Opacity(0.25));
+        assert_eq!(animation.value_at(0.625), Value::Opacity(0.75));
+        assert_eq!(animation.value_at(1.0), Value::Opacity(1.0));
+    }
+
+    #[test]
+    fn test_value_at_discrete() {
+        let animation = opacity_animation(&[0.0, 0.25, 1.0], true);
+        assert_eq!(animation.value_at(0.1), Value::Opacity(0.0));
+        assert_eq!(animation.value_at(0.5), Value::Opacity(0.5));
+        assert_eq!(animation.value_at(0.99), Value::Opacity(0.5));
+        assert_eq!(animation.value_at(1.0), Value::Opacity(1.0));
+    }
+}
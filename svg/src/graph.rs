@@ -0,0 +1,121 @@
+// pathfinder/svg/src/graph.rs
+//
+// Copyright © 2019 The Pathfinder Project Developers.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! The structure of the source document, kept alongside the flattened scene.
+//!
+//! Every drawn element becomes a node that records its transform and the range of scene objects
+//! it produced. Since objects are pushed in document order, the objects of an element and all
+//! of its descendants are contiguous.
+
+use crate::concat_transforms;
+use pathfinder_geometry::basic::transform2d::Transform2DF32;
+use pathfinder_renderer::scene::Scene;
+use std::ops::Range;
+
+/// The element hierarchy of a built SVG. The first node is the root `<svg>` element.
+#[derive(Clone, Debug, Default)]
+pub struct SceneGraph {
+    pub nodes: Vec<GraphNode>,
+    // The node that new nodes are added to, while building.
+    current: Option<GraphNodeId>,
+}
+
+#[derive(Clone, Debug)]
+pub struct GraphNode {
+    /// The ID of the element, or the empty string if it has none.
+    pub id: String,
+    /// The classes of the element. These are only known for elements with IDs, and only if
+    /// source attributes were given when building; see `BuildOptions::source_attributes`.
+    pub classes: Vec<String>,
+    /// The name of the element, such as `g` or `path`.
+    pub element: &'static str,
+    /// The transform from the user space of this element to that of its parent. For the root,
+    /// this is the global transform of the scene, and for a nested `<svg>`, it includes the
+    /// mapping of its view box onto its viewport.
+    pub transform: Transform2DF32,
+    /// The scene objects that this element and its descendants produced.
+    pub objects: Range<u32>,
+    pub parent: Option<GraphNodeId>,
+    pub children: Vec<GraphNodeId>,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct GraphNodeId(pub u32);
+
+impl SceneGraph {
+    #[inline]
+    pub fn root(&self) -> GraphNodeId {
+        GraphNodeId(0)
+    }
+
+    #[inline]
+    pub fn get(&self, node: GraphNodeId) -> &GraphNode {
+        &self.nodes[node.0 as usize]
+    }
+
+    /// Returns the first node, in document order, with the given element ID.
+    pub fn find(&self, id: &str) -> Option<GraphNodeId> {
+        self.nodes.iter().position(|node| node.id == id).map(|index| GraphNodeId(index as u32))
+    }
+
+    /// Returns the transform from the user space of `node` to scene space.
+    pub fn world_transform(&self, mut node: GraphNodeId) -> Transform2DF32 {
+        let mut transform = self.get(node).transform;
+        while let Some(parent) = self.get(node).parent {
+            transform = concat_transforms(&self.get(parent).transform, &transform);
+            node = parent;
+        }
+        transform
+    }
+
+    /// Applies `transform`, in scene space, to the outlines of the objects of `node`.
+    ///
+    /// Paints, clip paths, and strokes aren't updated, so large changes may need a rebuild.
+    pub fn transform_objects(&self,
+                             scene: &mut Scene,
+                             node: GraphNodeId,
+                             transform: &Transform2DF32) {
+        for object_index in self.get(node).objects.clone() {
+            let object = &mut scene.objects[object_index as usize];
+            object.outline_mut().transform(transform);
+            scene.bounds = scene.bounds.union_rect(object.outline().bounds());
+        }
+    }
+
+    pub(crate) fn begin_node(&mut self,
+                             id: String,
+                             classes: Vec<String>,
+                             element: &'static str,
+                             transform: Transform2DF32,
+                             first_object_index: u32)
+                             -> GraphNodeId {
+        let node = GraphNodeId(self.nodes.len() as u32);
+        self.nodes.push(GraphNode {
+            id,
+            classes,
+            element,
+            transform,
+            objects: first_object_index..first_object_index,
+            parent: self.current,
+            children: vec![],
+        });
+        if let Some(parent) = self.current {
+            self.nodes[parent.0 as usize].children.push(node);
+        }
+        self.current = Some(node);
+        node
+    }
+
+    pub(crate) fn end_node(&mut self, node: GraphNodeId, object_count: u32) {
+        let graph_node = &mut self.nodes[node.0 as usize];
+        graph_node.objects.end = object_count;
+        self.current = graph_node.parent;
+    }
+}
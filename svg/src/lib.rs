@@ -13,28 +13,79 @@
 #[macro_use]
 extern crate bitflags;
 
+use crate::graph::SceneGraph;
+use crate::marker::{MarkerOrient, MarkerUnits, Markers, path_marker_vertices};
+use crate::source::SourceAttributes;
+use crate::text::FontCache;
 use pathfinder_geometry::basic::line_segment::LineSegmentF32;
-use pathfinder_geometry::basic::point::Point2DF32;
+use pathfinder_geometry::basic::point::{Point2DF32, Point2DI32};
 use pathfinder_geometry::basic::rect::RectF32;
 use pathfinder_geometry::basic::transform2d::{Transform2DF32, Transform2DF32PathIter};
 use pathfinder_geometry::color::ColorU;
 use pathfinder_geometry::outline::Outline;
 use pathfinder_geometry::segment::{Segment, SegmentFlags};
-use pathfinder_geometry::stroke::OutlineStrokeToFill;
-use pathfinder_renderer::scene::{Paint, PathObject, PathObjectKind, Scene};
+use pathfinder_geometry::stroke::{LineCap, LineJoin, OutlineStrokeToFill, StrokeStyle};
+use pathfinder_gpu::resources::ResourceLoader;
+use pathfinder_renderer::gradient::{Gradient, GradientGeometry, GradientId, GradientStop};
+use pathfinder_renderer::gradient::SpreadMethod;
+use pathfinder_renderer::image::{Image, ImageId};
+use pathfinder_renderer::scene::{ClipPath, ClipPathId, FillRule, Layer, Mask, MaskKind, Paint};
+use pathfinder_renderer::scene::{PaintId, PathObject, PathObjectKind, Scene};
+use std::cmp::{self, Ordering};
+use std::collections::HashMap;
+use std::f32::consts::PI;
+use std::error::Error;
 use std::fmt::{Display, Formatter, Result as FormatResult};
 use std::mem;
-use usvg::{Color as SvgColor, Node, NodeExt, NodeKind, Paint as UsvgPaint};
-use usvg::{PathSegment as UsvgPathSegment, Rect as UsvgRect, Transform as UsvgTransform};
-use usvg::{Tree, Visibility};
+use std::ops::Range;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::sync::Arc;
+use usvg::{Align, AspectRatio, BaseGradient, Color as SvgColor, Fill as UsvgFill};
+use usvg::{FillRule as UsvgFillRule, Image as UsvgImage, ImageData as UsvgImageData};
+use usvg::{ImageFormat as UsvgImageFormat, LineCap as UsvgLineCap, LineJoin as UsvgLineJoin};
+use usvg::{Node, NodeExt, NodeKind, Opacity, Paint as UsvgPaint, Path as UsvgPath};
+use usvg::{PathSegment as UsvgPathSegment, Rect as UsvgRect, SpreadMethod as UsvgSpreadMethod};
+use usvg::{Stroke as UsvgStroke, Svg as UsvgSvg, Transform as UsvgTransform, Tree, Units};
+use usvg::{Error as UsvgError, Options as UsvgOptions, Visibility};
 
+pub mod animation;
+pub mod graph;
+pub mod marker;
+pub mod source;
+
+mod pattern;
+mod text;
+
+// The minimum width of a stroke in device pixels.
 const HAIRLINE_STROKE_WIDTH: f32 = 0.0333;
 
+// The maximum width and height of the image that one pattern tile is drawn into.
+const MAX_PATTERN_TILE_LENGTH: i32 = 1024;
+
 pub struct BuiltSVG {
     pub scene: Scene,
+    /// Every unsupported feature that was encountered.
     pub result_flags: BuildResultFlags,
+    /// Each use of an unsupported feature, in document order.
+    pub diagnostics: Vec<Diagnostic>,
+    /// The element hierarchy, if `BuildOptions::build_graph` was set.
+    pub graph: Option<SceneGraph>,
+    render_transform: Transform2DF32,
+    flatten_tolerance: Option<f32>,
+    markers: Option<Rc<Markers>>,
+    source_attributes: Option<Rc<SourceAttributes>>,
+    // Gradients already in the scene, by the ID of their node and their transform to scene space.
+    // Pattern tiles are built as scenes of their own, with caches of their own.
+    gradient_cache: HashMap<(String, [u32; 6]), GradientId>,
+    // The contents of images referenced by path, loaded before building starts.
+    external_images: HashMap<PathBuf, Vec<u8>>,
+    fonts: FontCache,
 }
 
+/// Nodes in `<defs>`, by ID.
+type Defs = HashMap<String, Node>;
+
 bitflags! {
     // NB: If you change this, make sure to update the `Display`
     // implementation as well.
@@ -54,25 +105,188 @@ bitflags! {
         const UNSUPPORTED_FILTER_ATTR          = 0x1000;
         const UNSUPPORTED_MASK_ATTR            = 0x2000;
         const UNSUPPORTED_OPACITY_ATTR         = 0x4000;
+        const UNSUPPORTED_MARKER_ATTR          = 0x8000;
+    }
+}
+
+/// An unsupported feature used by an element.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+    /// The ID of the element, or the empty string if it has none.
+    pub node_id: String,
+    /// The name of the element, such as `g` or `clipPath`.
+    pub element: &'static str,
+    /// The feature, as exactly one flag.
+    pub feature: BuildResultFlags,
+}
+
+/// Why an SVG document couldn't be loaded.
+#[derive(Debug)]
+pub enum SvgError {
+    /// The document isn't well-formed SVG.
+    Parse(UsvgError),
+}
+
+/// Options that control how a tree is turned into a scene.
+#[derive(Clone, Debug, Default)]
+pub struct BuildOptions {
+    /// A transform applied to the whole scene, after fitting it to `target_size`.
+    pub transform: Transform2DF32,
+    /// If set, the root view box is mapped onto a rectangle of this size at the origin, in
+    /// pixels, as `fit` directs. Otherwise, scene coordinates are the root's user units.
+    pub target_size: Option<Point2DF32>,
+    /// How the root view box is fitted to `target_size`.
+    pub fit: FitMode,
+    /// If set, curves are replaced with lines that stay within this distance of them, in scene
+    /// units, as the scene is built.
+    pub flatten_tolerance: Option<f32>,
+    /// The transform that the scene is expected to be rendered with. Strokes are kept at least a
+    /// hairline wide in device space under it.
+    pub render_transform: Transform2DF32,
+    /// Whether to keep the element hierarchy in `BuiltSVG::graph`.
+    pub build_graph: bool,
+    /// The markers of the source document. usvg drops markers, so none are drawn unless these
+    /// are read from the document with `Markers::from_data()`.
+    pub markers: Option<Rc<Markers>>,
+    /// Attributes of the source document that usvg drops, such as `class`. They're
+    /// ignored unless these are read from the document with `SourceAttributes::from_data()`.
+    pub source_attributes: Option<Rc<SourceAttributes>>,
+}
+
+/// How the root view box is fitted to the target size.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FitMode {
+    /// Scales uniformly so that the view box fits inside the target, aligned as the root's
+    /// `preserveAspectRatio` directs.
+    Contain,
+    /// Scales uniformly so that the view box covers the target, aligned as the root's
+    /// `preserveAspectRatio` directs.
+    Cover,
+    /// Scales each axis independently so that the view box fills the target exactly.
+    Stretch,
+}
+
+impl Default for FitMode {
+    #[inline]
+    fn default() -> FitMode {
+        FitMode::Contain
     }
 }
 
 impl BuiltSVG {
-    // TODO(pcwalton): Allow a global transform to be set.
+    /// Builds a scene from the tree. Images that refer to external files aren't drawn, and text
+    /// is drawn with system fonts only; use `from_tree_with_resources()` to load them.
+    #[inline]
     pub fn from_tree(tree: Tree) -> BuiltSVG {
-        let global_transform = Transform2DF32::default();
+        BuiltSVG::from_tree_with_options(tree, &BuildOptions::default(), None)
+    }
+
+    /// Builds a scene that is expected to be rendered with `render_transform`. Strokes are kept
+    /// at least a hairline wide in device space under it.
+    #[inline]
+    pub fn from_tree_with_render_transform(tree: Tree, render_transform: &Transform2DF32)
+                                           -> BuiltSVG {
+        let options = BuildOptions {
+            render_transform: *render_transform,
+            ..BuildOptions::default()
+        };
+        BuiltSVG::from_tree_with_options(tree, &options, None)
+    }
+
+    /// Builds a scene, loading images that refer to external files and fonts from `resources`.
+    #[inline]
+    pub fn from_tree_with_resources(tree: Tree, resources: &dyn ResourceLoader) -> BuiltSVG {
+        BuiltSVG::from_tree_with_options(tree, &BuildOptions::default(), Some(resources))
+    }
+
+    /// Parses an SVG document and builds a scene from it as `options` direct. Images that refer
+    /// to external files aren't drawn; use `from_data_with_resources()` to load them.
+    ///
+    /// Unless `options` supplies markers and source attributes, they're read from `data`.
+    #[inline]
+    pub fn from_data(data: &[u8], options: &BuildOptions) -> Result<BuiltSVG, SvgError> {
+        BuiltSVG::from_data_with_options(data, options, None)
+    }
+
+    /// Parses an SVG document and builds a scene from it, loading images that refer to external
+    /// files and fonts from `resources`.
+    #[inline]
+    pub fn from_data_with_resources(data: &[u8],
+                                    options: &BuildOptions,
+                                    resources: &dyn ResourceLoader)
+                                    -> Result<BuiltSVG, SvgError> {
+        BuiltSVG::from_data_with_options(data, options, Some(resources))
+    }
+
+    fn from_data_with_options(data: &[u8],
+                              options: &BuildOptions,
+                              resources: Option<&dyn ResourceLoader>)
+                              -> Result<BuiltSVG, SvgError> {
+        let tree = Tree::from_data(data, &UsvgOptions::default()).map_err(SvgError::Parse)?;
+        let mut options = options.clone();
+        if options.markers.is_none() {
+            options.markers = Some(Rc::new(Markers::from_data(data)));
+        }
+        if options.source_attributes.is_none() {
+            options.source_attributes = Some(Rc::new(SourceAttributes::from_data(data)));
+        }
+        Ok(BuiltSVG::from_tree_with_options(tree, &options, resources))
+    }
+
+    /// Builds a scene as `options` direct, loading external images and fonts from `resources`
+    /// if given.
+    pub fn from_tree_with_options(tree: Tree,
+                                  options: &BuildOptions,
+                                  resources: Option<&dyn ResourceLoader>)
+                                  -> BuiltSVG {
+        let root = &tree.root();
+        let external_images = match resources {
+            None => HashMap::new(),
+            Some(resources) => load_external_images(root, resources),
+        };
 
         let mut built_svg = BuiltSVG {
             scene: Scene::new(),
             result_flags: BuildResultFlags::empty(),
+            diagnostics: vec![],
+            graph: if options.build_graph { Some(SceneGraph::default()) } else { None },
+            render_transform: options.render_transform,
+            flatten_tolerance: options.flatten_tolerance,
+            markers: options.markers.clone(),
+            source_attributes: options.source_attributes.clone(),
+            gradient_cache: HashMap::new(),
+            external_images,
+            fonts: FontCache::new(root, resources),
         };
 
-        let root = &tree.root();
+        let defs = collect_defs(root);
         match *root.borrow() {
             NodeKind::Svg(ref svg) => {
-                built_svg.scene.view_box = usvg_rect_to_euclid_rect(&svg.view_box.rect);
+                let view_box = usvg_rect_to_euclid_rect(&svg.view_box.rect);
+                let (view_box, fit_transform) = match options.target_size {
+                    None => (view_box, Transform2DF32::default()),
+                    Some(target_size) => {
+                        let target = RectF32::new(Point2DF32::default(), target_size);
+                        let (align, slice) = match options.fit {
+                            FitMode::Contain => (&svg.view_box.aspect.align, false),
+                            FitMode::Cover => (&svg.view_box.aspect.align, true),
+                            FitMode::Stretch => (&Align::None, false),
+                        };
+                        (target, view_box_transform(view_box, target, align, slice))
+                    }
+                };
+                let global_transform = concat_transforms(&options.transform, &fit_transform);
+
+                built_svg.scene.view_box = options.transform.transform_rect(&view_box);
+                let classes = built_svg.source_classes(root);
+                let graph_root = built_svg.graph.as_mut().map(|graph| {
+                    graph.begin_node(root.id().to_string(), classes, "svg", global_transform, 0)
+                });
                 for kid in root.children() {
-                    built_svg.process_node(&kid, &global_transform);
+                    built_svg.process_node(&kid, &defs, &global_transform, None, None);
+                }
+                if let (Some(graph), Some(graph_root)) = (built_svg.graph.as_mut(), graph_root) {
+                    graph.end_node(graph_root, built_svg.scene.objects.len() as u32);
                 }
             }
             _ => unreachable!(),
@@ -80,112 +294,873 @@ impl BuiltSVG {
 
         // FIXME(pcwalton): This is needed to avoid stack exhaustion in debug builds when
         // recursively dropping reference counts on very large SVGs. :(
+        mem::forget(defs);
         mem::forget(tree);
 
         built_svg
     }
 
-    fn process_node(&mut self, node: &Node, transform: &Transform2DF32) {
+    // `folded_opacity` is `None` outside of layers. Inside one, it's the opacity of any nested
+    // groups, which gets folded into paints since layers don't nest.
+    fn process_node(&mut self,
+                    node: &Node,
+                    defs: &Defs,
+                    transform: &Transform2DF32,
+                    clip_path: Option<ClipPathId>,
+                    folded_opacity: Option<f32>) {
+        // Only elements that draw something, directly or through their children, go into the
+        // graph.
+        let is_drawn = match *node.borrow() {
+            NodeKind::Group(..) |
+            NodeKind::Svg(..) |
+            NodeKind::Path(..) |
+            NodeKind::Text(..) |
+            NodeKind::Image(..) => true,
+            _ => false,
+        };
+        let first_object_index = self.scene.objects.len() as u32;
+        let graph_node = if is_drawn && self.graph.is_some() {
+            let mut node_transform = usvg_transform_to_transform_2d(&node.transform());
+            if let NodeKind::Svg(ref svg) = *node.borrow() {
+                if let Some(view_box_transform) = usvg_nested_svg_transform(svg) {
+                    node_transform = concat_transforms(&node_transform, &view_box_transform);
+                }
+            }
+            let classes = self.source_classes(node);
+            self.graph.as_mut().map(|graph| {
+                graph.begin_node(node.id().to_string(),
+                                 classes,
+                                 usvg_node_element_name(node),
+                                 node_transform,
+                                 first_object_index)
+            })
+        } else {
+            None
+        };
+
+        self.process_node_contents(node, defs, transform, clip_path, folded_opacity);
+
+        if let (Some(graph), Some(graph_node)) = (self.graph.as_mut(), graph_node) {
+            graph.end_node(graph_node, self.scene.objects.len() as u32);
+        }
+    }
+
+    fn process_node_contents(&mut self,
+                             node: &Node,
+                             defs: &Defs,
+                             transform: &Transform2DF32,
+                             clip_path: Option<ClipPathId>,
+                             folded_opacity: Option<f32>) {
         let node_transform = usvg_transform_to_transform_2d(&node.transform());
-        let transform = transform.pre_mul(&node_transform);
+        let transform = concat_transforms(transform, &node_transform);
 
         match *node.borrow() {
             NodeKind::Group(ref group) => {
-                if group.clip_path.is_some() {
-                    self.result_flags.insert(BuildResultFlags::UNSUPPORTED_CLIP_PATH_ATTR);
+                let mut clip_path = clip_path;
+                if let Some(ref clip_path_id) = group.clip_path {
+                    match self.push_svg_clip_path(clip_path_id, defs, node, &transform, clip_path) {
+                        Some(new_clip_path) => clip_path = Some(new_clip_path),
+                        None => {
+                            self.report(node, BuildResultFlags::UNSUPPORTED_CLIP_PATH_ATTR)
+                        }
+                    }
                 }
                 if group.filter.is_some() {
-                    self.result_flags.insert(BuildResultFlags::UNSUPPORTED_FILTER_ATTR);
+                    self.report(node, BuildResultFlags::UNSUPPORTED_FILTER_ATTR);
                 }
-                if group.mask.is_some() {
-                    self.result_flags.insert(BuildResultFlags::UNSUPPORTED_MASK_ATTR);
+
+                // Masked groups become layers, which don't nest.
+                let mut mask_node = None;
+                if let Some(ref mask_id) = group.mask {
+                    match defs.get(mask_id) {
+                        Some(mask) if folded_opacity.is_none() => mask_node = Some(mask.clone()),
+                        _ => self.report(node, BuildResultFlags::UNSUPPORTED_MASK_ATTR),
+                    }
                 }
-                if group.opacity.is_some() {
-                    self.result_flags.insert(BuildResultFlags::UNSUPPORTED_OPACITY_ATTR);
+
+                // Folding nested group opacity into paints is only exact if the group's contents
+                // don't overlap, so it's reported if they do.
+                let mut layer_opacity = None;
+                let mut folds_opacity = false;
+                let mut folded_opacity = folded_opacity;
+                let opacity = group.opacity.map(|opacity| opacity.value() as f32);
+                if mask_node.is_some() {
+                    // The opacity is applied along with the mask when the layer is composited.
+                    layer_opacity = Some(opacity.unwrap_or(1.0));
+                    folded_opacity = Some(1.0);
+                } else if let Some(opacity) = opacity {
+                    match folded_opacity {
+                        None => {
+                            layer_opacity = Some(opacity);
+                            folded_opacity = Some(1.0);
+                        }
+                        Some(ref mut folded_opacity) => {
+                            *folded_opacity *= opacity;
+                            folds_opacity = opacity < 1.0;
+                        }
+                    }
                 }
 
+                let first_object_index = self.scene.objects.len() as u32;
                 for kid in node.children() {
-                    self.process_node(&kid, &transform)
+                    self.process_node(&kid, defs, &transform, clip_path, folded_opacity)
                 }
-            }
-            NodeKind::Path(ref path) if path.visibility == Visibility::Visible => {
-                if let Some(ref fill) = path.fill {
-                    let style =
-                        self.scene.push_paint(&Paint::from_svg_paint(&fill.paint,
-                                                                     &mut self.result_flags));
-
-                    let path = UsvgPathToSegments::new(path.segments.iter().cloned());
-                    let path = Transform2DF32PathIter::new(path, &transform);
-                    let outline = Outline::from_segments(path);
-
-                    self.scene.bounds = self.scene.bounds.union_rect(outline.bounds());
-                    self.scene.objects.push(PathObject::new(
-                        outline,
-                        style,
-                        node.id().to_string(),
-                        PathObjectKind::Fill,
-                    ));
+
+                let objects = first_object_index..(self.scene.objects.len() as u32);
+                if folds_opacity && self.objects_overlap(objects) {
+                    self.report(node, BuildResultFlags::UNSUPPORTED_OPACITY_ATTR);
                 }
 
-                if let Some(ref stroke) = path.stroke {
-                    let style =
-                        self.scene.push_paint(&Paint::from_svg_paint(&stroke.paint,
-                                                                     &mut self.result_flags));
-                    let stroke_width =
-                        f32::max(stroke.width.value() as f32, HAIRLINE_STROKE_WIDTH);
-
-                    let path = UsvgPathToSegments::new(path.segments.iter().cloned());
-                    let outline = Outline::from_segments(path);
-
-                    let mut stroke_to_fill = OutlineStrokeToFill::new(outline, stroke_width);
-                    stroke_to_fill.offset();
-                    let mut outline = stroke_to_fill.outline;
-                    outline.transform(&transform);
-
-                    self.scene.bounds = self.scene.bounds.union_rect(outline.bounds());
-                    self.scene.objects.push(PathObject::new(
-                        outline,
-                        style,
-                        node.id().to_string(),
-                        PathObjectKind::Stroke,
-                    ));
+                if let Some(opacity) = layer_opacity {
+                    let objects = first_object_index..(self.scene.objects.len() as u32);
+                    let mask = mask_node.as_ref().and_then(|mask_node| {
+                        self.push_svg_mask(mask_node, defs, node, &transform, clip_path)
+                    });
+                    if mask_node.is_some() && mask.is_none() {
+                        self.report(node, BuildResultFlags::UNSUPPORTED_MASK_ATTR);
+                    }
+                    self.scene.push_layer(Layer { objects, opacity, mask });
                 }
             }
-            NodeKind::Path(..) => {}
-            NodeKind::ClipPath(..) => {
-                self.result_flags.insert(BuildResultFlags::UNSUPPORTED_CLIP_PATH_NODE);
+            NodeKind::Path(ref path) if path.visibility == Visibility::Visible => {
+                let path_segments = UsvgPathToSegments::new(path.segments.iter().cloned());
+                let outline = Outline::from_segments(path_segments);
+                let non_scaling_stroke = match self.source_attributes {
+                    Some(ref attributes) => attributes.has_non_scaling_stroke(&node.id()),
+                    None => false,
+                };
+                self.push_svg_shape(node,
+                                    outline,
+                                    path.fill.as_ref(),
+                                    path.stroke.as_ref(),
+                                    non_scaling_stroke,
+                                    defs,
+                                    &transform,
+                                    clip_path,
+                                    folded_opacity.unwrap_or(1.0));
+                self.push_svg_markers(node, path, &transform, clip_path, folded_opacity);
             }
+            NodeKind::Path(..) => {}
             NodeKind::Defs { .. } => {
-                if node.has_children() {
-                    self.result_flags.insert(BuildResultFlags::UNSUPPORTED_DEFS_NODE);
+                // Paint servers, clip paths, and masks are looked up through `Defs` when they're
+                // referenced.
+                for kid in node.children() {
+                    self.process_node(&kid, defs, &transform, clip_path, folded_opacity)
                 }
             }
             NodeKind::Filter(..) => {
-                self.result_flags.insert(BuildResultFlags::UNSUPPORTED_FILTER_NODE);
+                self.report(node, BuildResultFlags::UNSUPPORTED_FILTER_NODE);
+            }
+            NodeKind::Image(ref image) => {
+                let opacity = folded_opacity.unwrap_or(1.0);
+                if self.push_svg_image(node, image, &transform, clip_path, opacity).is_none() {
+                    self.report(node, BuildResultFlags::UNSUPPORTED_IMAGE_NODE);
+                }
+            }
+            NodeKind::ClipPath(..) |
+            NodeKind::LinearGradient(..) |
+            NodeKind::Mask(..) |
+            NodeKind::Pattern(..) |
+            NodeKind::RadialGradient(..) => {}
+            NodeKind::Svg(ref svg) => {
+                // A nested `<svg>` is a group that maps its view box onto its viewport. Since
+                // `overflow` is `hidden` by default, its contents are clipped to the viewport.
+                let view_box_transform = match usvg_nested_svg_transform(svg) {
+                    Some(view_box_transform) => view_box_transform,
+                    None => return,
+                };
+                let viewport = usvg_nested_svg_viewport(svg);
+                let viewport = Transform2DF32PathIter::new(rect_to_segments(viewport).into_iter(),
+                                                           &transform);
+                let mut outline = Outline::from_segments(viewport);
+                if let Some(tolerance) = self.flatten_tolerance {
+                    outline.flatten(tolerance);
+                }
+                let clip_path = Some(self.scene.push_clip_path(ClipPath {
+                    outline,
+                    fill_rule: FillRule::Winding,
+                    parent: clip_path,
+                }));
+
+                let transform = concat_transforms(&transform, &view_box_transform);
+                for kid in node.children() {
+                    self.process_node(&kid, defs, &transform, clip_path, folded_opacity)
+                }
+            }
+            NodeKind::Text(ref text) => {
+                let span_outlines = match self.fonts.layout_text(text) {
+                    Some(span_outlines) => span_outlines,
+                    None => {
+                        self.report(node, BuildResultFlags::UNSUPPORTED_TEXT_NODE);
+                        return;
+                    }
+                };
+                for (span, outline) in span_outlines {
+                    self.push_svg_shape(node,
+                                        outline,
+                                        span.fill.as_ref(),
+                                        span.stroke.as_ref(),
+                                        false,
+                                        defs,
+                                        &transform,
+                                        clip_path,
+                                        folded_opacity.unwrap_or(1.0));
+                }
+            }
+        }
+    }
+
+    /// Fills and strokes `outline`, which is in the user space of `node`.
+    #[allow(clippy::too_many_arguments)]
+    fn push_svg_shape(&mut self,
+                      node: &Node,
+                      outline: Outline,
+                      fill: Option<&UsvgFill>,
+                      stroke: Option<&UsvgStroke>,
+                      non_scaling_stroke: bool,
+                      defs: &Defs,
+                      transform: &Transform2DF32,
+                      clip_path: Option<ClipPathId>,
+                      folded_opacity: f32) {
+        // For `objectBoundingBox` gradients.
+        let bounding_box = outline.bounds();
+
+        if let Some(fill) = fill {
+            let style = self.push_svg_paint(node,
+                                            &fill.paint,
+                                            fill.opacity,
+                                            folded_opacity,
+                                            defs,
+                                            transform,
+                                            || bounding_box);
+
+            let mut outline = outline.clone();
+            outline.transform(transform);
+
+            self.push_object(outline,
+                             style,
+                             &node.id(),
+                             PathObjectKind::Fill,
+                             usvg_fill_rule_to_fill_rule(fill.rule),
+                             clip_path);
+        }
+
+        if let Some(stroke) = stroke {
+            let style = self.push_svg_paint(node,
+                                            &stroke.paint,
+                                            stroke.opacity,
+                                            folded_opacity,
+                                            defs,
+                                            transform,
+                                            || bounding_box);
+            let mut stroke_style = usvg_stroke_to_stroke_style(stroke);
+
+            // Normally, strokes are offset in user space and then transformed, as SVG requires.
+            // Non-scaling strokes are offset in scene space instead.
+            let stroke_transform = if non_scaling_stroke {
+                Transform2DF32::default()
+            } else {
+                *transform
+            };
+            let device_transform = concat_transforms(&self.render_transform, &stroke_transform);
+            let min_scale_factor = device_transform.min_scale_factor();
+            if min_scale_factor > 0.0 {
+                stroke_style.line_width = f32::max(stroke_style.line_width,
+                                                   HAIRLINE_STROKE_WIDTH / min_scale_factor);
             }
-            NodeKind::Image(..) => {
-                self.result_flags.insert(BuildResultFlags::UNSUPPORTED_IMAGE_NODE);
+
+            let mut outline = outline;
+            if non_scaling_stroke {
+                outline.transform(transform);
             }
-            NodeKind::LinearGradient(..) => {
-                self.result_flags.insert(BuildResultFlags::UNSUPPORTED_LINEAR_GRADIENT_NODE);
+
+            let mut stroke_to_fill = OutlineStrokeToFill::new(outline, stroke_style);
+            stroke_to_fill.offset();
+            let mut outline = stroke_to_fill.outline;
+            if !non_scaling_stroke {
+                outline.transform(transform);
             }
-            NodeKind::Mask(..) => {
-                self.result_flags.insert(BuildResultFlags::UNSUPPORTED_MASK_NODE);
+
+            self.push_object(outline,
+                             style,
+                             &node.id(),
+                             PathObjectKind::Stroke,
+                             FillRule::Winding,
+                             clip_path);
+        }
+    }
+
+    fn push_svg_paint<F>(&mut self,
+                         node: &Node,
+                         svg_paint: &UsvgPaint,
+                         opacity: Opacity,
+                         folded_opacity: f32,
+                         defs: &Defs,
+                         transform: &Transform2DF32,
+                         get_bounding_box: F)
+                         -> PaintId
+                         where F: FnOnce() -> RectF32 {
+        let opacity = opacity.value() as f32 * folded_opacity;
+        let paint = match *svg_paint {
+            UsvgPaint::Color(color) => Paint::from_color(ColorU::from_svg_color(color, opacity)),
+            UsvgPaint::Link(ref id) => {
+                let color = ColorU { r: 255, g: 255, b: 255, a: opacity_to_alpha(opacity) };
+                let paint = defs.get(id).and_then(|node| {
+                    if let NodeKind::Pattern(..) = *node.borrow() {
+                        let bounding_box = get_bounding_box();
+                        let image_id = self.push_svg_pattern(node, defs, transform, bounding_box)?;
+                        return Some(Paint { color, gradient: None, image: Some(image_id) });
+                    }
+                    let gradient = Gradient::from_svg_node(node, transform, get_bounding_box)?;
+                    let key = (id.clone(), transform_key(&gradient.transform));
+                    let scene = &mut self.scene;
+                    let gradient_id = *self.gradient_cache.entry(key).or_insert_with(|| {
+                        scene.push_gradient(gradient)
+                    });
+                    Some(Paint { color, gradient: Some(gradient_id), image: None })
+                });
+                match paint {
+                    Some(paint) => paint,
+                    None => {
+                        self.report(node, BuildResultFlags::UNSUPPORTED_LINK_PAINT);
+                        let color = ColorU { a: opacity_to_alpha(opacity), ..ColorU::black() };
+                        Paint::from_color(color)
+                    }
+                }
             }
-            NodeKind::Pattern(..) => {
-                self.result_flags.insert(BuildResultFlags::UNSUPPORTED_PATTERN_NODE);
+        };
+        self.scene.push_paint(&paint)
+    }
+
+    /// Draws one tile of the `<pattern>` node `node` into an image that repeats across the plane.
+    /// `transform` maps the user space of the element being painted, whose bounding box is
+    /// `bounding_box`, to scene space. Returns `None` if `node` isn't a pattern.
+    fn push_svg_pattern(&mut self,
+                        node: &Node,
+                        defs: &Defs,
+                        transform: &Transform2DF32,
+                        bounding_box: RectF32)
+                        -> Option<ImageId> {
+        let bounding_box_transform = object_bounding_box_transform(bounding_box);
+        let (tile_rect, pattern_transform, content_transform) = match *node.borrow() {
+            NodeKind::Pattern(ref pattern) => {
+                let mut tile_rect = usvg_rect_to_euclid_rect(&pattern.rect);
+                if let Units::ObjectBoundingBox = pattern.units {
+                    tile_rect = bounding_box_transform.transform_rect(&tile_rect);
+                }
+
+                // Maps the contents of the pattern to the space of the tile, whose origin is at
+                // the tile's upper left corner.
+                let content_transform = match pattern.view_box {
+                    Some(ref view_box) => {
+                        let view_box_rect = usvg_rect_to_euclid_rect(&view_box.rect);
+                        let viewport = RectF32::new(Point2DF32::default(), tile_rect.size());
+                        let fit = usvg_aspect_ratio_transform(view_box_rect.size(),
+                                                              viewport,
+                                                              &view_box.aspect);
+                        let origin = Transform2DF32::from_translation(&-view_box_rect.origin());
+                        concat_transforms(&fit, &origin)
+                    }
+                    None => {
+                        match pattern.content_units {
+                            Units::ObjectBoundingBox => {
+                                Transform2DF32::from_scale(&bounding_box.size())
+                            }
+                            Units::UserSpaceOnUse => Transform2DF32::default(),
+                        }
+                    }
+                };
+
+                let pattern_transform = usvg_transform_to_transform_2d(&pattern.transform);
+                (tile_rect, concat_transforms(transform, &pattern_transform), content_transform)
+            }
+            _ => return None,
+        };
+
+        // Draw the tile at roughly the resolution at which it'll be displayed.
+        let device_transform = concat_transforms(&self.render_transform, &pattern_transform);
+        let device_origin = device_transform.transform_point(&Point2DF32::default());
+        let device_axis_length = |axis| {
+            (device_transform.transform_point(&axis) - device_origin).length()
+        };
+        let device_scale = Point2DF32::new(device_axis_length(Point2DF32::new(1.0, 0.0)),
+                                           device_axis_length(Point2DF32::new(0.0, 1.0)));
+        let size = tile_rect.size().scale_xy(device_scale).ceil().to_i32();
+        let size = Point2DI32::new(clamp_pattern_tile_length(size.x()),
+                                   clamp_pattern_tile_length(size.y()));
+        let pixels_per_unit = Point2DF32::new(size.x() as f32 / tile_rect.size().x(),
+                                              size.y() as f32 / tile_rect.size().y());
+
+        // Build the contents in pixel space as a scene of their own, and draw that.
+        let pixels = if tile_rect.size().x() > 0.0 && tile_rect.size().y() > 0.0 {
+            let pixel_transform = concat_transforms(&Transform2DF32::from_scale(&pixels_per_unit),
+                                                    &content_transform);
+            let scene = mem::replace(&mut self.scene, Scene::new());
+            let gradient_cache = mem::replace(&mut self.gradient_cache, HashMap::new());
+            let render_transform = mem::replace(&mut self.render_transform,
+                                                Transform2DF32::default());
+            let graph = self.graph.take();
+            for kid in node.children() {
+                self.process_node(&kid, defs, &pixel_transform, None, Some(1.0));
+            }
+            self.graph = graph;
+            self.render_transform = render_transform;
+            self.gradient_cache = gradient_cache;
+            let content = mem::replace(&mut self.scene, scene);
+            if pattern::uses_unsupported_features(&content) {
+                self.report(node, BuildResultFlags::UNSUPPORTED_PATTERN_NODE);
+            }
+            pattern::rasterize_scene(&content, size)
+        } else {
+            // Per SVG, an empty tile disables the paint.
+            vec![ColorU::default(); size.x() as usize * size.y() as usize]
+        };
+
+        let image_transform =
+            concat_transforms(&pattern_transform,
+                              &Transform2DF32::row_major(1.0 / pixels_per_unit.x(), 0.0,
+                                                         0.0, 1.0 / pixels_per_unit.y(),
+                                                         tile_rect.origin().x(),
+                                                         tile_rect.origin().y()));
+        Some(self.scene.push_image(Image {
+            size,
+            pixels: Arc::new(pixels),
+            transform: image_transform,
+            repeat: true,
+        }))
+    }
+
+    /// Draws an `<image>` as a rectangle painted with the image. Returns `None` if the image
+    /// couldn't be loaded or decoded.
+    fn push_svg_image(&mut self,
+                      node: &Node,
+                      image: &UsvgImage,
+                      transform: &Transform2DF32,
+                      clip_path: Option<ClipPathId>,
+                      opacity: f32)
+                      -> Option<()> {
+        let data = match image.data {
+            UsvgImageData::Raw(ref data) => &data[..],
+            UsvgImageData::Path(ref path) => &self.external_images.get(path)?[..],
+        };
+        let (size, pixels) = match image.format {
+            UsvgImageFormat::PNG | UsvgImageFormat::JPEG => decode_image(data)?,
+            // TODO(pcwalton): Build SVG images as nested scenes.
+            UsvgImageFormat::SVG => return None,
+        };
+
+        // Image space -> the viewport's user space -> scene space.
+        let viewport = usvg_rect_to_euclid_rect(&image.view_box.rect);
+        let aspect = &image.view_box.aspect;
+        let placement = usvg_aspect_ratio_transform(size.to_f32(), viewport, aspect);
+        let image_rect = RectF32::new(Point2DF32::default(), size.to_f32());
+        let rect = match placement.transform_rect(&image_rect).intersection(viewport) {
+            Some(rect) => rect,
+            None => return Some(()),
+        };
+        let image_transform = concat_transforms(transform, &placement);
+
+        let pixels = Arc::new(pixels);
+        let image_id = self.scene.push_image(Image {
+            size,
+            pixels,
+            transform: image_transform,
+            repeat: false,
+        });
+        let color = ColorU { r: 255, g: 255, b: 255, a: opacity_to_alpha(opacity) };
+        let paint = self.scene.push_paint(&Paint { color, gradient: None, image: Some(image_id) });
+
+        let path = Transform2DF32PathIter::new(rect_to_segments(rect).into_iter(), transform);
+        let outline = Outline::from_segments(path);
+        self.push_object(outline,
+                         paint,
+                         &node.id(),
+                         PathObjectKind::Fill,
+                         FillRule::Winding,
+                         clip_path);
+        Some(())
+    }
+
+    /// Draws the markers that the path `node` uses at its vertices. `transform` maps the user
+    /// space of the path to scene space.
+    fn push_svg_markers(&mut self,
+                        node: &Node,
+                        path: &UsvgPath,
+                        transform: &Transform2DF32,
+                        clip_path: Option<ClipPathId>,
+                        folded_opacity: Option<f32>) {
+        let markers = match self.markers {
+            Some(ref markers) => markers.clone(),
+            None => return,
+        };
+        let uses = match markers.uses(&node.id()) {
+            Some(uses) => uses,
+            None => return,
+        };
+
+        // FIXME(pcwalton): `markerUnits="strokeWidth"` on an unstroked path should use the
+        // computed `stroke-width`, which usvg doesn't keep.
+        let stroke_width = path.stroke.as_ref().map_or(1.0, |stroke| stroke.width.value() as f32);
+
+        let vertices = path_marker_vertices(path.segments.iter().cloned(),
+                                            uses.source_vertices.as_ref().map(Vec::as_slice));
+        for (vertex_index, vertex) in vertices.iter().enumerate() {
+            let is_start = vertex_index == 0;
+            let marker_id = if is_start {
+                &uses.start
+            } else if vertex_index + 1 == vertices.len() {
+                &uses.end
+            } else {
+                &uses.mid
+            };
+            let marker_id = match *marker_id {
+                Some(ref marker_id) => marker_id,
+                None => continue,
+            };
+            let marker = match markers.get(marker_id) {
+                Some(marker) => marker,
+                None => {
+                    self.report(node, BuildResultFlags::UNSUPPORTED_MARKER_ATTR);
+                    continue;
+                }
+            };
+
+            // Per SVG, an empty viewport or view box disables rendering of the marker.
+            let viewport = RectF32::new(Point2DF32::default(), marker.size);
+            if viewport.size().x() <= 0.0 || viewport.size().y() <= 0.0 {
+                continue;
             }
-            NodeKind::RadialGradient(..) => {
-                self.result_flags.insert(BuildResultFlags::UNSUPPORTED_RADIAL_GRADIENT_NODE);
+            let view_box_transform = match marker.view_box {
+                None => Transform2DF32::default(),
+                Some(view_box) if view_box.size().x() > 0.0 && view_box.size().y() > 0.0 => {
+                    view_box_transform(view_box, viewport, &marker.align, marker.slice)
+                }
+                Some(_) => continue,
+            };
+
+            let angle = match marker.orient {
+                MarkerOrient::Auto => vertex.angle,
+                MarkerOrient::AutoStartReverse if is_start => vertex.angle + PI,
+                MarkerOrient::AutoStartReverse => vertex.angle,
+                MarkerOrient::Angle(angle) => angle,
+            };
+            let scale = match marker.units {
+                MarkerUnits::StrokeWidth => stroke_width,
+                MarkerUnits::UserSpaceOnUse => 1.0,
+            };
+
+            // Places the reference point of the viewport on the vertex.
+            let ref_point = view_box_transform.transform_point(&marker.ref_point);
+            let mut viewport_transform = Transform2DF32::from_translation(&vertex.position);
+            for step in &[Transform2DF32::from_rotation(angle),
+                          Transform2DF32::from_scale(&Point2DF32::splat(scale)),
+                          Transform2DF32::from_translation(&-ref_point)] {
+                viewport_transform = concat_transforms(&viewport_transform, step);
             }
-            NodeKind::Svg(..) => {
-                self.result_flags.insert(BuildResultFlags::UNSUPPORTED_NESTED_SVG_NODE);
+            let viewport_transform = concat_transforms(transform, &viewport_transform);
+
+            // Since `overflow` is `hidden` by default, the contents are clipped to the viewport.
+            let viewport = Transform2DF32PathIter::new(rect_to_segments(viewport).into_iter(),
+                                                       &viewport_transform);
+            let mut outline = Outline::from_segments(viewport);
+            if let Some(tolerance) = self.flatten_tolerance {
+                outline.flatten(tolerance);
             }
-            NodeKind::Text(..) => {
-                self.result_flags.insert(BuildResultFlags::UNSUPPORTED_TEXT_NODE);
+            let clip_path = Some(self.scene.push_clip_path(ClipPath {
+                outline,
+                fill_rule: FillRule::Winding,
+                parent: clip_path,
+            }));
+
+            // The marker's contents belong to the path in the graph.
+            let content_transform = concat_transforms(&viewport_transform, &view_box_transform);
+            let root = marker.contents.root();
+            let defs = collect_defs(&root);
+            let graph = self.graph.take();
+            for kid in root.children() {
+                self.process_node(&kid, &defs, &content_transform, clip_path, folded_opacity);
             }
+            self.graph = graph;
         }
     }
+
+    /// Returns the `class` names of the source element of `node`, which usvg 0.4 drops.
+    fn source_classes(&self, node: &Node) -> Vec<String> {
+        match self.source_attributes {
+            Some(ref attributes) => attributes.classes(&node.id()).to_vec(),
+            None => vec![],
+        }
+    }
+
+    // Returns true if the bounds of any two of the given objects intersect.
+    fn objects_overlap(&self, objects: Range<u32>) -> bool {
+        let objects = &self.scene.objects[(objects.start as usize)..(objects.end as usize)];
+        let mut bounds: Vec<RectF32> =
+            objects.iter().map(|object| object.outline().bounds()).collect();
+        any_rects_intersect(&mut bounds)
+    }
+
+    /// Records that `node` uses the unsupported `feature`.
+    fn report(&mut self, node: &Node, feature: BuildResultFlags) {
+        self.result_flags.insert(feature);
+        self.diagnostics.push(Diagnostic {
+            node_id: node.id().to_string(),
+            element: usvg_node_element_name(node),
+            feature,
+        });
+    }
+
+    /// Pushes an object whose outline is in scene space, flattening it if requested.
+    fn push_object(&mut self,
+                   mut outline: Outline,
+                   paint: PaintId,
+                   name: &str,
+                   kind: PathObjectKind,
+                   fill_rule: FillRule,
+                   clip_path: Option<ClipPathId>) {
+        if let Some(tolerance) = self.flatten_tolerance {
+            outline.flatten(tolerance);
+        }
+        self.scene.bounds = self.scene.bounds.union_rect(outline.bounds());
+        self.scene.objects.push(PathObject::new(outline,
+                                                paint,
+                                                name.to_string(),
+                                                kind,
+                                                fill_rule,
+                                                clip_path));
+    }
+
+    /// Pushes the `<clipPath>` with the given ID, as applied to `group`, on top of `parent`.
+    fn push_svg_clip_path(&mut self,
+                          id: &str,
+                          defs: &Defs,
+                          group: &Node,
+                          transform: &Transform2DF32,
+                          parent: Option<ClipPathId>)
+                          -> Option<ClipPathId> {
+        let clip_path_node = defs.get(id)?;
+        let (object_bounding_box, clip_path_transform, clip_path_clip_path) =
+            match *clip_path_node.borrow() {
+                NodeKind::ClipPath(ref clip_path) => {
+                    let object_bounding_box = match clip_path.units {
+                        Units::ObjectBoundingBox => true,
+                        Units::UserSpaceOnUse => false,
+                    };
+                    (object_bounding_box,
+                     usvg_transform_to_transform_2d(&clip_path.transform),
+                     clip_path.clip_path.clone())
+                }
+                _ => return None,
+            };
+
+        // A `clip-path` on the `<clipPath>` itself narrows the stack further.
+        let parent = match clip_path_clip_path {
+            None => parent,
+            Some(ref id) => Some(self.push_svg_clip_path(id, defs, group, transform, parent)?),
+        };
+
+        let mut clip_path_transform = clip_path_transform;
+        if object_bounding_box {
+            let mut bounding_box = None;
+            union_user_space_bounds(group, &Transform2DF32::default(), &mut bounding_box);
+            let bounding_box_transform =
+                object_bounding_box_transform(bounding_box.unwrap_or_default());
+            clip_path_transform = concat_transforms(&bounding_box_transform,
+                                                    &clip_path_transform);
+        }
+        let clip_path_transform = concat_transforms(transform, &clip_path_transform);
+
+        // FIXME(pcwalton): Concatenating the children only gives their union if they don't wind
+        // against one another, and don't overlap at all under the even-odd rule. Mixed rules and
+        // overlapping even-odd children are reported.
+        let mut segments = vec![];
+        let mut kid_bounds = vec![];
+        let mut fill_rule = None;
+        let mut mixed_fill_rules = false;
+        for kid in clip_path_node.children() {
+            if let NodeKind::Path(ref path) = *kid.borrow() {
+                if path.visibility != Visibility::Visible {
+                    continue;
+                }
+
+                // usvg stores `clip-rule` as the fill rule of each child.
+                let kid_fill_rule = path.fill.as_ref().map_or(FillRule::Winding, |fill| {
+                    usvg_fill_rule_to_fill_rule(fill.rule)
+                });
+                fill_rule = match fill_rule {
+                    None => Some(kid_fill_rule),
+                    Some(fill_rule) if fill_rule == kid_fill_rule => Some(fill_rule),
+                    Some(_) => {
+                        mixed_fill_rules = true;
+                        Some(FillRule::Winding)
+                    }
+                };
+
+                let kid_transform = usvg_transform_to_transform_2d(&kid.transform());
+                let kid_transform = concat_transforms(&clip_path_transform, &kid_transform);
+                let path = UsvgPathToSegments::new(path.segments.iter().cloned());
+                let kid_segments: Vec<_> = Transform2DF32PathIter::new(path, &kid_transform)
+                    .collect();
+                kid_bounds.push(Outline::from_segments(kid_segments.iter().cloned()).bounds());
+                segments.extend(kid_segments);
+            }
+        }
+
+        if mixed_fill_rules ||
+                (fill_rule == Some(FillRule::EvenOdd) && any_rects_intersect(&mut kid_bounds)) {
+            self.report(group, BuildResultFlags::UNSUPPORTED_CLIP_PATH_ATTR);
+        }
+
+        let mut outline = Outline::from_segments(segments.into_iter());
+        if let Some(tolerance) = self.flatten_tolerance {
+            outline.flatten(tolerance);
+        }
+        let fill_rule = fill_rule.unwrap_or_default();
+        Some(self.scene.push_clip_path(ClipPath { outline, fill_rule, parent }))
+    }
+
+    /// Pushes the contents of the `<mask>` node `mask_node`, as applied to `group`, into the
+    /// scene. They must immediately follow the contents of `group`. Returns `None` if `mask_node`
+    /// isn't a mask.
+    fn push_svg_mask(&mut self,
+                     mask_node: &Node,
+                     defs: &Defs,
+                     group: &Node,
+                     transform: &Transform2DF32,
+                     clip_path: Option<ClipPathId>)
+                     -> Option<Mask> {
+        let (units, content_units, rect, has_mask) = match *mask_node.borrow() {
+            NodeKind::Mask(ref mask) => {
+                (mask.units, mask.content_units, mask.rect, mask.mask.is_some())
+            }
+            _ => return None,
+        };
+
+        // TODO(pcwalton): Masks on masks need layers to nest.
+        if has_mask {
+            self.report(mask_node, BuildResultFlags::UNSUPPORTED_MASK_ATTR);
+        }
+
+        let mut bounding_box = None;
+        union_user_space_bounds(group, &Transform2DF32::default(), &mut bounding_box);
+        let bounding_box_transform =
+            object_bounding_box_transform(bounding_box.unwrap_or_default());
+
+        // Nothing outside the mask region is drawn, so clip the contents of the mask to it.
+        let mut region = usvg_rect_to_euclid_rect(&rect);
+        if let Units::ObjectBoundingBox = units {
+            region = bounding_box_transform.transform_rect(&region);
+        }
+        let region = Transform2DF32PathIter::new(rect_to_segments(region).into_iter(), transform);
+        let clip_path = self.scene.push_clip_path(ClipPath {
+            outline: Outline::from_segments(region),
+            fill_rule: FillRule::Winding,
+            parent: clip_path,
+        });
+
+        let content_transform = match content_units {
+            Units::ObjectBoundingBox => concat_transforms(transform, &bounding_box_transform),
+            Units::UserSpaceOnUse => *transform,
+        };
+
+        // The contents of masks belong to the masked group in the graph.
+        let first_object_index = self.scene.objects.len() as u32;
+        let graph = self.graph.take();
+        for kid in mask_node.children() {
+            self.process_node(&kid, defs, &content_transform, Some(clip_path), Some(1.0));
+        }
+        self.graph = graph;
+        let objects = first_object_index..(self.scene.objects.len() as u32);
+
+        // FIXME(pcwalton): usvg 0.4 doesn't expose `mask-type`, so every mask is a luminance mask.
+        Some(Mask { objects, kind: MaskKind::Luminance })
+    }
+}
+
+/// Unions the bounds of the paths under `node` into `bounds`, where `transform` maps the user
+/// space of `node` to the space `bounds` is in.
+fn union_user_space_bounds(node: &Node,
+                           transform: &Transform2DF32,
+                           bounds: &mut Option<RectF32>) {
+    for kid in node.children() {
+        let kid_transform = usvg_transform_to_transform_2d(&kid.transform());
+        let kid_transform = concat_transforms(transform, &kid_transform);
+        match *kid.borrow() {
+            NodeKind::Path(ref path) => {
+                let path = UsvgPathToSegments::new(path.segments.iter().cloned());
+                let path = Transform2DF32PathIter::new(path, &kid_transform);
+                let path_bounds = Outline::from_segments(path).bounds();
+                *bounds = Some(match *bounds {
+                    None => path_bounds,
+                    Some(bounds) => bounds.union_rect(path_bounds),
+                });
+            }
+            NodeKind::Group(..) => union_user_space_bounds(&kid, &kid_transform, bounds),
+            NodeKind::Svg(ref svg) => {
+                if let Some(view_box_transform) = usvg_nested_svg_transform(svg) {
+                    let kid_transform = concat_transforms(&kid_transform, &view_box_transform);
+                    union_user_space_bounds(&kid, &kid_transform, bounds);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn load_external_images(root: &Node, resources: &dyn ResourceLoader)
+                        -> HashMap<PathBuf, Vec<u8>> {
+    let mut images = HashMap::new();
+    for node in root.descendants() {
+        if let NodeKind::Image(ref image) = *node.borrow() {
+            if let UsvgImageData::Path(ref path) = image.data {
+                if images.contains_key(path) {
+                    continue;
+                }
+                // Missing images are reported as unsupported when they're drawn.
+                if let Ok(data) = path.to_str().ok_or(()).and_then(|virtual_path| {
+                    resources.slurp(virtual_path).map_err(|_| ())
+                }) {
+                    images.insert(path.clone(), data);
+                }
+            }
+        }
+    }
+    images
+}
+
+/// Decodes a PNG or JPEG image into non-premultiplied RGBA pixels.
+fn decode_image(data: &[u8]) -> Option<(Point2DI32, Vec<ColorU>)> {
+    let image = image::load_from_memory(data).ok()?.to_rgba();
+    let (width, height) = image.dimensions();
+    if width == 0 || height == 0 {
+        return None;
+    }
+    let pixels = image.pixels().map(|pixel| {
+        ColorU { r: pixel[0], g: pixel[1], b: pixel[2], a: pixel[3] }
+    }).collect();
+    Some((Point2DI32::new(width as i32, height as i32), pixels))
+}
+
+fn rect_to_segments(rect: RectF32) -> Vec<Segment> {
+    let points = [rect.origin(), rect.upper_right(), rect.lower_right(), rect.lower_left()];
+    let mut segments: Vec<_> = (0..4).map(|index| {
+        Segment::line(&LineSegmentF32::new(&points[index], &points[(index + 1) % 4]))
+    }).collect();
+    segments[0].flags.insert(SegmentFlags::FIRST_IN_SUBPATH);
+    segments[3].flags.insert(SegmentFlags::CLOSES_SUBPATH);
+    segments
+}
+
+fn collect_defs(root: &Node) -> Defs {
+    let mut defs = Defs::new();
+    for kid in root.children() {
+        if let NodeKind::Defs { .. } = *kid.borrow() {
+            for def in kid.children() {
+                defs.insert(def.id().to_string(), def.clone());
+            }
+        }
+    }
+    defs
 }
 
 impl Display for BuildResultFlags {
@@ -226,27 +1201,100 @@ impl Display for BuildResultFlags {
             "filter attribute",
             "mask attribute",
             "opacity attribute",
+            "marker attribute",
         ];
     }
 }
 
-trait PaintExt {
-    fn from_svg_paint(svg_paint: &UsvgPaint, result_flags: &mut BuildResultFlags) -> Self;
+impl Display for Diagnostic {
+    fn fmt(&self, formatter: &mut Formatter) -> FormatResult {
+        write!(formatter, "{} on <{}>", self.feature, self.element)?;
+        if !self.node_id.is_empty() {
+            write!(formatter, " #{}", self.node_id)?;
+        }
+        formatter.write_str(" unsupported")
+    }
 }
 
-impl PaintExt for Paint {
-    #[inline]
-    fn from_svg_paint(svg_paint: &UsvgPaint, result_flags: &mut BuildResultFlags) -> Paint {
-        Paint {
-            color: match *svg_paint {
-                UsvgPaint::Color(color) => ColorU::from_svg_color(color),
-                UsvgPaint::Link(_) => {
-                    // TODO(pcwalton)
-                    result_flags.insert(BuildResultFlags::UNSUPPORTED_LINK_PAINT);
-                    ColorU::black()
-                }
+impl Display for SvgError {
+    fn fmt(&self, formatter: &mut Formatter) -> FormatResult {
+        match *self {
+            SvgError::Parse(ref error) => write!(formatter, "Failed to parse the SVG: {}", error),
+        }
+    }
+}
+
+impl Error for SvgError {}
+
+trait GradientExt: Sized {
+    fn from_svg_node<F>(node: &Node, transform: &Transform2DF32, get_bounding_box: F)
+                        -> Option<Self>
+                        where F: FnOnce() -> RectF32;
+    fn from_svg_base_gradient<F>(geometry: GradientGeometry,
+                                 base: &BaseGradient,
+                                 transform: &Transform2DF32,
+                                 get_bounding_box: F)
+                                 -> Self
+                                 where F: FnOnce() -> RectF32;
+}
+
+impl GradientExt for Gradient {
+    fn from_svg_node<F>(node: &Node, transform: &Transform2DF32, get_bounding_box: F)
+                        -> Option<Gradient>
+                        where F: FnOnce() -> RectF32 {
+        match *node.borrow() {
+            NodeKind::LinearGradient(ref gradient) => {
+                let geometry = GradientGeometry::Linear {
+                    from: Point2DF32::new(gradient.x1 as f32, gradient.y1 as f32),
+                    to: Point2DF32::new(gradient.x2 as f32, gradient.y2 as f32),
+                };
+                Some(Gradient::from_svg_base_gradient(geometry,
+                                                      &gradient.base,
+                                                      transform,
+                                                      get_bounding_box))
+            }
+            NodeKind::RadialGradient(ref gradient) => {
+                let geometry = GradientGeometry::Radial {
+                    center: Point2DF32::new(gradient.cx as f32, gradient.cy as f32),
+                    focal: Point2DF32::new(gradient.fx as f32, gradient.fy as f32),
+                    radius: gradient.r as f32,
+                };
+                Some(Gradient::from_svg_base_gradient(geometry,
+                                                      &gradient.base,
+                                                      transform,
+                                                      get_bounding_box))
             }
+            _ => None,
+        }
+    }
+
+    fn from_svg_base_gradient<F>(geometry: GradientGeometry,
+                                 base: &BaseGradient,
+                                 transform: &Transform2DF32,
+                                 get_bounding_box: F)
+                                 -> Gradient
+                                 where F: FnOnce() -> RectF32 {
+        // Gradient space -> (bounding box space ->) user space of the referencing element ->
+        // scene space.
+        let mut gradient_transform = usvg_transform_to_transform_2d(&base.transform);
+        if let Units::ObjectBoundingBox = base.units {
+            let bounding_box_transform = object_bounding_box_transform(get_bounding_box());
+            gradient_transform = concat_transforms(&bounding_box_transform, &gradient_transform);
         }
+        let transform = concat_transforms(transform, &gradient_transform);
+
+        let stops = base.stops.iter().map(|stop| {
+            let color = ColorU::from_svg_color(stop.color, stop.opacity.value() as f32);
+            GradientStop { offset: stop.offset.value() as f32, color }
+        }).collect();
+
+        let spread = match base.spread_method {
+            UsvgSpreadMethod::Pad => SpreadMethod::Pad,
+            UsvgSpreadMethod::Reflect => SpreadMethod::Reflect,
+            UsvgSpreadMethod::Repeat => SpreadMethod::Repeat,
+        };
+
+        Gradient { geometry, stops, spread, transform }
     }
 }
 
@@ -257,11 +1305,176 @@ fn usvg_rect_to_euclid_rect(rect: &UsvgRect) -> RectF32 {
     )
 }
 
+/// Returns the transform that maps the unit square to `bounding_box`, for `objectBoundingBox`
+/// units.
+fn object_bounding_box_transform(bounding_box: RectF32) -> Transform2DF32 {
+    Transform2DF32::row_major(bounding_box.size().x(), 0.0,
+                              0.0, bounding_box.size().y(),
+                              bounding_box.origin().x(), bounding_box.origin().y())
+}
+
+fn clamp_pattern_tile_length(length: i32) -> i32 {
+    cmp::max(cmp::min(length, MAX_PATTERN_TILE_LENGTH), 1)
+}
+
+// usvg positions nested `<svg>` elements with the node transform, so the viewport of one starts
+// at the origin of its user space.
+fn usvg_nested_svg_viewport(svg: &UsvgSvg) -> RectF32 {
+    RectF32::new(Point2DF32::default(),
+                 Point2DF32::new(svg.size.width as f32, svg.size.height as f32))
+}
+
+/// Returns the transform from the view box of a nested `<svg>` to its viewport, or `None` if
+/// either is empty, in which case the element isn't rendered.
+fn usvg_nested_svg_transform(svg: &UsvgSvg) -> Option<Transform2DF32> {
+    let viewport = usvg_nested_svg_viewport(svg);
+    let view_box = usvg_rect_to_euclid_rect(&svg.view_box.rect);
+    if viewport.size().x() <= 0.0 || viewport.size().y() <= 0.0 ||
+            view_box.size().x() <= 0.0 || view_box.size().y() <= 0.0 {
+        return None;
+    }
+    let aspect = &svg.view_box.aspect;
+    Some(view_box_transform(view_box, viewport, &aspect.align, aspect.slice))
+}
+
+/// Returns the transform that maps `view_box` onto `viewport` as `preserveAspectRatio` directs.
+fn view_box_transform(view_box: RectF32, viewport: RectF32, align: &Align, slice: bool)
+                      -> Transform2DF32 {
+    let fit = align_transform(view_box.size(), viewport, align, slice);
+    concat_transforms(&fit, &Transform2DF32::from_translation(&-view_box.origin()))
+}
+
+/// Returns the transform that fits content of the given size into `viewport` as
+/// `preserveAspectRatio` directs.
+fn usvg_aspect_ratio_transform(size: Point2DF32, viewport: RectF32, aspect: &AspectRatio)
+                               -> Transform2DF32 {
+    align_transform(size, viewport, &aspect.align, aspect.slice)
+}
+
+/// Returns the transform that fits content of the given size into `viewport`, aligned per
+/// `align`, and covering the viewport instead of fitting inside it if `slice` is set.
+fn align_transform(size: Point2DF32, viewport: RectF32, align: &Align, slice: bool)
+                   -> Transform2DF32 {
+    let scale = Point2DF32::new(viewport.size().x() / size.x(), viewport.size().y() / size.y());
+    let alignment = match *align {
+        Align::None => {
+            return Transform2DF32::row_major(scale.x(), 0.0,
+                                             0.0, scale.y(),
+                                             viewport.origin().x(), viewport.origin().y());
+        }
+        Align::XMinYMin => Point2DF32::new(0.0, 0.0),
+        Align::XMidYMin => Point2DF32::new(0.5, 0.0),
+        Align::XMaxYMin => Point2DF32::new(1.0, 0.0),
+        Align::XMinYMid => Point2DF32::new(0.0, 0.5),
+        Align::XMidYMid => Point2DF32::new(0.5, 0.5),
+        Align::XMaxYMid => Point2DF32::new(1.0, 0.5),
+        Align::XMinYMax => Point2DF32::new(0.0, 1.0),
+        Align::XMidYMax => Point2DF32::new(0.5, 1.0),
+        Align::XMaxYMax => Point2DF32::new(1.0, 1.0),
+    };
+
+    // `meet` fits the content inside the viewport; `slice` covers the viewport with it.
+    let scale = if slice {
+        f32::max(scale.x(), scale.y())
+    } else {
+        f32::min(scale.x(), scale.y())
+    };
+    let origin = viewport.origin() + (viewport.size() - size.scale(scale)) * alignment;
+    Transform2DF32::row_major(scale, 0.0, 0.0, scale, origin.x(), origin.y())
+}
+
+/// Returns the exact bits of a transform, for use as a hash key.
+fn transform_key(transform: &Transform2DF32) -> [u32; 6] {
+    let origin = transform.transform_point(&Point2DF32::default());
+    [
+        transform.m11().to_bits(), transform.m12().to_bits(),
+        transform.m21().to_bits(), transform.m22().to_bits(),
+        origin.x().to_bits(), origin.y().to_bits(),
+    ]
+}
+
+/// Returns a transform that applies `inner`, then `outer`.
+///
+/// Built from the images of the basis vectors so that it agrees exactly with
+/// `Transform2DF32::transform_point`.
+fn concat_transforms(outer: &Transform2DF32, inner: &Transform2DF32) -> Transform2DF32 {
+    let map = |point| outer.transform_point(&inner.transform_point(&point));
+    let origin = map(Point2DF32::default());
+    let x_axis = map(Point2DF32::new(1.0, 0.0)) - origin;
+    let y_axis = map(Point2DF32::new(0.0, 1.0)) - origin;
+    Transform2DF32::row_major(x_axis.x(), y_axis.x(),
+                              x_axis.y(), y_axis.y(),
+                              origin.x(), origin.y())
+}
+
+fn usvg_stroke_to_stroke_style(stroke: &UsvgStroke) -> StrokeStyle {
+    StrokeStyle {
+        line_width: stroke.width.value() as f32,
+        line_cap: match stroke.linecap {
+            UsvgLineCap::Butt => LineCap::Butt,
+            UsvgLineCap::Round => LineCap::Round,
+            UsvgLineCap::Square => LineCap::Square,
+        },
+        line_join: match stroke.linejoin {
+            UsvgLineJoin::Miter => LineJoin::Miter,
+            UsvgLineJoin::Round => LineJoin::Round,
+            UsvgLineJoin::Bevel => LineJoin::Bevel,
+        },
+        miter_limit: stroke.miterlimit as f32,
+        dash_array: match stroke.dasharray {
+            None => vec![],
+            Some(ref dash_array) => dash_array.iter().map(|&length| length as f32).collect(),
+        },
+        dash_offset: stroke.dashoffset as f32,
+    }
+}
+
+fn usvg_node_element_name(node: &Node) -> &'static str {
+    match *node.borrow() {
+        NodeKind::Svg(..) => "svg",
+        NodeKind::Defs { .. } => "defs",
+        NodeKind::LinearGradient(..) => "linearGradient",
+        NodeKind::RadialGradient(..) => "radialGradient",
+        NodeKind::ClipPath(..) => "clipPath",
+        NodeKind::Mask(..) => "mask",
+        NodeKind::Pattern(..) => "pattern",
+        NodeKind::Filter(..) => "filter",
+        NodeKind::Group(..) => "g",
+        NodeKind::Path(..) => "path",
+        NodeKind::Text(..) => "text",
+        NodeKind::Image(..) => "image",
+    }
+}
+
+// Sorts the rects and returns true if any two of them intersect.
+fn any_rects_intersect(rects: &mut [RectF32]) -> bool {
+    rects.sort_by(|a, b| a.min_x().partial_cmp(&b.min_x()).unwrap_or(Ordering::Equal));
+    for (index, rect) in rects.iter().enumerate() {
+        for other in &rects[(index + 1)..] {
+            if other.min_x() >= rect.max_x() {
+                break;
+            }
+            if rect.intersects(*other) {
+                return true;
+            }
+        }
+    }
+    false
+}
+
+fn usvg_fill_rule_to_fill_rule(fill_rule: UsvgFillRule) -> FillRule {
+    match fill_rule {
+        UsvgFillRule::NonZero => FillRule::Winding,
+        UsvgFillRule::EvenOdd => FillRule::EvenOdd,
+    }
+}
+
+// usvg's `(a, b, c, d, e, f)` are in the SVG sense: `x' = ax + cy + e`, `y' = bx + dy + f`.
 fn usvg_transform_to_transform_2d(transform: &UsvgTransform) -> Transform2DF32 {
     Transform2DF32::row_major(
         transform.a as f32,
-        transform.b as f32,
         transform.c as f32,
+        transform.b as f32,
         transform.d as f32,
         transform.e as f32,
         transform.f as f32,
@@ -355,17 +1568,267 @@ where
 }
 
 trait ColorUExt {
-    fn from_svg_color(svg_color: SvgColor) -> Self;
+    fn from_svg_color(svg_color: SvgColor, opacity: f32) -> Self;
 }
 
 impl ColorUExt for ColorU {
     #[inline]
-    fn from_svg_color(svg_color: SvgColor) -> ColorU {
+    fn from_svg_color(svg_color: SvgColor, opacity: f32) -> ColorU {
         ColorU {
             r: svg_color.red,
             g: svg_color.green,
             b: svg_color.blue,
-            a: 255,
+            a: opacity_to_alpha(opacity),
+        }
+    }
+}
+
+#[inline]
+fn opacity_to_alpha(opacity: f32) -> u8 {
+    (f32::max(f32::min(opacity, 1.0), 0.0) * 255.0).round() as u8
+}
+
+#[cfg(test)]
+mod test {
+    use super::{BuildOptions, BuildResultFlags, BuiltSVG, Diagnostic, SvgError};
+    use pathfinder_renderer::scene::{ClipPathId, MaskKind};
+    use pathfinder_geometry::basic::point::Point2DF32;
+
+    fn build(svg: &str) -> BuiltSVG {
+        BuiltSVG::from_data(svg.as_bytes(), &BuildOptions::default()).unwrap()
+    }
+
+    fn object_points(built_svg: &BuiltSVG, object_index: usize) -> Vec<Point2DF32> {
+        let outline = built_svg.scene.objects[object_index].outline();
+        outline.contours
+               .iter()
+               .flat_map(|contour| (0..contour.len()).map(move |i| contour.position_of(i)))
+               .collect()
+    }
+
+    fn assert_has_point(points: &[Point2DF32], expected: Point2DF32) {
+        assert!(points.iter().any(|point| (*point - expected).length() < 0.001),
+                "{:?} not in {:?}",
+                expected,
+                points);
+    }
+
+    #[test]
+    fn test_rotated_and_skewed_outlines() {
+        let built_svg = build(r#"<svg xmlns="http://www.w3.org/2000/svg" width="100" height="100">
+            <rect width="10" height="10" transform="rotate(30)"/>
+            <rect width="10" height="10" transform="skewX(45)"/>
+        </svg>"#);
+
+        let rotated = object_points(&built_svg, 0);
+        assert_has_point(&rotated, Point2DF32::new(8.660254, 5.0));
+        assert_has_point(&rotated, Point2DF32::new(3.660254, 13.660254));
+        assert_has_point(&rotated, Point2DF32::new(-5.0, 8.660254));
+
+        let skewed = object_points(&built_svg, 1);
+        assert_has_point(&skewed, Point2DF32::new(10.0, 0.0));
+        assert_has_point(&skewed, Point2DF32::new(20.0, 10.0));
+        assert_has_point(&skewed, Point2DF32::new(10.0, 10.0));
+    }
+
+    #[test]
+    fn test_matrix_transform_entries() {
+        let built_svg = build(r#"<svg xmlns="http://www.w3.org/2000/svg" width="100" height="100">
+            <rect width="1" height="1" transform="matrix(1 2 3 4 5 6)"/>
+        </svg>"#);
+        let points = object_points(&built_svg, 0);
+        assert_has_point(&points, Point2DF32::new(5.0, 6.0));
+        assert_has_point(&points, Point2DF32::new(6.0, 8.0));
+        assert_has_point(&points, Point2DF32::new(8.0, 10.0));
+        assert_has_point(&points, Point2DF32::new(9.0, 12.0));
+    }
+
+    #[test]
+    fn test_gradients_are_shared_between_uses() {
+        let built_svg = build(r#"<svg xmlns="http://www.w3.org/2000/svg" width="100" height="100">
+            <linearGradient id="g" gradientUnits="userSpaceOnUse" x2="10">
+                <stop offset="0" stop-color="red"/>
+                <stop offset="1" stop-color="blue"/>
+            </linearGradient>
+            <rect width="10" height="10" fill="url(#g)"/>
+            <rect y="20" width="10" height="10" fill="url(#g)"/>
+            <rect y="40" width="10" height="10" fill="url(#g)" transform="scale(2)"/>
+        </svg>"#);
+        assert_eq!(built_svg.scene.gradients.len(), 2);
+    }
+
+    #[test]
+    fn test_folded_opacity_over_overlapping_content_is_reported() {
+        // The outer groups have two children, so that usvg doesn't merge them into the inner
+        // ones.
+        let built_svg = build(r#"<svg xmlns="http://www.w3.org/2000/svg" width="100" height="100">
+            <g opacity="0.5">
+                <g id="apart" opacity="0.5">
+                    <rect width="10" height="10"/>
+                    <rect x="10" width="10" height="10"/>
+                </g>
+                <rect y="50" width="10" height="10"/>
+            </g>
+        </svg>"#);
+        assert!(!built_svg.result_flags.contains(BuildResultFlags::UNSUPPORTED_OPACITY_ATTR));
+
+        let built_svg = build(r#"<svg xmlns="http://www.w3.org/2000/svg" width="100" height="100">
+            <g opacity="0.5">
+                <g id="overlapping" opacity="0.5">
+                    <rect width="10" height="10"/>
+                    <rect x="5" width="10" height="10"/>
+                </g>
+                <rect y="50" width="10" height="10"/>
+            </g>
+        </svg>"#);
+        assert!(built_svg.result_flags.contains(BuildResultFlags::UNSUPPORTED_OPACITY_ATTR));
+        assert_eq!(built_svg.diagnostics[0].node_id, "overlapping");
+    }
+
+    #[test]
+    fn test_non_scaling_stroke_keeps_its_width() {
+        let built_svg = build(r#"<svg xmlns="http://www.w3.org/2000/svg" width="100" height="100">
+            <path id="scaling" d="M0 5 H10" transform="scale(1 4)" fill="none" stroke="black"
+                  stroke-width="2"/>
+            <path id="non-scaling" d="M0 5 H10" transform="scale(1 4)" fill="none"
+                  stroke="black" stroke-width="2" vector-effect="non-scaling-stroke"/>
+        </svg>"#);
+        assert_eq!(built_svg.scene.objects.len(), 2);
+        let scaling_bounds = built_svg.scene.objects[0].outline().bounds();
+        assert!((scaling_bounds.size().y() - 8.0).abs() < 0.001);
+        let non_scaling_bounds = built_svg.scene.objects[1].outline().bounds();
+        assert!((non_scaling_bounds.size().y() - 2.0).abs() < 0.001);
+        assert!((non_scaling_bounds.min_y() - 19.0).abs() < 0.001);
+    }
+
+    #[test]
+    fn test_unsupported_clip_rules_are_reported() {
+        let built_svg = build(r#"<svg xmlns="http://www.w3.org/2000/svg" width="100" height="100">
+            <clipPath id="c">
+                <rect width="10" height="10"/>
+                <rect x="20" width="10" height="10"/>
+            </clipPath>
+            <g clip-path="url(#c)"><rect width="100" height="100"/></g>
+        </svg>"#);
+        assert!(!built_svg.result_flags.contains(BuildResultFlags::UNSUPPORTED_CLIP_PATH_ATTR));
+
+        let built_svg = build(r#"<svg xmlns="http://www.w3.org/2000/svg" width="100" height="100">
+            <clipPath id="c">
+                <rect width="10" height="10" clip-rule="evenodd"/>
+                <rect x="20" width="10" height="10"/>
+            </clipPath>
+            <g clip-path="url(#c)"><rect width="100" height="100"/></g>
+        </svg>"#);
+        assert!(built_svg.result_flags.contains(BuildResultFlags::UNSUPPORTED_CLIP_PATH_ATTR));
+
+        let built_svg = build(r#"<svg xmlns="http://www.w3.org/2000/svg" width="100" height="100">
+            <clipPath id="c" clip-rule="evenodd">
+                <rect width="10" height="10"/>
+                <rect x="5" width="10" height="10"/>
+            </clipPath>
+            <g clip-path="url(#c)"><rect width="100" height="100"/></g>
+        </svg>"#);
+        assert!(built_svg.result_flags.contains(BuildResultFlags::UNSUPPORTED_CLIP_PATH_ATTR));
+    }
+
+    #[test]
+    fn test_mask_becomes_luminance_layer() {
+        let built_svg = build(r#"<svg xmlns="http://www.w3.org/2000/svg" width="100" height="100">
+            <mask id="m"><rect width="10" height="10" fill="white"/></mask>
+            <g mask="url(#m)" opacity="0.5"><rect width="20" height="20"/></g>
+        </svg>"#);
+        assert_eq!(built_svg.scene.layers.len(), 1);
+        let layer = &built_svg.scene.layers[0];
+        assert_eq!(layer.objects, 0..1);
+        assert_eq!(layer.opacity, 0.5);
+        let mask = layer.mask.as_ref().unwrap();
+        assert_eq!(mask.kind, MaskKind::Luminance);
+        assert_eq!(mask.objects, 1..2);
+        // The mask's contents are clipped to the mask region.
+        assert!(built_svg.scene.objects[1].clip_path().is_some());
+    }
+
+    #[test]
+    fn test_pattern_with_gradient_is_reported() {
+        let built_svg = build(r#"<svg xmlns="http://www.w3.org/2000/svg" width="100" height="100">
+            <linearGradient id="g">
+                <stop offset="0" stop-color="red"/>
+                <stop offset="1" stop-color="blue"/>
+            </linearGradient>
+            <pattern id="p" width="10" height="10" patternUnits="userSpaceOnUse">
+                <rect width="5" height="5" fill="url(#g)"/>
+            </pattern>
+            <rect width="100" height="100" fill="url(#p)"/>
+        </svg>"#);
+        assert!(built_svg.result_flags.contains(BuildResultFlags::UNSUPPORTED_PATTERN_NODE));
+        assert_eq!(built_svg.diagnostics[0].node_id, "p");
+        assert_eq!(built_svg.scene.images.len(), 1);
+    }
+
+    #[test]
+    fn test_nested_svg_clips_to_viewport() {
+        // The rect sets `x` and `y`, since usvg 0.4 otherwise takes them from the nested `<svg>`.
+        let built_svg = build(r#"<svg xmlns="http://www.w3.org/2000/svg" width="100" height="100">
+            <svg x="10" y="10" width="20" height="20" viewBox="0 0 10 10">
+                <rect x="0" y="0" width="100" height="100"/>
+            </svg>
+        </svg>"#);
+        assert_eq!(built_svg.scene.clip_paths.len(), 1);
+        let clip_bounds = built_svg.scene.clip_paths[0].outline.bounds();
+        assert_has_point(&[clip_bounds.origin(), clip_bounds.lower_right()],
+                         Point2DF32::new(10.0, 10.0));
+        assert_has_point(&[clip_bounds.origin(), clip_bounds.lower_right()],
+                         Point2DF32::new(30.0, 30.0));
+
+        // The contents are scaled from the view box to the viewport.
+        let object = &built_svg.scene.objects[0];
+        assert_eq!(object.clip_path(), Some(ClipPathId(0)));
+        let object_bounds = object.outline().bounds();
+        assert_has_point(&[object_bounds.lower_right()], Point2DF32::new(210.0, 210.0));
+    }
+
+    #[test]
+    fn test_diagnostic_names_element() {
+        let built_svg = build(r#"<svg xmlns="http://www.w3.org/2000/svg" width="100" height="100">
+            <filter id="f"><feGaussianBlur stdDeviation="2"/></filter>
+            <g id="blurred" filter="url(#f)"><rect width="10" height="10"/></g>
+        </svg>"#);
+        let expected = Diagnostic {
+            node_id: "blurred".to_string(),
+            element: "g",
+            feature: BuildResultFlags::UNSUPPORTED_FILTER_ATTR,
+        };
+        assert!(built_svg.diagnostics.contains(&expected), "{:?}", built_svg.diagnostics);
+        assert!(built_svg.result_flags.contains(BuildResultFlags::UNSUPPORTED_FILTER_ATTR));
+    }
+
+    #[test]
+    fn test_graph_classes_and_nested_svg_transform() {
+        let options = BuildOptions { build_graph: true, ..BuildOptions::default() };
+        let svg = r#"<svg xmlns="http://www.w3.org/2000/svg" width="100" height="100">
+            <svg id="inner" x="10" y="10" width="20" height="20" viewBox="0 0 10 10">
+                <rect id="r" class="a b" width="5" height="5"/>
+            </svg>
+        </svg>"#;
+        let built_svg = BuiltSVG::from_data(svg.as_bytes(), &options).unwrap();
+        let graph = built_svg.graph.as_ref().unwrap();
+        let rect = graph.find("r").unwrap();
+        assert_eq!(graph.get(rect).classes, vec!["a".to_string(), "b".to_string()]);
+
+        let world_transform = graph.world_transform(rect);
+        assert_has_point(&[world_transform.transform_point(&Point2DF32::new(5.0, 5.0))],
+                         Point2DF32::new(20.0, 20.0));
+    }
+
+    #[test]
+    fn test_malformed_data_is_an_error() {
+        let mismatched: &[u8] = b"<svg xmlns=\"http://www.w3.org/2000/svg\"></g>";
+        let not_utf8: &[u8] = b"\xff\xfe";
+        for data in &[mismatched, not_utf8] {
+            match BuiltSVG::from_data(data, &BuildOptions::default()) {
+                Err(SvgError::Parse(_)) => {}
+                Ok(_) => panic!("malformed data was accepted"),
+            }
         }
     }
 }
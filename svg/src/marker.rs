@@ -0,0 +1,635 @@
+// pathfinder/svg/src/marker.rs
+//
+// Copyright © 2019 The Pathfinder Project Developers.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Reads `<marker>` elements and the `marker-start`, `marker-mid`, and `marker-end` properties.
+//!
+//! usvg 0.4 drops markers while simplifying the tree, so they're read from the source document.
+//! The contents of each marker are parsed by usvg as a document of their own, along with the
+//! `<defs>` and `<style>` elements of the source, so that they can be built like any other
+//! nodes. Marker properties are matched to the paths of the tree by element ID.
+
+use pathfinder_geometry::basic::point::Point2DF32;
+use pathfinder_geometry::basic::rect::RectF32;
+use roxmltree::{Document, Node as XmlNode};
+use std::collections::HashMap;
+use std::fmt::{self, Debug, Formatter};
+use std::str;
+use usvg::{Align, Options as UsvgOptions, PathSegment as UsvgPathSegment, Tree};
+
+const XLINK_NAMESPACE: &str = "http://www.w3.org/1999/xlink";
+
+/// The markers of a document and the elements that use them.
+// FIXME(pcwalton): Markers on elements without IDs, and marker properties set in style sheets,
+// aren't found.
+#[derive(Default)]
+pub struct Markers {
+    definitions: HashMap<String, Marker>,
+    // By the ID of the element that uses them.
+    uses: HashMap<String, MarkerUses>,
+}
+
+pub(crate) struct Marker {
+    pub(crate) contents: Tree,
+    // In the coordinate system of the contents.
+    pub(crate) ref_point: Point2DF32,
+    // The width and height of the viewport.
+    pub(crate) size: Point2DF32,
+    pub(crate) units: MarkerUnits,
+    pub(crate) orient: MarkerOrient,
+    pub(crate) view_box: Option<RectF32>,
+    pub(crate) align: Align,
+    pub(crate) slice: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub(crate) enum MarkerUnits {
+    StrokeWidth,
+    UserSpaceOnUse,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub(crate) enum MarkerOrient {
+    Auto,
+    AutoStartReverse,
+    // In radians.
+    Angle(f32),
+}
+
+/// The IDs of the markers drawn on an element.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct MarkerUses {
+    pub(crate) start: Option<String>,
+    pub(crate) mid: Option<String>,
+    pub(crate) end: Option<String>,
+    // The vertices of the `d` attribute of a `<path>`, which usvg may split further.
+    pub(crate) source_vertices: Option<Vec<SourceVertex>>,
+}
+
+/// A vertex as written in path data, before arcs are converted to curves.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct SourceVertex {
+    position: Point2DF32,
+    // Whether the vertex ends an elliptical arc.
+    ends_arc: bool,
+}
+
+/// A vertex of a path, with the direction that markers placed on it point in.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct MarkerVertex {
+    pub(crate) position: Point2DF32,
+    // In radians, clockwise from the positive X axis.
+    pub(crate) angle: f32,
+}
+
+impl Markers {
+    /// Finds the markers in an SVG document. Markers that can't be parsed are skipped.
+    pub fn from_data(data: &[u8]) -> Markers {
+        let mut markers = Markers::default();
+        let text = match str::from_utf8(data) {
+            Ok(text) => text,
+            Err(_) => return markers,
+        };
+        let document = match Document::parse(text) {
+            Ok(document) => document,
+            Err(_) => return markers,
+        };
+
+        // Marker contents can use paint servers and styles from the rest of the document.
+        let mut shared = String::new();
+        for node in document.descendants() {
+            if node.is_element() && (node.tag_name().name() == "defs" ||
+                                     node.tag_name().name() == "style") {
+                serialize_node(&node, &mut shared);
+            }
+        }
+
+        for node in document.descendants().filter(|node| node.is_element()) {
+            let id = match node.attribute("id") {
+                None => continue,
+                Some(id) => id,
+            };
+            match node.tag_name().name() {
+                "marker" => {
+                    if let Some(marker) = parse_marker(&node, &shared) {
+                        markers.definitions.insert(id.to_string(), marker);
+                    }
+                }
+                "path" | "line" | "polyline" | "polygon" => {
+                    let uses = MarkerUses {
+                        start: marker_property(&node, "marker-start"),
+                        mid: marker_property(&node, "marker-mid"),
+                        end: marker_property(&node, "marker-end"),
+                        source_vertices: match node.tag_name().name() {
+                            "path" => node.attribute("d").and_then(path_data_vertices),
+                            _ => None,
+                        },
+                    };
+                    if uses.start.is_some() || uses.mid.is_some() || uses.end.is_some() {
+                        markers.uses.insert(id.to_string(), uses);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        markers
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.uses.is_empty()
+    }
+
+    #[inline]
+    pub(crate) fn uses(&self, element_id: &str) -> Option<&MarkerUses> {
+        self.uses.get(element_id)
+    }
+
+    #[inline]
+    pub(crate) fn get(&self, marker_id: &str) -> Option<&Marker> {
+        self.definitions.get(marker_id)
+    }
+}
+
+impl Debug for Markers {
+    fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
+        formatter.debug_struct("Markers")
+                 .field("definitions", &self.definitions.keys().collect::<Vec<_>>())
+                 .field("uses", &self.uses)
+                 .finish()
+    }
+}
+
+/// Returns the vertices that `marker-start`, `marker-mid`, and `marker-end` are placed on, in
+/// that order: the first vertex gets the start marker, the last gets the end marker, and the
+/// rest get the mid marker.
+///
+/// usvg converts each arc into several curves. If the `source_vertices` of the path are known,
+/// the vertices between those curves are left out, so that they don't get mid markers.
+pub(crate) fn path_marker_vertices<I>(segments: I, source_vertices: Option<&[SourceVertex]>)
+                                      -> Vec<MarkerVertex>
+                                      where I: Iterator<Item = UsvgPathSegment> {
+    struct Vertex {
+        position: Point2DF32,
+        incoming: Option<Point2DF32>,
+        outgoing: Option<Point2DF32>,
+    }
+
+    let mut vertices: Vec<Vertex> = vec![];
+    let mut subpath_start_index = 0;
+    let push_edge = |vertices: &mut Vec<Vertex>, outgoing, to, incoming| {
+        if let Some(last) = vertices.last_mut() {
+            last.outgoing = Some(outgoing);
+        }
+        vertices.push(Vertex { position: to, incoming: Some(incoming), outgoing: None });
+    };
+
+    for segment in segments {
+        let from = vertices.last().map(|vertex| vertex.position).unwrap_or_default();
+        match segment {
+            UsvgPathSegment::MoveTo { x, y } => {
+                subpath_start_index = vertices.len();
+                let position = Point2DF32::new(x as f32, y as f32);
+                vertices.push(Vertex { position, incoming: None, outgoing: None });
+            }
+            UsvgPathSegment::LineTo { x, y } => {
+                let to = Point2DF32::new(x as f32, y as f32);
+                push_edge(&mut vertices, to - from, to, to - from);
+            }
+            UsvgPathSegment::CurveTo { x1, y1, x2, y2, x, y } => {
+                let ctrl0 = Point2DF32::new(x1 as f32, y1 as f32);
+                let ctrl1 = Point2DF32::new(x2 as f32, y2 as f32);
+                let to = Point2DF32::new(x as f32, y as f32);
+                // Where control points coincide with endpoints, the tangent comes from the next
+                // distinct point.
+                let outgoing = first_nonzero(&[ctrl0 - from, ctrl1 - from, to - from]);
+                let incoming = first_nonzero(&[to - ctrl1, to - ctrl0, to - from]);
+                push_edge(&mut vertices, outgoing, to, incoming);
+            }
+            UsvgPathSegment::ClosePath => {
+                if subpath_start_index >= vertices.len() {
+                    continue;
+                }
+                let start = vertices[subpath_start_index].position;
+                if start != from {
+                    push_edge(&mut vertices, start - from, start, start - from);
+                }
+
+                // The ends of a closed subpath meet, so each takes the direction of the other.
+                let last_index = vertices.len() - 1;
+                if last_index != subpath_start_index {
+                    vertices[subpath_start_index].incoming = vertices[last_index].incoming;
+                    vertices[last_index].outgoing = vertices[subpath_start_index].outgoing;
+                }
+            }
+        }
+    }
+
+    // A vertex that doesn't match the next source vertex is inside an arc if that vertex ends
+    // one. Otherwise, the path data wasn't understood, and the vertex is kept.
+    let mut next_source_vertex = source_vertices.unwrap_or(&[]).iter().peekable();
+    vertices.retain(|vertex| {
+        match next_source_vertex.peek() {
+            Some(source_vertex) if points_are_close(source_vertex.position, vertex.position) => {
+                next_source_vertex.next();
+                true
+            }
+            Some(source_vertex) => !source_vertex.ends_arc,
+            None => true,
+        }
+    });
+
+    vertices.into_iter().map(|vertex| {
+        let direction = match (vertex.incoming, vertex.outgoing) {
+            (Some(incoming), Some(outgoing)) => {
+                let (incoming, outgoing) = (normalize(incoming), normalize(outgoing));
+                let bisector = incoming + outgoing;
+                if bisector.is_zero() { incoming } else { bisector }
+            }
+            (Some(direction), None) | (None, Some(direction)) => direction,
+            (None, None) => Point2DF32::new(1.0, 0.0),
+        };
+        MarkerVertex { position: vertex.position, angle: f32::atan2(direction.y(), direction.x()) }
+    }).collect()
+}
+
+fn first_nonzero(vectors: &[Point2DF32]) -> Point2DF32 {
+    vectors.iter()
+           .cloned()
+           .find(|vector| !vector.is_zero())
+           .unwrap_or_default()
+}
+
+fn normalize(vector: Point2DF32) -> Point2DF32 {
+    if vector.is_zero() { vector } else { vector.normalize() }
+}
+
+fn points_are_close(a: Point2DF32, b: Point2DF32) -> bool {
+    let delta = a - b;
+    let tolerance = 0.001 * f32::max(1.0, f32::max(a.x().abs(), a.y().abs()));
+    delta.x().abs() <= tolerance && delta.y().abs() <= tolerance
+}
+
+// Returns the vertices that the commands of path data end on, in the same way that
+// `path_marker_vertices()` finds them, or `None` if the data can't be parsed.
+fn path_data_vertices(data: &str) -> Option<Vec<SourceVertex>> {
+    let mut vertices = vec![];
+    let mut scanner = PathDataScanner { data: data.as_bytes(), index: 0 };
+    let (mut current, mut subpath_start) = (Point2DF32::default(), Point2DF32::default());
+    let mut command = None;
+
+    loop {
+        scanner.skip_separators();
+        let byte = match scanner.data.get(scanner.index) {
+            None => break,
+            Some(&byte) => byte,
+        };
+        if byte.is_ascii_alphabetic() {
+            command = Some(byte);
+            scanner.index += 1;
+        }
+        let letter = command?;
+        let relative = letter.is_ascii_lowercase();
+        let origin = if relative { current } else { Point2DF32::default() };
+
+        let to = match letter.to_ascii_uppercase() {
+            b'Z' => {
+                if !points_are_close(current, subpath_start) {
+                    vertices.push(SourceVertex { position: subpath_start, ends_arc: false });
+                }
+                current = subpath_start;
+                // Numbers can't follow `Z`, so don't repeat it.
+                command = None;
+                continue;
+            }
+            b'M' => {
+                let to = origin + scanner.point()?;
+                vertices.push(SourceVertex { position: to, ends_arc: false });
+                subpath_start = to;
+                // Further coordinate pairs are implicit line-tos.
+                command = Some(if relative { b'l' } else { b'L' });
+                current = to;
+                continue;
+            }
+            b'L' | b'T' => origin + scanner.point()?,
+            b'H' => Point2DF32::new(origin.x() + scanner.number()?, current.y()),
+            b'V' => Point2DF32::new(current.x(), origin.y() + scanner.number()?),
+            b'C' => {
+                scanner.point()?;
+                scanner.point()?;
+                origin + scanner.point()?
+            }
+            b'S' | b'Q' => {
+                scanner.point()?;
+                origin + scanner.point()?
+            }
+            b'A' => {
+                let radii = scanner.point()?;
+                scanner.number()?;
+                scanner.flag()?;
+                scanner.flag()?;
+                let to = origin + scanner.point()?;
+                // Per SVG, an arc to the current point is left out, and one with a zero radius
+                // is a line.
+                if points_are_close(to, current) {
+                    continue;
+                }
+                current = to;
+                let ends_arc = radii.x() != 0.0 && radii.y() != 0.0;
+                vertices.push(SourceVertex { position: to, ends_arc });
+                continue;
+            }
+            _ => return None,
+        };
+        vertices.push(SourceVertex { position: to, ends_arc: false });
+        current = to;
+    }
+
+    Some(vertices)
+}
+
+struct PathDataScanner<'a> {
+    data: &'a [u8],
+    index: usize,
+}
+
+impl<'a> PathDataScanner<'a> {
+    fn skip_separators(&mut self) {
+        while self.index < self.data.len() &&
+                (self.data[self.index] == b',' || self.data[self.index].is_ascii_whitespace()) {
+            self.index += 1;
+        }
+    }
+
+    fn number(&mut self) -> Option<f32> {
+        self.skip_separators();
+        let start = self.index;
+        let mut seen_point = false;
+        if self.index < self.data.len() && (self.data[self.index] == b'+' ||
+                                            self.data[self.index] == b'-') {
+            self.index += 1;
+        }
+        while self.index < self.data.len() {
+            match self.data[self.index] {
+                b'0'..=b'9' => {}
+                b'.' if !seen_point => seen_point = true,
+                b'e' | b'E' => {
+                    self.index += 1;
+                    if self.index < self.data.len() && (self.data[self.index] == b'+' ||
+                                                        self.data[self.index] == b'-') {
+                        self.index += 1;
+                    }
+                    seen_point = true;
+                    continue;
+                }
+                _ => break,
+            }
+            self.index += 1;
+        }
+        str::from_utf8(&self.data[start..self.index]).ok()?.parse().ok()
+    }
+
+    fn point(&mut self) -> Option<Point2DF32> {
+        let x = self.number()?;
+        Some(Point2DF32::new(x, self.number()?))
+    }
+
+    // Arc flags are a single digit, and may be written without separators.
+    fn flag(&mut self) -> Option<bool> {
+        self.skip_separators();
+        let flag = match self.data.get(self.index) {
+            Some(b'0') => false,
+            Some(b'1') => true,
+            _ => return None,
+        };
+        self.index += 1;
+        Some(flag)
+    }
+}
+
+fn parse_marker(node: &XmlNode, shared: &str) -> Option<Marker> {
+    let number = |name: &str, default: f32| {
+        node.attribute(name).map_or(Some(default), parse_length)
+    };
+
+    let view_box = match node.attribute("viewBox") {
+        None => None,
+        Some(view_box) => {
+            let numbers = view_box.split(|c: char| c == ',' || c.is_whitespace())
+                                  .filter(|number| !number.is_empty())
+                                  .map(parse_length)
+                                  .collect::<Option<Vec<f32>>>()?;
+            if numbers.len() != 4 {
+                return None;
+            }
+            Some(RectF32::new(Point2DF32::new(numbers[0], numbers[1]),
+                              Point2DF32::new(numbers[2], numbers[3])))
+        }
+    };
+
+    let (align, slice) = parse_preserve_aspect_ratio(node.attribute("preserveAspectRatio"));
+    let units = match node.attribute("markerUnits") {
+        Some("userSpaceOnUse") => MarkerUnits::UserSpaceOnUse,
+        _ => MarkerUnits::StrokeWidth,
+    };
+    let orient = match node.attribute("orient").map(str::trim) {
+        Some("auto") => MarkerOrient::Auto,
+        Some("auto-start-reverse") => MarkerOrient::AutoStartReverse,
+        Some(angle) => MarkerOrient::Angle(parse_angle(angle)?),
+        None => MarkerOrient::Angle(0.0),
+    };
+
+    let mut contents = String::new();
+    contents.push_str("<svg xmlns=\"http://www.w3.org/2000/svg\" \
+                       xmlns:xlink=\"http://www.w3.org/1999/xlink\" width=\"1\" height=\"1\">");
+    contents.push_str(shared);
+    for kid in node.children() {
+        serialize_node(&kid, &mut contents);
+    }
+    contents.push_str("</svg>");
+    let contents = Tree::from_str(&contents, &UsvgOptions::default()).ok()?;
+
+    Some(Marker {
+        contents,
+        ref_point: Point2DF32::new(number("refX", 0.0)?, number("refY", 0.0)?),
+        size: Point2DF32::new(number("markerWidth", 3.0)?, number("markerHeight", 3.0)?),
+        units,
+        orient,
+        view_box,
+        align,
+        slice,
+    })
+}
+
+// Marker properties are inherited, and may be set with presentation attributes, inline styles,
+// or the `marker` shorthand.
+fn marker_property(node: &XmlNode, name: &str) -> Option<String> {
+    for ancestor in node.ancestors().filter(|ancestor| ancestor.is_element()) {
+        let mut value = None;
+        if let Some(style) = ancestor.attribute("style") {
+            for declaration in style.split(';') {
+                let colon = match declaration.find(':') {
+                    None => continue,
+                    Some(colon) => colon,
+                };
+                let property = declaration[..colon].trim();
+                if property == name || property == "marker" {
+                    value = Some(declaration[(colon + 1)..].trim());
+                }
+            }
+        }
+        let value = value.or_else(|| ancestor.attribute(name))
+                         .or_else(|| ancestor.attribute("marker"));
+        if let Some(value) = value {
+            let value = value.trim();
+            if value.starts_with("url(") && value.ends_with(')') {
+                let url = value[4..(value.len() - 1)].trim();
+                let url = url.trim_matches(|c| c == '"' || c == '\'');
+                if url.starts_with('#') {
+                    return Some(url[1..].to_string());
+                }
+            }
+            // `none`, or something we don't understand.
+            return None;
+        }
+    }
+    None
+}
+
+fn parse_preserve_aspect_ratio(value: Option<&str>) -> (Align, bool) {
+    let mut words = value.unwrap_or("").split_whitespace();
+    let align = match words.next() {
+        Some("none") => Align::None,
+        Some("xMinYMin") => Align::XMinYMin,
+        Some("xMidYMin") => Align::XMidYMin,
+        Some("xMaxYMin") => Align::XMaxYMin,
+        Some("xMinYMid") => Align::XMinYMid,
+        Some("xMaxYMid") => Align::XMaxYMid,
+        Some("xMinYMax") => Align::XMinYMax,
+        Some("xMidYMax") => Align::XMidYMax,
+        Some("xMaxYMax") => Align::XMaxYMax,
+        _ => Align::XMidYMid,
+    };
+    (align, words.next() == Some("slice"))
+}
+
+fn parse_length(value: &str) -> Option<f32> {
+    value.trim().trim_end_matches("px").parse().ok()
+}
+
+// Returns radians.
+fn parse_angle(value: &str) -> Option<f32> {
+    if value.ends_with("deg") {
+        parse_length(&value[..value.len() - 3]).map(f32::to_radians)
+    } else if value.ends_with("rad") {
+        parse_length(&value[..value.len() - 3])
+    } else {
+        parse_length(value).map(f32::to_radians)
+    }
+}
+
+fn serialize_node(node: &XmlNode, output: &mut String) {
+    if node.is_text() {
+        output.push_str(&escape(node.text().unwrap_or("")));
+        return;
+    }
+    if !node.is_element() {
+        return;
+    }
+
+    let name = node.tag_name().name();
+    output.push('<');
+    output.push_str(name);
+    for attribute in node.attributes() {
+        output.push(' ');
+        if attribute.namespace() == Some(XLINK_NAMESPACE) {
+            output.push_str("xlink:");
+        }
+        output.push_str(attribute.name());
+        output.push_str("=\"");
+        output.push_str(&escape(attribute.value()));
+        output.push('"');
+    }
+    output.push('>');
+    for kid in node.children() {
+        serialize_node(&kid, output);
+    }
+    output.push_str("</");
+    output.push_str(name);
+    output.push('>');
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod test {
+    use super::{path_data_vertices, path_marker_vertices};
+    use pathfinder_geometry::basic::point::Point2DF32;
+    use std::f32::consts::FRAC_PI_4;
+    use usvg::PathSegment;
+
+    #[test]
+    fn test_closed_subpath_vertex_angles() {
+        let segments = vec![
+            PathSegment::MoveTo { x: 0.0, y: 0.0 },
+            PathSegment::LineTo { x: 10.0, y: 0.0 },
+            PathSegment::LineTo { x: 10.0, y: 10.0 },
+            PathSegment::ClosePath,
+        ];
+        let vertices = path_marker_vertices(segments.into_iter(), None);
+        assert_eq!(vertices.len(), 4);
+        // The corner bisects the right angle between the two edges.
+        assert!((vertices[1].angle - FRAC_PI_4).abs() < 0.001);
+        // The start and end of the closed subpath point in the same direction.
+        assert!((vertices[0].angle - vertices[3].angle).abs() < 0.001);
+    }
+
+    #[test]
+    fn test_path_data_vertices() {
+        let vertices = path_data_vertices("M0,0 l10-10H20v5 a5 5 0 0110 0 A5,5 0 0 1 30 5 \
+                                           q1 1 2 2t1e1.5z").unwrap();
+        let positions: Vec<_> = vertices.iter().map(|vertex| vertex.position).collect();
+        assert_eq!(positions, vec![Point2DF32::new(0.0, 0.0),
+                                   Point2DF32::new(10.0, -10.0),
+                                   Point2DF32::new(20.0, -10.0),
+                                   Point2DF32::new(20.0, -5.0),
+                                   Point2DF32::new(30.0, -5.0),
+                                   Point2DF32::new(30.0, 5.0),
+                                   Point2DF32::new(32.0, 7.0),
+                                   Point2DF32::new(42.0, 7.5),
+                                   Point2DF32::new(0.0, 0.0)]);
+        let arc_ends: Vec<_> = vertices.iter().map(|vertex| vertex.ends_arc).collect();
+        assert_eq!(arc_ends, vec![false, false, false, false, true, true, false, false, false]);
+
+        assert!(path_data_vertices("M0 0 X1 1").is_none());
+    }
+
+    #[test]
+    fn test_arcs_get_no_extra_mid_vertices() {
+        // `M0 0 A10 10 0 0 1 20 0 L20 10`, with the arc split into two curves as usvg does.
+        let source_vertices = path_data_vertices("M0 0 A10 10 0 0 1 20 0 L20 10").unwrap();
+        let segments = vec![
+            PathSegment::MoveTo { x: 0.0, y: 0.0 },
+            PathSegment::CurveTo { x1: 0.0, y1: -5.5, x2: 4.5, y2: -10.0, x: 10.0, y: -10.0 },
+            PathSegment::CurveTo { x1: 15.5, y1: -10.0, x2: 20.0, y2: -5.5, x: 20.0, y: 0.0 },
+            PathSegment::LineTo { x: 20.0, y: 10.0 },
+        ];
+        let vertices = path_marker_vertices(segments.clone().into_iter(), None);
+        assert_eq!(vertices.len(), 4);
+
+        let vertices = path_marker_vertices(segments.into_iter(), Some(&source_vertices));
+        let positions: Vec<_> = vertices.iter().map(|vertex| vertex.position).collect();
+        assert_eq!(positions, vec![Point2DF32::new(0.0, 0.0),
+                                   Point2DF32::new(20.0, 0.0),
+                                   Point2DF32::new(20.0, 10.0)]);
+    }
+}
@@ -0,0 +1,299 @@
+// pathfinder/svg/src/pattern.rs
+//
+// Copyright © 2019 The Pathfinder Project Developers.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Renders the contents of `<pattern>` tiles into images.
+//!
+//! Each tile is drawn once, when the scene is built, and then repeated across the plane by the
+//! renderer as an image paint. Tiles are small, so they're rasterized on the CPU by accumulating
+//! the exact area that each edge covers in each pixel.
+
+use pathfinder_geometry::basic::point::{Point2DF32, Point2DI32};
+use pathfinder_geometry::color::ColorU;
+use pathfinder_geometry::outline::Contour;
+use pathfinder_geometry::segment::Segment;
+use pathfinder_renderer::scene::{FillRule, Scene};
+
+// The maximum distance, in pixels, between a curve and the lines that approximate it.
+const FLATTENING_TOLERANCE: f32 = 0.25;
+const MAX_CURVE_SUBDIVISIONS: u32 = 64;
+
+/// Draws the objects of `scene`, whose coordinates are in pixels, into an image of the given size.
+/// Returns non-premultiplied pixels, in rows from top to bottom.
+// FIXME(pcwalton): Only the color of each paint is drawn, so gradients and images inside
+// patterns come out flat, and clip paths and layers are ignored. See `uses_unsupported_features()`.
+pub(crate) fn rasterize_scene(scene: &Scene, size: Point2DI32) -> Vec<ColorU> {
+    let (width, height) = (size.x() as usize, size.y() as usize);
+
+    // Premultiplied RGBA.
+    let mut pixels = vec![[0.0; 4]; width * height];
+    let mut coverage = CoverageBuffer::new(size);
+    for object in &scene.objects {
+        let color = scene.paints[object.paint().0 as usize].color.to_f32();
+        if color.a() == 0.0 {
+            continue;
+        }
+
+        coverage.clear();
+        for contour in &object.outline().contours {
+            coverage.add_contour(contour);
+        }
+
+        for y in 0..height {
+            let mut winding = 0.0;
+            for x in 0..width {
+                winding += coverage.area[y * coverage.stride + x];
+                let alpha = winding_to_coverage(winding, object.fill_rule()) * color.a();
+                if alpha == 0.0 {
+                    continue;
+                }
+
+                let pixel = &mut pixels[y * width + x];
+                let src = [color.r() * alpha, color.g() * alpha, color.b() * alpha, alpha];
+                for (dest, src) in pixel.iter_mut().zip(src.iter()) {
+                    *dest = src + *dest * (1.0 - alpha);
+                }
+            }
+        }
+    }
+
+    pixels.iter().map(|pixel| {
+        let alpha = pixel[3];
+        if alpha == 0.0 {
+            return ColorU::default();
+        }
+        let to_u8 = |value: f32| (f32::min(value, 1.0) * 255.0).round() as u8;
+        ColorU {
+            r: to_u8(pixel[0] / alpha),
+            g: to_u8(pixel[1] / alpha),
+            b: to_u8(pixel[2] / alpha),
+            a: to_u8(alpha),
+        }
+    }).collect()
+}
+
+/// Returns true if `scene` uses anything that `rasterize_scene()` can't draw: gradients, images,
+/// clip paths, or layers.
+pub(crate) fn uses_unsupported_features(scene: &Scene) -> bool {
+    scene.paints.iter().any(|paint| paint.gradient.is_some() || paint.image.is_some()) ||
+        scene.objects.iter().any(|object| object.clip_path().is_some()) ||
+        !scene.layers.is_empty()
+}
+
+fn winding_to_coverage(winding: f32, fill_rule: FillRule) -> f32 {
+    match fill_rule {
+        FillRule::Winding => f32::min(winding.abs(), 1.0),
+        FillRule::EvenOdd => {
+            let winding = winding.abs() % 2.0;
+            if winding > 1.0 { 2.0 - winding } else { winding }
+        }
+    }
+}
+
+// The signed area that edges cover in each pixel. Summing a row from left to right yields the
+// winding number of each pixel, with fractional values along edges.
+struct CoverageBuffer {
+    area: Vec<f32>,
+    size: Point2DI32,
+    // Two more than the width, since an edge on the right border touches the pixel past it.
+    stride: usize,
+}
+
+impl CoverageBuffer {
+    fn new(size: Point2DI32) -> CoverageBuffer {
+        let stride = size.x() as usize + 2;
+        CoverageBuffer { area: vec![0.0; stride * size.y() as usize], size, stride }
+    }
+
+    fn clear(&mut self) {
+        for area in &mut self.area {
+            *area = 0.0;
+        }
+    }
+
+    fn add_contour(&mut self, contour: &Contour) {
+        if contour.is_empty() {
+            return;
+        }
+
+        // Fills implicitly close open contours.
+        for segment in contour.iter() {
+            self.add_segment(&segment);
+        }
+        if !contour.is_closed() {
+            self.add_line(contour.position_of(contour.len() - 1), contour.position_of(0));
+        }
+    }
+
+    fn add_segment(&mut self, segment: &Segment) {
+        if segment.is_line() {
+            self.add_line(segment.baseline.from(), segment.baseline.to());
+            return;
+        }
+
+        let cubic = segment.to_cubic();
+        let hull_length = (cubic.ctrl.from() - cubic.baseline.from()).length() +
+            (cubic.ctrl.to() - cubic.ctrl.from()).length() +
+            (cubic.baseline.to() - cubic.ctrl.to()).length();
+        let subdivisions = f32::sqrt(hull_length / FLATTENING_TOLERANCE).ceil() as u32;
+        let subdivisions = subdivisions.max(1).min(MAX_CURVE_SUBDIVISIONS);
+
+        let mut from = segment.baseline.from();
+        for subdivision in 1..=subdivisions {
+            let to = segment.sample(subdivision as f32 / subdivisions as f32);
+            self.add_line(from, to);
+            from = to;
+        }
+    }
+
+    fn add_line(&mut self, from: Point2DF32, to: Point2DF32) {
+        // Split the line where it crosses the left and right borders. Parts to the left add to
+        // the winding number of the whole row, so they're moved onto the left border; parts to
+        // the right don't touch any pixel.
+        let width = self.size.x() as f32;
+        let mut splits = [0.0, 1.0, 1.0, 1.0];
+        let mut split_count = 1;
+        let dx = to.x() - from.x();
+        if dx != 0.0 {
+            for &border in &[0.0, width] {
+                let t = (border - from.x()) / dx;
+                if t > 0.0 && t < 1.0 {
+                    splits[split_count] = t;
+                    split_count += 1;
+                }
+            }
+        }
+        splits[split_count] = 1.0;
+        splits[0..=split_count].sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+        for pair in splits[0..=split_count].windows(2) {
+            let (mut from, mut to) = (from.lerp(to, pair[0]), from.lerp(to, pair[1]));
+            let mid_x = (from.x() + to.x()) * 0.5;
+            if mid_x >= width {
+                continue;
+            }
+            if mid_x <= 0.0 {
+                from.set_x(0.0);
+                to.set_x(0.0);
+            }
+            from.set_x(f32::max(f32::min(from.x(), width), 0.0));
+            to.set_x(f32::max(f32::min(to.x(), width), 0.0));
+            self.accumulate_line(from, to);
+        }
+    }
+
+    // `from` and `to` must be between the left and right borders.
+    fn accumulate_line(&mut self, from: Point2DF32, to: Point2DF32) {
+        if from.y() == to.y() {
+            return;
+        }
+
+        let (direction, top, bottom) = if from.y() < to.y() {
+            (1.0, from, to)
+        } else {
+            (-1.0, to, from)
+        };
+        let dxdy = (bottom.x() - top.x()) / (bottom.y() - top.y());
+
+        let height = self.size.y() as f32;
+        let first_row = f32::max(top.y(), 0.0).floor() as usize;
+        let last_row = f32::max(f32::min(bottom.y(), height), 0.0).ceil() as usize;
+        for row in first_row..last_row {
+            let row_top = f32::max(row as f32, top.y());
+            let row_bottom = f32::min(row as f32 + 1.0, bottom.y());
+            if row_bottom <= row_top {
+                continue;
+            }
+            let x0 = top.x() + (row_top - top.y()) * dxdy;
+            let x1 = top.x() + (row_bottom - top.y()) * dxdy;
+            self.accumulate_span(row, x0, x1, (row_bottom - row_top) * direction);
+        }
+    }
+
+    // Adds the area to the right of the part of an edge within one row, which runs from `x0` to
+    // `x1` and has the signed height `dy`.
+    fn accumulate_span(&mut self, row: usize, x0: f32, x1: f32, dy: f32) {
+        let area = &mut self.area[(row * self.stride)..((row + 1) * self.stride)];
+        let (x0, x1) = if x0 < x1 { (x0, x1) } else { (x1, x0) };
+        let (x0_floor, x1_ceil) = (x0.floor(), x1.ceil());
+        let (x0i, x1i) = (x0_floor as usize, x1_ceil as usize);
+
+        if x1i <= x0i + 1 {
+            // The edge stays within one pixel.
+            let x_mid = (x0 + x1) * 0.5 - x0_floor;
+            area[x0i] += dy - dy * x_mid;
+            area[x0i + 1] += dy * x_mid;
+            return;
+        }
+
+        let inv_dx = (x1 - x0).recip();
+        let x0_fract = x0 - x0_floor;
+        let area_first = 0.5 * inv_dx * (1.0 - x0_fract) * (1.0 - x0_fract);
+        let x1_fract = x1 - x1_ceil + 1.0;
+        let area_last = 0.5 * inv_dx * x1_fract * x1_fract;
+
+        area[x0i] += dy * area_first;
+        if x1i == x0i + 2 {
+            area[x0i + 1] += dy * (1.0 - area_first - area_last);
+        } else {
+            let area_second = inv_dx * (1.5 - x0_fract);
+            area[x0i + 1] += dy * (area_second - area_first);
+            for x in (x0i + 2)..(x1i - 1) {
+                area[x] += dy * inv_dx;
+            }
+            let area_before_last = area_second + (x1i - x0i - 3) as f32 * inv_dx;
+            area[x1i - 1] += dy * (1.0 - area_before_last - area_last);
+        }
+        area[x1i] += dy * area_last;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{CoverageBuffer, winding_to_coverage};
+    use pathfinder_geometry::basic::point::{Point2DF32, Point2DI32};
+    use pathfinder_geometry::basic::rect::RectF32;
+    use pathfinder_geometry::outline::Outline;
+    use pathfinder_renderer::scene::FillRule;
+
+    fn add_rect(coverage: &mut CoverageBuffer, origin: Point2DF32, size: Point2DF32) {
+        let segments = crate::rect_to_segments(RectF32::new(origin, size));
+        for contour in &Outline::from_segments(segments.into_iter()).contours {
+            coverage.add_contour(contour);
+        }
+    }
+
+    fn winding_at(coverage: &CoverageBuffer, x: usize, y: usize) -> f32 {
+        coverage.area[(y * coverage.stride)..(y * coverage.stride + x + 1)].iter().sum()
+    }
+
+    #[test]
+    fn test_half_covered_pixel() {
+        let mut coverage = CoverageBuffer::new(Point2DI32::new(3, 1));
+        add_rect(&mut coverage, Point2DF32::new(0.0, 0.0), Point2DF32::new(1.5, 1.0));
+        // The sign of the winding number depends on the orientation of the rect, so compare
+        // coverage.
+        let coverage_at = |x| winding_to_coverage(winding_at(&coverage, x, 0), FillRule::Winding);
+        assert!((coverage_at(0) - 1.0).abs() < 0.001);
+        assert!((coverage_at(1) - 0.5).abs() < 0.001);
+        assert!(coverage_at(2).abs() < 0.001);
+    }
+
+    #[test]
+    fn test_even_odd_hole() {
+        let mut coverage = CoverageBuffer::new(Point2DI32::new(3, 3));
+        add_rect(&mut coverage, Point2DF32::new(0.0, 0.0), Point2DF32::new(3.0, 3.0));
+        add_rect(&mut coverage, Point2DF32::new(1.0, 1.0), Point2DF32::new(1.0, 1.0));
+        let center = winding_at(&coverage, 1, 1);
+        let edge = winding_at(&coverage, 0, 1);
+        assert!(winding_to_coverage(center, FillRule::EvenOdd).abs() < 0.001);
+        assert!((winding_to_coverage(center, FillRule::Winding) - 1.0).abs() < 0.001);
+        assert!((winding_to_coverage(edge, FillRule::EvenOdd) - 1.0).abs() < 0.001);
+    }
+}
@@ -0,0 +1,90 @@
+// pathfinder/svg/src/source.rs
+//
+// Copyright © 2019 The Pathfinder Project Developers.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Reads attributes that usvg 0.4 drops while simplifying the tree from the source document.
+//!
+//! Like markers, they're matched to the nodes of the tree by element ID.
+
+use roxmltree::{Document, Node as XmlNode};
+use std::collections::{HashMap, HashSet};
+use std::str;
+
+/// Attributes of the elements of a document that aren't in its usvg tree.
+// FIXME(pcwalton): Elements without IDs, and properties set in style sheets, aren't found.
+#[derive(Clone, Debug, Default)]
+pub struct SourceAttributes {
+    // The `class` attribute of each element that has one, split into class names.
+    classes: HashMap<String, Vec<String>>,
+    // The IDs of elements with `vector-effect: non-scaling-stroke`.
+    non_scaling_strokes: HashSet<String>,
+}
+
+impl SourceAttributes {
+    /// Reads the attributes of the elements of an SVG document. If the document can't be parsed,
+    /// none are found.
+    pub fn from_data(data: &[u8]) -> SourceAttributes {
+        let mut attributes = SourceAttributes::default();
+        let text = match str::from_utf8(data) {
+            Ok(text) => text,
+            Err(_) => return attributes,
+        };
+        let document = match Document::parse(text) {
+            Ok(document) => document,
+            Err(_) => return attributes,
+        };
+
+        for node in document.descendants().filter(|node| node.is_element()) {
+            let id = match node.attribute("id") {
+                None => continue,
+                Some(id) => id,
+            };
+            if let Some(classes) = node.attribute("class") {
+                let classes = classes.split_whitespace().map(|class| class.to_string()).collect();
+                attributes.classes.insert(id.to_string(), classes);
+            }
+            let vector_effect = property(&node, "vector-effect");
+            if vector_effect.map_or(false, |value| value == "non-scaling-stroke") {
+                attributes.non_scaling_strokes.insert(id.to_string());
+            }
+        }
+
+        attributes
+    }
+
+    #[inline]
+    pub(crate) fn classes(&self, element_id: &str) -> &[String] {
+        match self.classes.get(element_id) {
+            Some(classes) => classes,
+            None => &[],
+        }
+    }
+
+    #[inline]
+    pub(crate) fn has_non_scaling_stroke(&self, element_id: &str) -> bool {
+        self.non_scaling_strokes.contains(element_id)
+    }
+}
+
+// The value of a property of the element itself, from its inline style or its presentation
+// attribute.
+fn property(node: &XmlNode, name: &str) -> Option<String> {
+    if let Some(style) = node.attribute("style") {
+        for declaration in style.split(';').rev() {
+            let colon = match declaration.find(':') {
+                None => continue,
+                Some(colon) => colon,
+            };
+            if declaration[..colon].trim() == name {
+                return Some(declaration[(colon + 1)..].trim().to_string());
+            }
+        }
+    }
+    node.attribute(name).map(|value| value.trim().to_string())
+}
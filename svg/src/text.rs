@@ -0,0 +1,302 @@
+// pathfinder/svg/src/text.rs
+//
+// Copyright © 2019 The Pathfinder Project Developers.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Lays out `<text>` elements as glyph outlines.
+//!
+//! Fonts are looked up by family name, first as `fonts/<family>.ttf` or `fonts/<family>.otf`
+//! through the caller's `ResourceLoader` and then among the fonts installed on the system. All
+//! the families a document uses are resolved before building starts.
+
+use font_kit::family_name::FamilyName;
+use font_kit::font::Font;
+use font_kit::hinting::HintingOptions;
+use font_kit::properties::Properties;
+use font_kit::source::SystemSource;
+use lyon_path::PathEvent;
+use lyon_path::builder::FlatPathBuilder;
+use lyon_path::default::Path;
+use pathfinder_geometry::basic::line_segment::LineSegmentF32;
+use pathfinder_geometry::basic::point::Point2DF32;
+use pathfinder_geometry::basic::transform2d::{Transform2DF32, Transform2DF32PathIter};
+use pathfinder_geometry::outline::Outline;
+use pathfinder_geometry::segment::{Segment, SegmentFlags};
+use pathfinder_gpu::resources::ResourceLoader;
+use std::collections::HashMap;
+use std::sync::Arc;
+use usvg::{Node, NodeKind, NumberList, Text, TextAnchor, TextSpan, Visibility};
+
+pub(crate) struct FontCache {
+    // Keyed by family name as written in the document. `None` if nothing matched the name.
+    fonts: HashMap<String, Option<Font>>,
+    // Used for characters that none of the requested families have.
+    fallback: Option<Font>,
+}
+
+impl FontCache {
+    /// Loads every font family used by the text under `root`. The system fonts aren't touched
+    /// unless there is some text.
+    pub(crate) fn new(root: &Node, resources: Option<&dyn ResourceLoader>) -> FontCache {
+        let mut system_source = None;
+        let mut fonts = HashMap::new();
+        for node in root.descendants() {
+            if let NodeKind::Text(ref text) = *node.borrow() {
+                let system_source = system_source.get_or_insert_with(SystemSource::new);
+                for span in text.chunks.iter().flat_map(|chunk| chunk.spans.iter()) {
+                    for family in parse_family_list(&span.font.family) {
+                        if !fonts.contains_key(family) {
+                            let font = load_font(family, system_source, resources);
+                            fonts.insert(family.to_owned(), font);
+                        }
+                    }
+                }
+            }
+        }
+
+        let fallback = system_source.and_then(|system_source| {
+            select_system_font(FamilyName::SansSerif, &system_source)
+        });
+        FontCache { fonts, fallback }
+    }
+
+    /// Returns the outline of each span of `text`, in the user space of the element. Returns
+    /// `None` if no font could be found for some span.
+    // FIXME(pcwalton): Each character is mapped to one glyph and positioned by its advance
+    // alone, which ignores kerning, ligatures, and complex scripts. `rotate`, font weights and
+    // styles, and text decorations are ignored too, and only the first of the `x`, `y`, `dx`,
+    // and `dy` positions of a chunk is used. usvg 0.4 doesn't keep `letter-spacing`,
+    // `word-spacing`, or `baseline-shift`.
+    pub(crate) fn layout_text<'a>(&self, text: &'a Text) -> Option<Vec<(&'a TextSpan, Outline)>> {
+        let mut span_outlines = vec![];
+        let mut pen = Point2DF32::default();
+
+        for chunk in &text.chunks {
+            let first = |list: &Option<NumberList>| {
+                list.as_ref().and_then(|list| list.first()).map(|&number| number as f32)
+            };
+            let mut origin = Point2DF32::new(first(&chunk.x).unwrap_or(pen.x()),
+                                             first(&chunk.y).unwrap_or(pen.y()));
+            origin = origin + Point2DF32::new(first(&chunk.dx).unwrap_or(0.0),
+                                              first(&chunk.dy).unwrap_or(0.0));
+
+            // `text-anchor` depends on the advance of the whole chunk, so lay it out starting at
+            // zero and shift it into place afterward.
+            let mut advance = 0.0;
+            let mut chunk_segments = Vec::with_capacity(chunk.spans.len());
+            for span in &chunk.spans {
+                let fonts = self.fonts_for_family_list(&span.font.family);
+                if fonts.is_empty() {
+                    return None;
+                }
+
+                let size = span.font.size as f32;
+
+                let mut segments = vec![];
+                for character in span.text.chars() {
+                    let (font, glyph_id) = match fonts.iter().filter_map(|font| {
+                        font.glyph_for_char(character).map(|glyph_id| (*font, glyph_id))
+                    }).next() {
+                        Some(glyph) => glyph,
+                        None => continue,
+                    };
+
+                    // Glyphs are in font units with y pointing up.
+                    let scale = size / font.metrics().units_per_em as f32;
+                    if span.visibility == Visibility::Visible {
+                        let glyph_transform =
+                            Transform2DF32::row_major(scale, 0.0,
+                                                      0.0, -scale,
+                                                      advance, 0.0);
+                        push_glyph_segments(font, glyph_id, &glyph_transform, &mut segments);
+                    }
+
+                    let glyph_advance = font.advance(glyph_id).map_or(0.0, |advance| advance.x);
+                    advance += glyph_advance * scale;
+                }
+                chunk_segments.push((span, segments));
+            }
+
+            let anchor_offset = match chunk.anchor {
+                TextAnchor::Start => 0.0,
+                TextAnchor::Middle => -advance * 0.5,
+                TextAnchor::End => -advance,
+            };
+            let origin = origin + Point2DF32::new(anchor_offset, 0.0);
+            let chunk_transform = Transform2DF32::from_translation(&origin);
+
+            for (span, segments) in chunk_segments {
+                if segments.is_empty() {
+                    continue;
+                }
+                let segments = Transform2DF32PathIter::new(segments.into_iter(), &chunk_transform);
+                span_outlines.push((span, Outline::from_segments(segments)));
+            }
+
+            pen = origin + Point2DF32::new(advance, 0.0);
+        }
+
+        Some(span_outlines)
+    }
+
+    // Returns the fonts to try, in order, for a `font-family` list.
+    fn fonts_for_family_list(&self, family_list: &str) -> Vec<&Font> {
+        let mut fonts: Vec<_> = parse_family_list(family_list).filter_map(|family| {
+            self.fonts.get(family).and_then(|font| font.as_ref())
+        }).collect();
+        fonts.extend(self.fallback.as_ref());
+        fonts
+    }
+}
+
+fn parse_family_list<'a>(family_list: &'a str) -> impl Iterator<Item = &'a str> {
+    family_list.split(',')
+               .map(|family| family.trim().trim_matches(|c| c == '\'' || c == '"'))
+               .filter(|family| !family.is_empty())
+}
+
+fn load_font(family: &str, system_source: &SystemSource, resources: Option<&dyn ResourceLoader>)
+             -> Option<Font> {
+    if let Some(resources) = resources {
+        for extension in &["ttf", "otf"] {
+            if let Ok(data) = resources.slurp(&format!("fonts/{}.{}", family, extension)) {
+                if let Ok(font) = Font::from_bytes(Arc::new(data), 0) {
+                    return Some(font);
+                }
+            }
+        }
+    }
+
+    let family_name = match &*family.to_lowercase() {
+        "serif" => FamilyName::Serif,
+        "sans-serif" => FamilyName::SansSerif,
+        "monospace" => FamilyName::Monospace,
+        "cursive" => FamilyName::Cursive,
+        "fantasy" => FamilyName::Fantasy,
+        _ => FamilyName::Title(family.to_owned()),
+    };
+    select_system_font(family_name, system_source)
+}
+
+fn select_system_font(family_name: FamilyName, system_source: &SystemSource) -> Option<Font> {
+    let handle = system_source.select_best_match(&[family_name], &Properties::new()).ok()?;
+    handle.load().ok()
+}
+
+fn push_glyph_segments(font: &Font,
+                       glyph_id: u32,
+                       transform: &Transform2DF32,
+                       segments: &mut Vec<Segment>) {
+    let mut builder = Path::builder();
+    if font.outline(glyph_id, HintingOptions::None, &mut builder).is_err() {
+        return;
+    }
+
+    let mut glyph_segments = vec![];
+    let (mut first_subpath_point, mut last_subpath_point) = (Point2DF32::default(),
+                                                             Point2DF32::default());
+    let mut just_moved = false;
+    for event in builder.build().iter() {
+        let (mut segment, to) = match event {
+            PathEvent::MoveTo(to) => {
+                first_subpath_point = Point2DF32::new(to.x, to.y);
+                last_subpath_point = first_subpath_point;
+                just_moved = true;
+                continue;
+            }
+            PathEvent::LineTo(to) => {
+                let to = Point2DF32::new(to.x, to.y);
+                (Segment::line(&LineSegmentF32::new(&last_subpath_point, &to)), to)
+            }
+            PathEvent::QuadraticTo(ctrl, to) => {
+                let (ctrl, to) = (Point2DF32::new(ctrl.x, ctrl.y), Point2DF32::new(to.x, to.y));
+                let baseline = LineSegmentF32::new(&last_subpath_point, &to);
+                (Segment::quadratic(&baseline, &ctrl), to)
+            }
+            PathEvent::CubicTo(ctrl0, ctrl1, to) => {
+                let ctrl = LineSegmentF32::new(&Point2DF32::new(ctrl0.x, ctrl0.y),
+                                               &Point2DF32::new(ctrl1.x, ctrl1.y));
+                let to = Point2DF32::new(to.x, to.y);
+                let baseline = LineSegmentF32::new(&last_subpath_point, &to);
+                (Segment::cubic(&baseline, &ctrl), to)
+            }
+            PathEvent::Close => {
+                let baseline = LineSegmentF32::new(&last_subpath_point, &first_subpath_point);
+                let mut segment = Segment::line(&baseline);
+                segment.flags.insert(SegmentFlags::CLOSES_SUBPATH);
+                (segment, first_subpath_point)
+            }
+            // Font outlines never contain arcs.
+            PathEvent::Arc(..) => continue,
+        };
+
+        if just_moved {
+            segment.flags.insert(SegmentFlags::FIRST_IN_SUBPATH);
+        }
+        last_subpath_point = to;
+        just_moved = false;
+        glyph_segments.push(segment);
+    }
+
+    segments.extend(Transform2DF32PathIter::new(glyph_segments.into_iter(), transform));
+}
+
+#[cfg(test)]
+mod test {
+    use super::{FamilyName, FontCache, select_system_font};
+    use font_kit::source::SystemSource;
+    use std::collections::HashMap;
+    use usvg::{NodeKind, Options, Text, Tree};
+
+    fn parse_text(svg: &str) -> Text {
+        let tree = Tree::from_str(svg, &Options::default()).unwrap();
+        let root = tree.root();
+        for node in root.descendants() {
+            if let NodeKind::Text(ref text) = *node.borrow() {
+                return text.clone();
+            }
+        }
+        panic!("no text in {}", svg)
+    }
+
+    #[test]
+    fn test_text_without_fonts_is_not_laid_out() {
+        let text = parse_text("<svg xmlns='http://www.w3.org/2000/svg' width='100' \
+                               height='100'><text x='10' y='20'>H</text></svg>");
+        let fonts = FontCache { fonts: HashMap::new(), fallback: None };
+        assert!(fonts.layout_text(&text).is_none());
+    }
+
+    // Uses the system sans-serif font, so that no font has to be checked in.
+    #[test]
+    fn test_chunk_position_and_anchor() {
+        let system_source = SystemSource::new();
+        let font = select_system_font(FamilyName::SansSerif, &system_source)
+            .expect("a sans-serif system font is needed to lay out text");
+        let fonts = FontCache { fonts: HashMap::new(), fallback: Some(font) };
+
+        // Only the first value of each position list is used.
+        for &(anchor, expected_x) in &[("start", None), ("end", Some(15.0))] {
+            let text = parse_text(&format!("<svg xmlns='http://www.w3.org/2000/svg' \
+                                            width='100' height='100'>\
+                                            <text x='10 90' y='20' dx='5' \
+                                            text-anchor='{}' font-size='10'>H</text></svg>",
+                                           anchor));
+            let outlines = fonts.layout_text(&text).unwrap();
+            assert_eq!(outlines.len(), 1);
+            let bounds = outlines[0].1.bounds();
+
+            // `H` sits on the baseline, between its side bearings.
+            assert!((bounds.max_y() - 20.0).abs() < 0.01);
+            match expected_x {
+                None => assert!(bounds.min_x() > 15.0 && bounds.min_x() < 17.0),
+                Some(end) => assert!(bounds.max_x() < end && bounds.max_x() > end - 2.0),
+            }
+        }
+    }
+}
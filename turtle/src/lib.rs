@@ -21,8 +21,8 @@ use pathfinder_geometry::basic::rect::RectF32;
 use pathfinder_geometry::color::ColorU;
 use pathfinder_geometry::outline::Outline;
 use pathfinder_geometry::segment::{Segment, SegmentFlags};
-use pathfinder_geometry::stroke::OutlineStrokeToFill;
-use pathfinder_renderer::scene::{Paint, PathObject, PathObjectKind, Scene};
+use pathfinder_geometry::stroke::{OutlineStrokeToFill, StrokeStyle};
+use pathfinder_renderer::scene::{FillRule, Paint, PathObject, PathObjectKind, Scene};
 use std::fmt::{Display, Formatter, Result as FormatResult};
 use std::mem;
 use uturtle::ast::Command;
@@ -38,6 +38,27 @@ pub struct BuiltTurtle {
     id_counter: u32,
 }
 
+/// Where the turtle ended up after running a script, for drawing it on top of the scene.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TurtlePose {
+    pub position: Point2DF32,
+    /// Heading in degrees, clockwise from the positive x axis.
+    pub direction: f32,
+    /// False if the script hid the turtle with `spritehide`.
+    pub visible: bool,
+}
+
+/// Everything that `pushstate` saves and `popstate` restores.
+#[derive(Clone, Debug)]
+struct SavedState {
+    pos_x: f32,
+    pos_y: f32,
+    direction: f32,
+    pen_down: bool,
+    pen_width: f32,
+    pen_color: (u8, u8, u8),
+}
+
 #[derive(Debug)]
 struct TurtleState {
     pos_x: f32,
@@ -46,8 +67,10 @@ struct TurtleState {
     pen_down: bool,
     positions: Vec<(f32, f32)>,
     directions: Vec<f32>,
+    states: Vec<SavedState>,
     pen_width: f32,
     pen_color: (u8, u8, u8),
+    sprite_visible: bool,
     bounds: RectF32,
 }
 
@@ -60,11 +83,33 @@ impl TurtleState {
             pen_down: false,
             positions: Vec::new(),
             directions: Vec::new(),
+            states: Vec::new(),
             pen_width: 1.0,
             pen_color: (0, 0, 0),
+            sprite_visible: true,
             bounds: RectF32::new(Point2DF32::new(0.0, 0.0), Point2DF32::new(0.0, 0.0)),
         }
     }
+
+    fn save(&self) -> SavedState {
+        SavedState {
+            pos_x: self.pos_x,
+            pos_y: self.pos_y,
+            direction: self.direction,
+            pen_down: self.pen_down,
+            pen_width: self.pen_width,
+            pen_color: self.pen_color,
+        }
+    }
+
+    fn restore(&mut self, saved: SavedState) {
+        self.pos_x = saved.pos_x;
+        self.pos_y = saved.pos_y;
+        self.direction = saved.direction;
+        self.pen_down = saved.pen_down;
+        self.pen_width = saved.pen_width;
+        self.pen_color = saved.pen_color;
+    }
 }
 
 bitflags! {
@@ -74,6 +119,7 @@ bitflags! {
         const ERR_UNHANDLED_COMMAND       = 0x0001;
         const ERR_POPLOC_EMPTY_STACK       = 0x0002;
         const ERR_POPROT_EMPTY_STACK       = 0x0004;
+        const ERR_POPSTATE_EMPTY_STACK     = 0x0008;
     }
 }
 
@@ -103,6 +149,7 @@ impl Display for BuildResultFlags {
             "unhandled command",
             "poploc on empty stack",
             "poprot on empty stack",
+            "popstate on empty stack",
         ];
     }
 }
@@ -125,6 +172,14 @@ impl BuiltTurtle {
         built
     }
 
+    pub fn pose(&self) -> TurtlePose {
+        TurtlePose {
+            position: Point2DF32::new(self.state.pos_x, self.state.pos_y),
+            direction: self.state.direction,
+            visible: self.state.sprite_visible,
+        }
+    }
+
     fn id(&mut self) -> u32 {
         self.id_counter += 1;
         self.id_counter
@@ -153,14 +208,7 @@ impl BuiltTurtle {
                     let (s, c) = self.state.direction.to_radians().sin_cos();
                     let to_x = self.state.pos_x + unit * c;
                     let to_y = self.state.pos_y + unit * s;
-
-                    if self.state.pen_down {
-                        self.line_to(self.state.pos_x, self.state.pos_y, to_x, to_y);
-                        self.update_bounds(to_x, to_y);
-                    }
-
-                    self.state.pos_x = to_x;
-                    self.state.pos_y = to_y;
+                    self.move_to(to_x, to_y);
                 }
                 Command::PushLoc => self
                     .state
@@ -174,7 +222,7 @@ impl BuiltTurtle {
                         }
                         None => {
                             // NOOP.
-                            eprintln!("poploc on empty stack");
+                            self.result_flags.insert(BuildResultFlags::ERR_POPLOC_EMPTY_STACK);
                         }
                     }
                 }
@@ -186,7 +234,20 @@ impl BuiltTurtle {
                         }
                         None => {
                             // NOOP.
-                            eprintln!("poprot on empty stack");
+                            self.result_flags.insert(BuildResultFlags::ERR_POPROT_EMPTY_STACK);
+                        }
+                    }
+                }
+                Command::PushState => {
+                    let saved = self.state.save();
+                    self.state.states.push(saved);
+                }
+                Command::PopState => {
+                    match self.state.states.pop() {
+                        Some(saved) => self.state.restore(saved),
+                        None => {
+                            // NOOP.
+                            self.result_flags.insert(BuildResultFlags::ERR_POPSTATE_EMPTY_STACK);
                         }
                     }
                 }
@@ -203,18 +264,34 @@ impl BuiltTurtle {
                     self.state.pos_y = *y;
                     self.update_bounds(self.state.pos_x, *y);
                 }
+                Command::MoveTo(x, y) => self.move_to(*x, *y),
+                Command::MoveToX(x) => self.move_to(*x, self.state.pos_y),
+                Command::MoveToY(y) => self.move_to(self.state.pos_x, *y),
                 Command::PenWidth(w) => {
                     self.state.pen_width = *w;
                 }
                 Command::PenColor(r, g, b) => {
                     self.state.pen_color = (*r, *g, *b);
                 }
+                Command::SpriteHide => self.state.sprite_visible = false,
+                Command::SpriteShow => self.state.sprite_visible = true,
             }
         }
 
         self.scene.view_box = self.scene.bounds;
     }
 
+    // Moves the turtle, drawing a line if the pen is down.
+    fn move_to(&mut self, to_x: f32, to_y: f32) {
+        if self.state.pen_down {
+            self.line_to(self.state.pos_x, self.state.pos_y, to_x, to_y);
+            self.update_bounds(to_x, to_y);
+        }
+
+        self.state.pos_x = to_x;
+        self.state.pos_y = to_y;
+    }
+
     fn line_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32) {
         let style = self
             .scene
@@ -231,7 +308,8 @@ impl BuiltTurtle {
 
         let outline = Outline::from_segments(segments);
 
-        let mut stroke_to_fill = OutlineStrokeToFill::new(outline, stroke_width);
+        let stroke_style = StrokeStyle { line_width: stroke_width, ..StrokeStyle::default() };
+        let mut stroke_to_fill = OutlineStrokeToFill::new(outline, stroke_style);
         stroke_to_fill.offset();
         let outline = stroke_to_fill.outline;
 
@@ -243,6 +321,8 @@ impl BuiltTurtle {
             style,
             id,
             PathObjectKind::Stroke,
+            FillRule::Winding,
+            None,
         ));
     }
 }
@@ -260,13 +340,14 @@ impl PaintExt for Paint {
 
     #[inline]
     fn from_rgb(r: u8, g: u8, b: u8) -> Paint {
-        Paint {
-            color: ColorU {
-                r: r,
-                g: g,
-                b: b,
-                a: 255,
-            },
-        }
+        Paint::from_color(ColorU {
+            r: r,
+            g: g,
+            b: b,
+            a: 255,
+        })
     }
 }
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,227 @@
+// pathfinder/turtle/src/test.rs
+//
+// Copyright © 2019 The Pathfinder Project Developers.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Geometry tests for the Turtle interpreter.
+//!
+//! Each `.golden` file in `resources/turtle` describes the expected result of running the
+//! script with the same name, in any dialect: the final turtle pose, the scene bounds, the
+//! number of objects, and the outline points of each object.
+
+use crate::BuiltTurtle;
+use pathfinder_geometry::basic::point::Point2DF32;
+use pathfinder_geometry::basic::rect::RectF32;
+use pathfinder_geometry::outline::Outline;
+use uturtle::{Dialect, Parser as TurtleParser};
+
+const EPSILON: f32 = 0.001;
+
+static SQUARE_TURTLE: &'static str = include_str!("../../resources/turtle/square.turtle");
+static SQUARE_GOLDEN: &'static str = include_str!("../../resources/turtle/square.golden");
+static BRANCH_TURTLE: &'static str = include_str!("../../resources/turtle/branch.turtle");
+static BRANCH_GOLDEN: &'static str = include_str!("../../resources/turtle/branch.golden");
+static SETXY_LOGO: &'static str = include_str!("../../resources/turtle/setxy.logo");
+static SETXY_GOLDEN: &'static str = include_str!("../../resources/turtle/setxy.golden");
+static GOTO_PYTHON: &'static str = include_str!("../../resources/turtle/goto.py");
+static GOTO_GOLDEN: &'static str = include_str!("../../resources/turtle/goto.golden");
+
+fn build(script: &str) -> BuiltTurtle {
+    BuiltTurtle::from_ast(TurtleParser::new().parse(script).unwrap())
+}
+
+fn assert_approx_eq(actual: f32, expected: f32, what: &str) {
+    assert!((actual - expected).abs() <= EPSILON,
+            "{}: expected {}, found {}",
+            what,
+            expected,
+            actual);
+}
+
+fn assert_point_approx_eq(actual: Point2DF32, expected: Point2DF32, what: &str) {
+    assert!((actual - expected).length() <= EPSILON,
+            "{}: expected {:?}, found {:?}",
+            what,
+            expected,
+            actual);
+}
+
+fn assert_rect_approx_eq(actual: RectF32, expected: RectF32, what: &str) {
+    assert_point_approx_eq(actual.origin(), expected.origin(), what);
+    assert_point_approx_eq(actual.lower_right(), expected.lower_right(), what);
+}
+
+fn outline_points(outline: &Outline) -> Vec<Point2DF32> {
+    let mut points = vec![];
+    for contour in &outline.contours {
+        for point_index in 0..contour.len() {
+            points.push(contour.position_of(point_index));
+        }
+    }
+    points
+}
+
+fn parse_points(values: &[f32]) -> Vec<Point2DF32> {
+    assert!(values.len() % 2 == 0, "odd number of coordinates in golden file");
+    values.chunks(2).map(|xy| Point2DF32::new(xy[0], xy[1])).collect()
+}
+
+fn check_golden(name: &str, dialect: Dialect, script: &str, golden: &str) {
+    let built = BuiltTurtle::from_ast(uturtle::parse(script, dialect).unwrap());
+
+    for (line_index, line) in golden.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let what = format!("{}.golden:{}", name, line_index + 1);
+        let mut words = line.split_whitespace();
+        let key = words.next().unwrap();
+        let words: Vec<&str> = words.collect();
+        let numbers = || -> Vec<f32> { words.iter().map(|word| word.parse().unwrap()).collect() };
+
+        match key {
+            "position" => {
+                let expected = parse_points(&numbers());
+                let actual = Point2DF32::new(built.state.pos_x, built.state.pos_y);
+                assert_point_approx_eq(actual, expected[0], &what);
+            }
+            "direction" => assert_approx_eq(built.state.direction, numbers()[0], &what),
+            "pen" => assert_eq!(built.state.pen_down, words[0] == "down", "{}", what),
+            "objects" => {
+                let expected: usize = words[0].parse().unwrap();
+                assert_eq!(built.scene.objects.len(), expected, "{}", what);
+            }
+            "bounds" => {
+                let expected = parse_points(&numbers());
+                let expected = RectF32::from_points(expected[0], expected[1]);
+                assert_rect_approx_eq(built.scene.bounds, expected, &what);
+            }
+            "outline" => {
+                let numbers = numbers();
+                let object = &built.scene.objects[numbers[0] as usize];
+                let expected = parse_points(&numbers[1..]);
+                let actual = outline_points(object.outline());
+                assert_eq!(actual.len(), expected.len(), "{}: point count", what);
+                for (&actual, &expected) in actual.iter().zip(expected.iter()) {
+                    assert_point_approx_eq(actual, expected, &what);
+                }
+            }
+            _ => panic!("{}: unknown golden key `{}`", what, key),
+        }
+    }
+}
+
+#[test]
+fn test_golden_square() {
+    check_golden("square", Dialect::Turtle, SQUARE_TURTLE, SQUARE_GOLDEN);
+}
+
+#[test]
+fn test_golden_branch() {
+    check_golden("branch", Dialect::Turtle, BRANCH_TURTLE, BRANCH_GOLDEN);
+}
+
+#[test]
+fn test_golden_logo_absolute_moves() {
+    check_golden("setxy", Dialect::Logo, SETXY_LOGO, SETXY_GOLDEN);
+}
+
+#[test]
+fn test_golden_python_absolute_moves() {
+    check_golden("goto", Dialect::Python, GOTO_PYTHON, GOTO_GOLDEN);
+}
+
+#[test]
+fn test_pen_up_draws_nothing() {
+    let built = build("forward 10 turnright forward 10");
+    assert!(built.scene.objects.is_empty());
+    assert_point_approx_eq(Point2DF32::new(built.state.pos_x, built.state.pos_y),
+                           Point2DF32::new(10.0, 10.0),
+                           "position");
+}
+
+#[test]
+fn test_direction_wraps() {
+    let built = build("turnleft 30");
+    assert_approx_eq(built.state.direction, 330.0, "turnleft");
+    let built = build("direction -450");
+    assert_approx_eq(built.state.direction, 270.0, "direction");
+    let built = build("turnright 200 turnright 200");
+    assert_approx_eq(built.state.direction, 40.0, "turnright");
+}
+
+#[test]
+fn test_hairline_stroke_width() {
+    let built = build("penwidth 0 pendown forward 10");
+    assert_eq!(built.scene.objects.len(), 1);
+    let bounds = built.scene.objects[0].outline().bounds();
+    assert_approx_eq(bounds.size().y(), crate::HAIRLINE_STROKE_WIDTH, "stroke width");
+}
+
+#[test]
+fn test_go_updates_bounds_without_drawing() {
+    let built = build("go 30 -20 goy 40");
+    assert!(built.scene.objects.is_empty());
+    assert_rect_approx_eq(built.scene.bounds,
+                          RectF32::from_points(Point2DF32::new(0.0, -20.0),
+                                               Point2DF32::new(30.0, 40.0)),
+                          "bounds");
+    assert_rect_approx_eq(built.scene.view_box, built.scene.bounds, "view box");
+}
+
+#[test]
+fn test_pop_on_empty_stack_keeps_pose() {
+    let built = build("go 5 5 pushloc go 1 1 poploc poploc");
+    assert_point_approx_eq(Point2DF32::new(built.state.pos_x, built.state.pos_y),
+                           Point2DF32::new(5.0, 5.0),
+                           "position");
+    assert!(built.result_flags.contains(crate::BuildResultFlags::ERR_POPLOC_EMPTY_STACK));
+
+    let built = build("direction 45 pushrot turnleft poprot poprot");
+    assert_approx_eq(built.state.direction, 45.0, "direction");
+    assert!(built.result_flags.contains(crate::BuildResultFlags::ERR_POPROT_EMPTY_STACK));
+}
+
+#[test]
+fn test_reset_clears_scene() {
+    let built = build("pendown forward 10 reset forward 5");
+    assert!(built.scene.objects.is_empty());
+    assert_point_approx_eq(Point2DF32::new(built.state.pos_x, built.state.pos_y),
+                           Point2DF32::new(5.0, 0.0),
+                           "position");
+    assert!(!built.state.pen_down);
+}
+
+#[test]
+fn test_pose() {
+    let built = build("go 3 4 turnleft spritehide");
+    let pose = built.pose();
+    assert_point_approx_eq(pose.position, Point2DF32::new(3.0, 4.0), "position");
+    assert_approx_eq(pose.direction, 270.0, "direction");
+    assert!(!pose.visible);
+    assert!(build("spritehide spriteshow").pose().visible);
+}
+
+#[test]
+fn test_pushstate_restores_pen() {
+    let built = build("go 1 2 direction 45 pendown penwidth 3 pencolor 255,0,0 pushstate \
+                       penup penwidth 1 pencolor 0,0,255 go 7 7 turnleft popstate");
+    assert_point_approx_eq(Point2DF32::new(built.state.pos_x, built.state.pos_y),
+                           Point2DF32::new(1.0, 2.0),
+                           "position");
+    assert_approx_eq(built.state.direction, 45.0, "direction");
+    assert!(built.state.pen_down);
+    assert_approx_eq(built.state.pen_width, 3.0, "pen width");
+    assert_eq!(built.state.pen_color, (255, 0, 0));
+    assert!(built.result_flags.is_empty());
+
+    let built = build("pushstate popstate popstate");
+    assert!(built.result_flags.contains(crate::BuildResultFlags::ERR_POPSTATE_EMPTY_STACK));
+}
@@ -0,0 +1,279 @@
+// pathfinder/utils/svg-emitter/src/lib.rs
+//
+// Copyright © 2019 The Pathfinder Project Developers.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! The traversal shared by the utilities that convert SVGs into code for other rasterizers.
+//!
+//! The tree is walked once, with transforms accumulated and paint servers resolved, and each
+//! backend turns the resulting calls into its own API.
+
+use std::collections::HashMap;
+use usvg::{Color, FillRule, Node, NodeExt, NodeKind, Paint, PathSegment, SpreadMethod, Stop};
+use usvg::{Stroke, Transform, Tree, Units, Visibility};
+
+/// Nodes in `<defs>`, by ID.
+type Defs = HashMap<String, Node>;
+
+/// Receives the drawing operations of an SVG, in painter's order.
+pub trait Backend {
+    /// Called first, with the size of the document.
+    fn begin(&mut self, width: f64, height: f64);
+    fn end(&mut self);
+
+    /// Starts a group whose contents are composited as a whole with the given opacity.
+    fn begin_group(&mut self, opacity: f64);
+    fn end_group(&mut self, opacity: f64);
+
+    /// Sets the current path. `transform` maps the user space of the path to the canvas.
+    fn begin_path(&mut self, transform: &Transform, segments: &[PathSegment]);
+    fn fill(&mut self, paint: &ResolvedPaint, opacity: f64, fill_rule: FillRule);
+    /// Strokes the current path. The paint of `stroke` has already been resolved into `paint`.
+    fn stroke(&mut self, paint: &ResolvedPaint, stroke: &Stroke);
+    fn end_path(&mut self);
+
+    /// Notes something that couldn't be drawn.
+    fn unsupported(&mut self, description: &str);
+}
+
+/// A paint with any reference to a paint server resolved.
+pub enum ResolvedPaint {
+    Color(Color),
+    Gradient(Gradient),
+}
+
+pub struct Gradient {
+    pub geometry: GradientGeometry,
+    pub stops: Vec<Stop>,
+    pub spread_method: SpreadMethod,
+    /// Maps gradient space to the user space of the path, including any `objectBoundingBox`
+    /// mapping.
+    pub transform: Transform,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum GradientGeometry {
+    Linear { x1: f64, y1: f64, x2: f64, y2: f64 },
+    /// The gradient starts from a point at the focus, `(fx, fy)`.
+    Radial { cx: f64, cy: f64, r: f64, fx: f64, fy: f64 },
+}
+
+/// Walks the tree, sending everything it draws to `backend`.
+pub fn emit<B>(tree: &Tree, backend: &mut B) where B: Backend {
+    let root = tree.root();
+    let defs = collect_defs(&root);
+    match *root.borrow() {
+        NodeKind::Svg(ref svg) => {
+            backend.begin(svg.size.width, svg.size.height);
+            for kid in root.children() {
+                process_node(&kid, &defs, &Transform::default(), backend);
+            }
+            backend.end();
+        }
+        _ => unreachable!(),
+    };
+}
+
+// `transform` maps the user space of the parent of `node` to the canvas.
+fn process_node<B>(node: &Node, defs: &Defs, transform: &Transform, backend: &mut B)
+                   where B: Backend {
+    let transform = concat_transforms(transform, &node.transform());
+
+    match *node.borrow() {
+        NodeKind::Group(ref group) => {
+            // The contents are still drawn, just without the effect.
+            if let Some(ref clip_path) = group.clip_path {
+                backend.unsupported(&format!("clip-path #{} on group #{}", clip_path, node.id()));
+            }
+            if let Some(ref mask) = group.mask {
+                backend.unsupported(&format!("mask #{} on group #{}", mask, node.id()));
+            }
+            if let Some(ref filter) = group.filter {
+                backend.unsupported(&format!("filter #{} on group #{}", filter, node.id()));
+            }
+            if let Some(opacity) = group.opacity {
+                backend.begin_group(opacity.value());
+            }
+            for kid in node.children() {
+                process_node(&kid, defs, &transform, backend)
+            }
+            if let Some(opacity) = group.opacity {
+                backend.end_group(opacity.value());
+            }
+        }
+        NodeKind::Path(ref path) if path.visibility == Visibility::Visible => {
+            backend.begin_path(&transform, &path.segments);
+            if let Some(ref fill) = path.fill {
+                match resolve_paint(&fill.paint, defs, &path.segments) {
+                    Ok(paint) => backend.fill(&paint, fill.opacity.value(), fill.rule),
+                    Err(description) => backend.unsupported(&description),
+                }
+            }
+            if let Some(ref stroke) = path.stroke {
+                match resolve_paint(&stroke.paint, defs, &path.segments) {
+                    Ok(paint) => backend.stroke(&paint, stroke),
+                    Err(description) => backend.unsupported(&description),
+                }
+            }
+            backend.end_path();
+        }
+        NodeKind::Image(..) => backend.unsupported(&format!("image #{}", node.id())),
+        NodeKind::Text(..) => backend.unsupported(&format!("text #{}", node.id())),
+        NodeKind::Svg(..) => backend.unsupported(&format!("nested svg #{}", node.id())),
+        // Definitions are only drawn where they're used, and hidden paths not at all.
+        _ => {}
+    }
+}
+
+// Returns a description of the paint if it isn't supported.
+fn resolve_paint(paint: &Paint, defs: &Defs, segments: &[PathSegment])
+                 -> Result<ResolvedPaint, String> {
+    let id = match *paint {
+        Paint::Color(color) => return Ok(ResolvedPaint::Color(color)),
+        Paint::Link(ref id) => id,
+    };
+    let node = match defs.get(id) {
+        Some(node) => node.borrow(),
+        None => return Err(format!("missing paint server #{}", id)),
+    };
+
+    let (geometry, base) = match *node {
+        NodeKind::LinearGradient(ref gradient) => {
+            let geometry = GradientGeometry::Linear {
+                x1: gradient.x1,
+                y1: gradient.y1,
+                x2: gradient.x2,
+                y2: gradient.y2,
+            };
+            (geometry, &gradient.base)
+        }
+        NodeKind::RadialGradient(ref gradient) => {
+            let geometry = GradientGeometry::Radial {
+                cx: gradient.cx,
+                cy: gradient.cy,
+                r: gradient.r,
+                fx: gradient.fx,
+                fy: gradient.fy,
+            };
+            (geometry, &gradient.base)
+        }
+        // TODO(pcwalton): Patterns.
+        _ => return Err(format!("paint server #{}", id)),
+    };
+
+    // Gradient space -> (bounding box space ->) user space.
+    let mut transform = base.transform;
+    if let Units::ObjectBoundingBox = base.units {
+        transform = concat_transforms(&bounding_box_transform(segments), &transform);
+    }
+
+    Ok(ResolvedPaint::Gradient(Gradient {
+        geometry,
+        stops: base.stops.clone(),
+        spread_method: base.spread_method,
+        transform,
+    }))
+}
+
+fn collect_defs(root: &Node) -> Defs {
+    let mut defs = Defs::new();
+    for kid in root.children() {
+        if let NodeKind::Defs = *kid.borrow() {
+            for def in kid.children() {
+                defs.insert(def.id().to_string(), def.clone());
+            }
+        }
+    }
+    defs
+}
+
+/// Returns a transform that applies `inner`, then `outer`.
+pub fn concat_transforms(outer: &Transform, inner: &Transform) -> Transform {
+    Transform {
+        a: outer.a * inner.a + outer.c * inner.b,
+        b: outer.b * inner.a + outer.d * inner.b,
+        c: outer.a * inner.c + outer.c * inner.d,
+        d: outer.b * inner.c + outer.d * inner.d,
+        e: outer.a * inner.e + outer.c * inner.f + outer.e,
+        f: outer.b * inner.e + outer.d * inner.f + outer.f,
+    }
+}
+
+/// Converts an opacity to an 8-bit alpha value.
+pub fn alpha(opacity: f64) -> u8 {
+    (opacity.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+// Returns the transform that maps the unit square to the tight bounding box of the path, for
+// `objectBoundingBox` units.
+fn bounding_box_transform(segments: &[PathSegment]) -> Transform {
+    let (mut min, mut max) = ([f64::INFINITY; 2], [f64::NEG_INFINITY; 2]);
+    let mut add_point = |point: [f64; 2]| {
+        for axis in 0..2 {
+            min[axis] = min[axis].min(point[axis]);
+            max[axis] = max[axis].max(point[axis]);
+        }
+    };
+
+    let mut last = [0.0; 2];
+    for segment in segments {
+        match *segment {
+            PathSegment::MoveTo { x, y } | PathSegment::LineTo { x, y } => {
+                last = [x, y];
+                add_point(last);
+            }
+            PathSegment::CurveTo { x1, y1, x2, y2, x, y } => {
+                let points = [last, [x1, y1], [x2, y2], [x, y]];
+                for axis in 0..2 {
+                    for t in cubic_extrema(points[0][axis],
+                                           points[1][axis],
+                                           points[2][axis],
+                                           points[3][axis]) {
+                        add_point(evaluate_cubic(&points, t));
+                    }
+                }
+                last = [x, y];
+                add_point(last);
+            }
+            PathSegment::ClosePath => {}
+        }
+    }
+
+    if min[0] > max[0] {
+        return Transform::default();
+    }
+    Transform { a: max[0] - min[0], b: 0.0, c: 0.0, d: max[1] - min[1], e: min[0], f: min[1] }
+}
+
+// Returns the parameters in (0, 1) at which the derivative of a 1D cubic Bézier curve is zero.
+fn cubic_extrema(p0: f64, p1: f64, p2: f64, p3: f64) -> Vec<f64> {
+    let (a, b, c) = (-p0 + 3.0 * p1 - 3.0 * p2 + p3, 2.0 * (p0 - 2.0 * p1 + p2), p1 - p0);
+    let roots = if a.abs() < 1e-12 {
+        if b.abs() < 1e-12 { vec![] } else { vec![-c / b] }
+    } else {
+        let discriminant = b * b - 4.0 * a * c;
+        if discriminant < 0.0 {
+            vec![]
+        } else {
+            let root = discriminant.sqrt();
+            vec![(-b + root) / (2.0 * a), (-b - root) / (2.0 * a)]
+        }
+    };
+    roots.into_iter().filter(|&t| t > 0.0 && t < 1.0).collect()
+}
+
+fn evaluate_cubic(points: &[[f64; 2]; 4], t: f64) -> [f64; 2] {
+    let u = 1.0 - t;
+    let weights = [u * u * u, 3.0 * u * u * t, 3.0 * u * t * t, t * t * t];
+    let mut result = [0.0; 2];
+    for (point, weight) in points.iter().zip(weights.iter()) {
+        result[0] += point[0] * weight;
+        result[1] += point[1] * weight;
+    }
+    result
+}
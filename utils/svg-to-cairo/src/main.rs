@@ -0,0 +1,174 @@
+// pathfinder/utils/svg-to-cairo/src/main.rs
+//
+// Copyright © 2019 The Pathfinder Project Developers.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Converts an SVG into C code that draws it with Cairo, for reference renderings.
+
+use std::env;
+use svg_emitter::{Backend, GradientGeometry, ResolvedPaint};
+use usvg::{Color, FillRule, LineCap, LineJoin, Options, PathSegment, SpreadMethod, Stroke};
+use usvg::{Transform, Tree};
+
+fn main() {
+    let input_path = env::args().nth(1).unwrap();
+    let tree = Tree::from_file(&input_path, &Options::default()).unwrap();
+    svg_emitter::emit(&tree, &mut CairoBackend);
+}
+
+struct CairoBackend;
+
+impl Backend for CairoBackend {
+    fn begin(&mut self, _: f64, _: f64) {
+        println!("#ifndef PAINT_H");
+        println!("#define PAINT_H");
+        println!("static void paint(cairo_t *cr) {{");
+        println!("    cairo_matrix_t matrix;");
+        println!("    cairo_set_source_rgb(cr, 1, 1, 1);");
+        println!("    cairo_paint(cr);");
+    }
+
+    fn end(&mut self) {
+        println!("}}");
+        println!("#endif");
+    }
+
+    fn begin_group(&mut self, _: f64) {
+        println!("    cairo_push_group(cr);");
+    }
+
+    fn end_group(&mut self, opacity: f64) {
+        println!("    cairo_pop_group_to_source(cr);");
+        println!("    cairo_paint_with_alpha(cr, {});", opacity);
+    }
+
+    fn begin_path(&mut self, transform: &Transform, segments: &[PathSegment]) {
+        println!("    cairo_matrix_init(&matrix, {});", matrix_components(transform));
+        println!("    cairo_set_matrix(cr, &matrix);");
+        for segment in segments {
+            match segment {
+                PathSegment::MoveTo { x, y } => println!("    cairo_move_to(cr, {}, {});", x, y),
+                PathSegment::LineTo { x, y } => println!("    cairo_line_to(cr, {}, {});", x, y),
+                PathSegment::CurveTo { x1, y1, x2, y2, x, y } => {
+                    println!("    cairo_curve_to(cr, {}, {}, {}, {}, {}, {});",
+                             x1, y1, x2, y2, x, y);
+                }
+                PathSegment::ClosePath => println!("    cairo_close_path(cr);"),
+            }
+        }
+    }
+
+    fn fill(&mut self, paint: &ResolvedPaint, opacity: f64, fill_rule: FillRule) {
+        let fill_rule = match fill_rule {
+            FillRule::NonZero => "CAIRO_FILL_RULE_WINDING",
+            FillRule::EvenOdd => "CAIRO_FILL_RULE_EVEN_ODD",
+        };
+        set_source(paint, opacity);
+        println!("    cairo_set_fill_rule(cr, {});", fill_rule);
+        println!("    cairo_fill_preserve(cr);");
+    }
+
+    fn stroke(&mut self, paint: &ResolvedPaint, stroke: &Stroke) {
+        let cap = match stroke.linecap {
+            LineCap::Butt => "CAIRO_LINE_CAP_BUTT",
+            LineCap::Round => "CAIRO_LINE_CAP_ROUND",
+            LineCap::Square => "CAIRO_LINE_CAP_SQUARE",
+        };
+        let join = match stroke.linejoin {
+            LineJoin::Miter => "CAIRO_LINE_JOIN_MITER",
+            LineJoin::Round => "CAIRO_LINE_JOIN_ROUND",
+            LineJoin::Bevel => "CAIRO_LINE_JOIN_BEVEL",
+        };
+        set_source(paint, stroke.opacity.value());
+        println!("    cairo_set_line_width(cr, {});", stroke.width.value());
+        println!("    cairo_set_line_cap(cr, {});", cap);
+        println!("    cairo_set_line_join(cr, {});", join);
+        println!("    cairo_set_miter_limit(cr, {});", stroke.miterlimit);
+        if let Some(ref dash_array) = stroke.dasharray {
+            let dashes: Vec<_> = dash_array.iter().map(|length| length.to_string()).collect();
+            println!("    {{");
+            println!("        double dashes[] = {{ {} }};", dashes.join(", "));
+            println!("        cairo_set_dash(cr, dashes, {}, {});",
+                     dashes.len(),
+                     stroke.dashoffset);
+            println!("    }}");
+        }
+        println!("    cairo_stroke_preserve(cr);");
+        if stroke.dasharray.is_some() {
+            println!("    cairo_set_dash(cr, NULL, 0, 0);");
+        }
+    }
+
+    fn end_path(&mut self) {
+        println!("    cairo_new_path(cr);");
+    }
+
+    fn unsupported(&mut self, description: &str) {
+        println!("    // Unsupported: {}", description);
+    }
+}
+
+fn set_source(paint: &ResolvedPaint, opacity: f64) {
+    let gradient = match *paint {
+        ResolvedPaint::Color(color) => {
+            println!("    cairo_set_source_rgba(cr, {});", rgba_components(color, opacity));
+            return;
+        }
+        ResolvedPaint::Gradient(ref gradient) => gradient,
+    };
+
+    let extend = match gradient.spread_method {
+        SpreadMethod::Pad => "CAIRO_EXTEND_PAD",
+        SpreadMethod::Reflect => "CAIRO_EXTEND_REFLECT",
+        SpreadMethod::Repeat => "CAIRO_EXTEND_REPEAT",
+    };
+
+    println!("    {{");
+    match gradient.geometry {
+        GradientGeometry::Linear { x1, y1, x2, y2 } => {
+            println!("        cairo_pattern_t *pattern = cairo_pattern_create_linear({}, {}, \
+                      {}, {});",
+                     x1, y1, x2, y2);
+        }
+        GradientGeometry::Radial { cx, cy, r, fx, fy } => {
+            println!("        cairo_pattern_t *pattern = cairo_pattern_create_radial({}, {}, 0, \
+                      {}, {}, {});",
+                     fx, fy, cx, cy, r);
+        }
+    }
+    // Cairo has no opacity for sources, so it's folded into the stops.
+    for stop in &gradient.stops {
+        println!("        cairo_pattern_add_color_stop_rgba(pattern, {}, {});",
+                 stop.offset.value(),
+                 rgba_components(stop.color, stop.opacity.value() * opacity));
+    }
+    println!("        cairo_pattern_set_extend(pattern, {});", extend);
+
+    // Pattern matrices map user space to pattern space, the inverse of the gradient transform.
+    println!("        cairo_matrix_t pattern_matrix;");
+    println!("        cairo_matrix_init(&pattern_matrix, {});",
+             matrix_components(&gradient.transform));
+    println!("        cairo_matrix_invert(&pattern_matrix);");
+    println!("        cairo_pattern_set_matrix(pattern, &pattern_matrix);");
+    println!("        cairo_set_source(cr, pattern);");
+    println!("        cairo_pattern_destroy(pattern);");
+    println!("    }}");
+}
+
+fn matrix_components(transform: &Transform) -> String {
+    format!("{}, {}, {}, {}, {}, {}",
+            transform.a, transform.b, transform.c, transform.d, transform.e, transform.f)
+}
+
+fn rgba_components(color: Color, opacity: f64) -> String {
+    format!("{}, {}, {}, {}",
+            color.red as f64 / 255.0,
+            color.green as f64 / 255.0,
+            color.blue as f64 / 255.0,
+            opacity)
+}
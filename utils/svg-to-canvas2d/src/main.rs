@@ -0,0 +1,230 @@
+// pathfinder/utils/svg-to-canvas2d/src/main.rs
+//
+// Copyright © 2019 The Pathfinder Project Developers.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Converts an SVG into JavaScript that draws it with the HTML Canvas 2D API, for reference
+//! renderings.
+
+use std::env;
+use svg_emitter::{Backend, Gradient, GradientGeometry, ResolvedPaint};
+use usvg::{Color, FillRule, LineCap, LineJoin, Options, PathSegment, SpreadMethod, Stroke};
+use usvg::{Transform, Tree};
+
+fn main() {
+    let input_path = env::args().nth(1).unwrap();
+    let tree = Tree::from_file(&input_path, &Options::default()).unwrap();
+    svg_emitter::emit(&tree, &mut Canvas2DBackend);
+}
+
+struct Canvas2DBackend;
+
+impl Backend for Canvas2DBackend {
+    fn begin(&mut self, width: f64, height: f64) {
+        println!("function paint(ctx) {{");
+        // Groups with opacity are drawn into canvases of their own, which are kept here.
+        println!("    const layers = [];");
+        println!("    ctx.setTransform(1, 0, 0, 1, 0, 0);");
+        println!("    ctx.fillStyle = \"white\";");
+        println!("    ctx.fillRect(0, 0, {}, {});", width, height);
+    }
+
+    fn end(&mut self) {
+        println!("}}");
+    }
+
+    fn begin_group(&mut self, _: f64) {
+        println!("    layers.push(ctx);");
+        println!("    {{");
+        println!("        const layer = document.createElement(\"canvas\");");
+        println!("        layer.width = ctx.canvas.width;");
+        println!("        layer.height = ctx.canvas.height;");
+        println!("        ctx = layer.getContext(\"2d\");");
+        println!("    }}");
+    }
+
+    fn end_group(&mut self, opacity: f64) {
+        println!("    {{");
+        println!("        const layer = ctx.canvas;");
+        println!("        ctx = layers.pop();");
+        println!("        ctx.save();");
+        println!("        ctx.setTransform(1, 0, 0, 1, 0, 0);");
+        println!("        ctx.globalAlpha = {};", opacity);
+        println!("        ctx.drawImage(layer, 0, 0);");
+        println!("        ctx.restore();");
+        println!("    }}");
+    }
+
+    fn begin_path(&mut self, transform: &Transform, segments: &[PathSegment]) {
+        println!("    ctx.setTransform({}, {}, {}, {}, {}, {});",
+                 transform.a, transform.b, transform.c, transform.d, transform.e, transform.f);
+        println!("    ctx.beginPath();");
+        for segment in segments {
+            match segment {
+                PathSegment::MoveTo { x, y } => println!("    ctx.moveTo({}, {});", x, y),
+                PathSegment::LineTo { x, y } => println!("    ctx.lineTo({}, {});", x, y),
+                PathSegment::CurveTo { x1, y1, x2, y2, x, y } => {
+                    println!("    ctx.bezierCurveTo({}, {}, {}, {}, {}, {});",
+                             x1, y1, x2, y2, x, y);
+                }
+                PathSegment::ClosePath => println!("    ctx.closePath();"),
+            }
+        }
+    }
+
+    fn fill(&mut self, paint: &ResolvedPaint, opacity: f64, fill_rule: FillRule) {
+        let fill_rule = match fill_rule {
+            FillRule::NonZero => "nonzero",
+            FillRule::EvenOdd => "evenodd",
+        };
+        draw(paint, opacity, "fill", &format!("ctx.fill(\"{}\");", fill_rule));
+    }
+
+    fn stroke(&mut self, paint: &ResolvedPaint, stroke: &Stroke) {
+        let cap = match stroke.linecap {
+            LineCap::Butt => "butt",
+            LineCap::Round => "round",
+            LineCap::Square => "square",
+        };
+        let join = match stroke.linejoin {
+            LineJoin::Miter => "miter",
+            LineJoin::Round => "round",
+            LineJoin::Bevel => "bevel",
+        };
+        println!("    ctx.lineWidth = {};", stroke.width.value());
+        println!("    ctx.lineCap = \"{}\";", cap);
+        println!("    ctx.lineJoin = \"{}\";", join);
+        println!("    ctx.miterLimit = {};", stroke.miterlimit);
+        if let Some(ref dash_array) = stroke.dasharray {
+            let dash_array: Vec<_> = dash_array.iter().map(|length| length.to_string()).collect();
+            println!("    ctx.setLineDash([{}]);", dash_array.join(", "));
+            println!("    ctx.lineDashOffset = {};", stroke.dashoffset);
+        }
+        draw(paint, stroke.opacity.value(), "stroke", "ctx.stroke();");
+        if stroke.dasharray.is_some() {
+            println!("    ctx.setLineDash([]);");
+        }
+    }
+
+    fn end_path(&mut self) {}
+
+    fn unsupported(&mut self, description: &str) {
+        println!("    // Unsupported: {}", description);
+    }
+}
+
+// Sets the `fillStyle` or `strokeStyle` of the context, named by `style`, to the paint and runs
+// `draw_call` with it.
+fn draw(paint: &ResolvedPaint, opacity: f64, style: &str, draw_call: &str) {
+    let gradient = match *paint {
+        ResolvedPaint::Color(color) => {
+            println!("    ctx.{}Style = \"{}\";", style, rgba(color, opacity));
+            println!("    {}", draw_call);
+            return;
+        }
+        ResolvedPaint::Gradient(ref gradient) => gradient,
+    };
+
+    // FIXME(pcwalton): Canvas gradients always pad.
+    match gradient.spread_method {
+        SpreadMethod::Pad => {}
+        SpreadMethod::Reflect | SpreadMethod::Repeat => {
+            println!("    // Unsupported: spreadMethod other than pad");
+        }
+    }
+
+    // Canvas gradients have no transform of their own, but they're placed in the user space that
+    // is current when drawing, while the path was fixed when it was built. Where possible, the
+    // gradient is moved into the user space of the path instead, since transforming the context
+    // would scale the line width of strokes too.
+    let geometry = user_space_geometry(gradient);
+    if geometry.is_none() && style == "stroke" {
+        println!("    // Unsupported: stroke gradient that can't be mapped into user space");
+        return;
+    }
+
+    println!("    {{");
+    match geometry.unwrap_or(gradient.geometry) {
+        GradientGeometry::Linear { x1, y1, x2, y2 } => {
+            println!("        const gradient = ctx.createLinearGradient({}, {}, {}, {});",
+                     x1, y1, x2, y2);
+        }
+        GradientGeometry::Radial { cx, cy, r, fx, fy } => {
+            println!("        const gradient = ctx.createRadialGradient({}, {}, 0, {}, {}, {});",
+                     fx, fy, cx, cy, r);
+        }
+    }
+    for stop in &gradient.stops {
+        println!("        gradient.addColorStop({}, \"{}\");",
+                 stop.offset.value(),
+                 rgba(stop.color, stop.opacity.value() * opacity));
+    }
+
+    if geometry.is_some() {
+        println!("        ctx.{}Style = gradient;", style);
+        println!("        {}", draw_call);
+    } else {
+        let transform = &gradient.transform;
+        println!("        ctx.save();");
+        println!("        ctx.transform({}, {}, {}, {}, {}, {});",
+                 transform.a, transform.b, transform.c, transform.d, transform.e, transform.f);
+        println!("        ctx.{}Style = gradient;", style);
+        println!("        {}", draw_call);
+        println!("        ctx.restore();");
+    }
+    println!("    }}");
+}
+
+// Returns the geometry of the gradient in the user space of the path, or `None` if the gradient
+// transform can't be folded into it. Affine transforms keep linear gradients linear, but radial
+// gradients stay circular only under similarities.
+fn user_space_geometry(gradient: &Gradient) -> Option<GradientGeometry> {
+    let t = &gradient.transform;
+    let det = t.a * t.d - t.b * t.c;
+    if det == 0.0 {
+        return None;
+    }
+    let map = |x: f64, y: f64| (t.a * x + t.c * y + t.e, t.b * x + t.d * y + t.f);
+
+    match gradient.geometry {
+        GradientGeometry::Linear { x1, y1, x2, y2 } => {
+            let (dx, dy) = (x2 - x1, y2 - y1);
+            let (x1, y1) = map(x1, y1);
+            let length_squared = dx * dx + dy * dy;
+            if length_squared == 0.0 {
+                return Some(GradientGeometry::Linear { x1, y1, x2: x1, y2: y1 });
+            }
+
+            // The gradient is constant along lines perpendicular to the normal, which is the
+            // direction vector mapped by the inverse transpose of the transform.
+            let normal = ((t.d * dx - t.b * dy) / (det * length_squared),
+                          (t.a * dy - t.c * dx) / (det * length_squared));
+            let normal_length_squared = normal.0 * normal.0 + normal.1 * normal.1;
+            Some(GradientGeometry::Linear {
+                x1,
+                y1,
+                x2: x1 + normal.0 / normal_length_squared,
+                y2: y1 + normal.1 / normal_length_squared,
+            })
+        }
+        GradientGeometry::Radial { cx, cy, r, fx, fy } => {
+            let epsilon = 1e-9 * (t.a.abs() + t.b.abs() + t.c.abs() + t.d.abs());
+            let is_similarity = ((t.a - t.d).abs() <= epsilon && (t.b + t.c).abs() <= epsilon) ||
+                ((t.a + t.d).abs() <= epsilon && (t.b - t.c).abs() <= epsilon);
+            if !is_similarity {
+                return None;
+            }
+            let ((cx, cy), (fx, fy)) = (map(cx, cy), map(fx, fy));
+            Some(GradientGeometry::Radial { cx, cy, r: r * det.abs().sqrt(), fx, fy })
+        }
+    }
+}
+
+fn rgba(color: Color, opacity: f64) -> String {
+    format!("rgba({}, {}, {}, {})", color.red, color.green, color.blue, opacity)
+}
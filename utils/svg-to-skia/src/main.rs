@@ -8,80 +8,192 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
+//! Converts an SVG into C++ code that draws it with Skia, for reference renderings.
+
 use std::env;
-use usvg::{Node, NodeKind, Options, Paint, PathSegment, Tree};
+use svg_emitter::{Backend, GradientGeometry, ResolvedPaint, alpha};
+use usvg::{Color, FillRule, LineCap, LineJoin, Options, PathSegment, SpreadMethod, Stroke};
+use usvg::{Transform, Tree};
 
 fn main() {
-    let input_path = env::args().skip(1).next().unwrap();
+    let input_path = env::args().nth(1).unwrap();
     let tree = Tree::from_file(&input_path, &Options::default()).unwrap();
+    svg_emitter::emit(&tree, &mut SkiaBackend);
+}
+
+struct SkiaBackend;
+
+impl Backend for SkiaBackend {
+    fn begin(&mut self, _: f64, _: f64) {
+        println!("#ifndef PAINT_H");
+        println!("#define PAINT_H");
+        println!("static void paint(SkCanvas *canvas) {{");
+        println!("    SkPaint paint;");
+        println!("    SkPath path;");
+        println!("    paint.setAntiAlias(true);");
+        println!("    canvas->clear(SK_ColorWHITE);");
+    }
 
-    println!("#ifndef PAINT_H");
-    println!("#define PAINT_H");
-    println!("static void paint(SkCanvas *canvas) {{");
-    println!("    SkPaint paint;");
-    println!("    SkPath path;");
-    println!("    paint.setAntiAlias(true);");
-    println!("    canvas->clear(SK_ColorWHITE);");
-
-    let root = &tree.root();
-    match *root.borrow() {
-        NodeKind::Svg(_) => {
-            for kid in root.children() {
-                process_node(&kid);
+    fn end(&mut self) {
+        println!("}}");
+        println!("#endif");
+    }
+
+    fn begin_group(&mut self, opacity: f64) {
+        println!("    canvas->saveLayerAlpha(nullptr, {});", alpha(opacity));
+    }
+
+    fn end_group(&mut self, _: f64) {
+        println!("    canvas->restore();");
+    }
+
+    fn begin_path(&mut self, transform: &Transform, segments: &[PathSegment]) {
+        println!("    canvas->setMatrix({});", matrix(transform));
+        for segment in segments {
+            match segment {
+                PathSegment::MoveTo { x, y } => println!("    path.moveTo({}, {});", x, y),
+                PathSegment::LineTo { x, y } => println!("    path.lineTo({}, {});", x, y),
+                PathSegment::CurveTo { x1, y1, x2, y2, x, y } => {
+                    println!("    path.cubicTo({}, {}, {}, {}, {}, {});", x1, y1, x2, y2, x, y);
+                }
+                PathSegment::ClosePath => println!("    path.close();"),
             }
         }
-        _ => unreachable!(),
     }
 
-    println!("}}");
-    println!("#endif");
-}
+    fn fill(&mut self, paint: &ResolvedPaint, opacity: f64, fill_rule: FillRule) {
+        let fill_type = match fill_rule {
+            FillRule::NonZero => "SkPath::kWinding_FillType",
+            FillRule::EvenOdd => "SkPath::kEvenOdd_FillType",
+        };
+        println!("    path.setFillType({});", fill_type);
+        set_paint(paint, opacity);
+        println!("    paint.setStyle(SkPaint::kFill_Style);");
+        println!("    canvas->drawPath(path, paint);");
+    }
 
-fn process_node(node: &Node) {
-    match *node.borrow() {
-        NodeKind::Group(_) => {
-            for kid in node.children() {
-                process_node(&kid)
-            }
+    fn stroke(&mut self, paint: &ResolvedPaint, stroke: &Stroke) {
+        let cap = match stroke.linecap {
+            LineCap::Butt => "SkPaint::kButt_Cap",
+            LineCap::Round => "SkPaint::kRound_Cap",
+            LineCap::Square => "SkPaint::kSquare_Cap",
+        };
+        let join = match stroke.linejoin {
+            LineJoin::Miter => "SkPaint::kMiter_Join",
+            LineJoin::Round => "SkPaint::kRound_Join",
+            LineJoin::Bevel => "SkPaint::kBevel_Join",
+        };
+        set_paint(paint, stroke.opacity.value());
+        println!("    paint.setStrokeWidth({});", stroke.width.value());
+        println!("    paint.setStrokeCap({});", cap);
+        println!("    paint.setStrokeJoin({});", join);
+        println!("    paint.setStrokeMiter({});", stroke.miterlimit);
+        println!("    paint.setStyle(SkPaint::kStroke_Style);");
+        if let Some(ref dash_array) = stroke.dasharray {
+            println!("    {{");
+            println!("        SkScalar intervals[] = {{ {} }};", join_numbers(dash_array));
+            println!("        paint.setPathEffect(SkDashPathEffect::Make(intervals, {}, {}));",
+                     dash_array.len(),
+                     stroke.dashoffset);
+            println!("    }}");
         }
-        NodeKind::Path(ref path) => {
-            for segment in path.segments.iter() {
-                match segment {
-                    PathSegment::MoveTo { x, y } => println!("    path.moveTo({}, {});", x, y),
-                    PathSegment::LineTo { x, y } => println!("    path.lineTo({}, {});", x, y),
-                    PathSegment::CurveTo { x1, y1, x2, y2, x, y } => {
-                        println!("    path.cubicTo({}, {}, {}, {}, {}, {});",
-                                 x1, y1, x2, y2, x, y);
-                    }
-                    PathSegment::ClosePath => println!("    path.close();"),
-                }
-            }
+        println!("    canvas->drawPath(path, paint);");
+        if stroke.dasharray.is_some() {
+            println!("    paint.setPathEffect(nullptr);");
+        }
+    }
 
-            if let Some(ref fill) = path.fill {
-                set_color(&fill.paint);
-                println!("    paint.setStyle(SkPaint::kFill_Style);");
-                println!("    canvas->drawPath(path, paint);");
-            }
+    fn end_path(&mut self) {
+        println!("    path.reset();");
+    }
 
-            if let Some(ref stroke) = path.stroke {
-                set_color(&stroke.paint);
-                println!("    paint.setStrokeWidth({});", stroke.width.value());
-                println!("    paint.setStyle(SkPaint::kStroke_Style);");
-                println!("    canvas->drawPath(path, paint);");
-            }
+    fn unsupported(&mut self, description: &str) {
+        println!("    // Unsupported: {}", description);
+    }
+}
 
-            println!("    path.reset();");
+fn set_paint(paint: &ResolvedPaint, opacity: f64) {
+    let gradient = match *paint {
+        ResolvedPaint::Color(color) => {
+            println!("    paint.setShader(nullptr);");
+            println!("    paint.setColor(0x{:08x});", argb(color, opacity));
+            return;
+        }
+        ResolvedPaint::Gradient(ref gradient) => gradient,
+    };
+
+    let colors: Vec<_> = gradient.stops.iter().map(|stop| {
+        format!("0x{:08x}", argb(stop.color, stop.opacity.value()))
+    }).collect();
+    let positions: Vec<_> = gradient.stops.iter().map(|stop| stop.offset.value()).collect();
+    let tile_mode = match gradient.spread_method {
+        SpreadMethod::Pad => "SkShader::kClamp_TileMode",
+        SpreadMethod::Reflect => "SkShader::kMirror_TileMode",
+        SpreadMethod::Repeat => "SkShader::kRepeat_TileMode",
+    };
+
+    println!("    {{");
+    println!("        SkColor colors[] = {{ {} }};", colors.join(", "));
+    println!("        SkScalar positions[] = {{ {} }};", join_numbers(&positions));
+    println!("        SkMatrix matrix = {};", matrix(&gradient.transform));
+    match gradient.geometry {
+        GradientGeometry::Linear { x1, y1, x2, y2 } => {
+            println!("        SkPoint points[] = {{ {{ {}, {} }}, {{ {}, {} }} }};",
+                     x1, y1, x2, y2);
+            println!("        paint.setShader(SkGradientShader::MakeLinear(points, colors, \
+                      positions, {}, {}, 0, &matrix));",
+                     colors.len(),
+                     tile_mode);
+        }
+        GradientGeometry::Radial { cx, cy, r, fx, fy } => {
+            println!("        paint.setShader(SkGradientShader::MakeTwoPointConical(\
+                      SkPoint::Make({}, {}), 0, SkPoint::Make({}, {}), {}, colors, positions, \
+                      {}, {}, 0, &matrix));",
+                     fx, fy,
+                     cx, cy, r,
+                     colors.len(),
+                     tile_mode);
         }
-        _ => {}
     }
+    println!("    }}");
+
+    // With a shader, only the alpha of the color is used.
+    println!("    paint.setColor(0x{:08x});",
+             argb(Color { red: 0, green: 0, blue: 0 }, opacity));
 }
 
-fn set_color(paint: &Paint) {
-    if let Paint::Color(color) = *paint {
-        println!("    paint.setColor(0x{:x});",
-                ((color.red as u32) << 16) |
-                    ((color.green as u32) << 8) |
-                    ((color.blue as u32) << 0) |
-                    (0xff << 24));
+fn matrix(transform: &Transform) -> String {
+    format!("SkMatrix::MakeAll({}, {}, {}, {}, {}, {}, 0, 0, 1)",
+            transform.a, transform.c, transform.e,
+            transform.b, transform.d, transform.f)
+}
+
+fn argb(color: Color, opacity: f64) -> u32 {
+    ((alpha(opacity) as u32) << 24) |
+        ((color.red as u32) << 16) |
+        ((color.green as u32) << 8) |
+        (color.blue as u32)
+}
+
+fn join_numbers(numbers: &[f64]) -> String {
+    numbers.iter().map(|number| number.to_string()).collect::<Vec<_>>().join(", ")
+}
+
+#[cfg(test)]
+mod test {
+    use super::{argb, matrix};
+    use usvg::{Color, Transform};
+
+    #[test]
+    fn test_matrix_is_row_major() {
+        let transform = Transform { a: 1.0, b: 2.0, c: 3.0, d: 4.0, e: 5.0, f: 6.0 };
+        assert_eq!(matrix(&transform), "SkMatrix::MakeAll(1, 3, 5, 2, 4, 6, 0, 0, 1)");
+    }
+
+    #[test]
+    fn test_argb() {
+        let color = Color { red: 0x12, green: 0x34, blue: 0x56 };
+        assert_eq!(argb(color, 1.0), 0xff12_3456);
+        assert_eq!(argb(color, 0.0), 0x0012_3456);
     }
 }
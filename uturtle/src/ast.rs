@@ -10,7 +10,7 @@
 
 // Inspiration for the commands are from https://docs.kde.org/trunk5/en/kdeedu/kturtle/commands.html
 
-#[derive(Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum Command {
     Reset,
     PenUp,
@@ -22,11 +22,19 @@ pub enum Command {
     PopLoc,
     PushRot,
     PopRot,
+    PushState,
+    PopState,
     Go(f32, f32),
     GoX(f32),
     GoY(f32),
+    // Like `Go`, `GoX` and `GoY`, but these draw a line when the pen is down.
+    MoveTo(f32, f32),
+    MoveToX(f32),
+    MoveToY(f32),
     PenWidth(f32),
     PenColor(u8, u8, u8), // RGB color.
+    SpriteHide,
+    SpriteShow,
 }
 
 pub type Turtle = Vec<Command>;
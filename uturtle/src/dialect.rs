@@ -0,0 +1,237 @@
+// pathfinder/uturtle/src/dialect.rs
+//
+// Copyright © 2019 The Pathfinder Project Developers.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Alternative turtle dialects that lower into the native `Command` set.
+//!
+//! Python's `turtle` module and UCBLogo both use a y-up coordinate system, start with the pen
+//! down, and measure positive angles counterclockwise (Python) or from north (Logo). The helpers
+//! here do the conversion so that the interpreter only ever sees native commands.
+
+use crate::ast::Command;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Dialect {
+    /// The native, KTurtle-inspired dialect.
+    Turtle,
+    /// A subset of UCBLogo.
+    Logo,
+    /// A subset of Python's `turtle` module.
+    Python,
+}
+
+impl Dialect {
+    pub fn from_extension(extension: &str) -> Option<Dialect> {
+        match &*extension.to_ascii_lowercase() {
+            "turtle" => Some(Dialect::Turtle),
+            "logo" | "lg" | "lgo" => Some(Dialect::Logo),
+            "py" => Some(Dialect::Python),
+            _ => None,
+        }
+    }
+
+    /// Detects the dialect from the first meaningful line of the script.
+    ///
+    /// A `#!` line naming `turtle`, `logo` or `python` selects that dialect explicitly (this
+    /// covers `#!/usr/bin/env python3`). Otherwise, `import turtle` or `from turtle import ...`
+    /// before any other statement selects Python.
+    pub fn from_header(source: &str) -> Option<Dialect> {
+        for line in source.lines().map(str::trim).filter(|line| !line.is_empty()) {
+            if line.starts_with("#!") {
+                let header = line[2..].to_ascii_lowercase();
+                if header.contains("python") {
+                    return Some(Dialect::Python);
+                }
+                if header.contains("logo") {
+                    return Some(Dialect::Logo);
+                }
+                if header.contains("turtle") {
+                    return Some(Dialect::Turtle);
+                }
+                return None;
+            }
+            if line.starts_with('#') {
+                continue;
+            }
+            if line.starts_with("import turtle") || line.starts_with("from turtle import") {
+                return Some(Dialect::Python);
+            }
+            return None;
+        }
+        None
+    }
+
+    /// Picks a dialect for a script, preferring its header line over its file extension.
+    pub fn detect(extension: Option<&str>, source: &str) -> Dialect {
+        Dialect::from_header(source)
+            .or_else(|| extension.and_then(Dialect::from_extension))
+            .unwrap_or(Dialect::Turtle)
+    }
+
+    /// Commands that put the native interpreter into this dialect's initial state.
+    pub(crate) fn prologue(self) -> Vec<Command> {
+        match self {
+            Dialect::Turtle => vec![],
+            Dialect::Logo => vec![Command::PenDown, Command::Direction(-90.0)],
+            Dialect::Python => vec![Command::PenDown],
+        }
+    }
+}
+
+/// Replaces a leading `#!` header line with spaces so that byte offsets stay valid.
+pub(crate) fn blank_header(source: &str) -> String {
+    let mut output = String::with_capacity(source.len());
+    let mut in_header = false;
+    let mut seen_content = false;
+    for ch in source.chars() {
+        if !seen_content && !ch.is_whitespace() {
+            seen_content = true;
+            in_header = ch == '#' && source.trim_start().starts_with("#!");
+        }
+        if in_header && ch == '\n' {
+            in_header = false;
+        }
+        if in_header {
+            output.extend((0..ch.len_utf8()).map(|_| ' '));
+        } else {
+            output.push(ch);
+        }
+    }
+    output
+}
+
+/// Replaces comments running from `comment` to the end of the line with spaces, skipping over
+/// string literals delimited by any of `quotes`. Byte offsets are preserved.
+pub(crate) fn blank_comments(source: &str, comment: char, quotes: &[char]) -> String {
+    let mut output = String::with_capacity(source.len());
+    let (mut in_comment, mut in_string) = (false, None);
+    for ch in source.chars() {
+        if ch == '\n' {
+            in_comment = false;
+            in_string = None;
+        } else if in_comment {
+            output.extend((0..ch.len_utf8()).map(|_| ' '));
+            continue;
+        } else if let Some(quote) = in_string {
+            if ch == quote {
+                in_string = None;
+            }
+        } else if ch == comment {
+            in_comment = true;
+            output.push(' ');
+            continue;
+        } else if quotes.contains(&ch) {
+            in_string = Some(ch);
+        }
+        output.push(ch);
+    }
+    output
+}
+
+/// Strips the quotes from a Python string literal.
+pub(crate) fn unquote(string: &str) -> &str {
+    &string[1..(string.len() - 1)]
+}
+
+/// Expands Python's `circle(radius, extent)` into the same polygon that Python draws.
+pub(crate) fn circle(radius: f32, extent: f32) -> Vec<Command> {
+    let fraction = extent.abs() / 360.0;
+    let steps = 1 + (f32::min(11.0 + radius.abs() / 6.0, 59.0) * fraction) as u32;
+    let mut angle = extent / steps as f32;
+    let mut length = 2.0 * radius * (0.5 * angle).to_radians().sin();
+    if radius < 0.0 {
+        length = -length;
+        angle = -angle;
+    }
+
+    // Python turns counterclockwise for positive angles, which is a negative native turn.
+    let mut commands = vec![Command::Turn(-0.5 * angle)];
+    for _ in 0..steps {
+        commands.push(Command::Move(length));
+        commands.push(Command::Turn(-angle));
+    }
+    commands.push(Command::Turn(0.5 * angle));
+    commands
+}
+
+/// Python accepts colors in either the 0.0–1.0 or the 0–255 range, depending on `colormode()`.
+/// We guess from the values themselves.
+pub(crate) fn python_rgb(r: f32, g: f32, b: f32) -> Command {
+    let scale = if r <= 1.0 && g <= 1.0 && b <= 1.0 { 255.0 } else { 1.0 };
+    Command::PenColor(to_u8(r * scale), to_u8(g * scale), to_u8(b * scale))
+}
+
+/// Parses a Tk-style color string: `#rrggbb` or one of the common color names.
+pub(crate) fn python_named_color(name: &str) -> Option<Command> {
+    if name.starts_with('#') && name.len() == 7 && name.is_ascii() {
+        let channel = |index: usize| u8::from_str_radix(&name[index..(index + 2)], 16).ok();
+        return Some(Command::PenColor(channel(1)?, channel(3)?, channel(5)?));
+    }
+
+    let (r, g, b) = match &*name.to_ascii_lowercase() {
+        "black" => (0, 0, 0),
+        "white" => (255, 255, 255),
+        "red" => (255, 0, 0),
+        "green" => (0, 255, 0),
+        "blue" => (0, 0, 255),
+        "yellow" => (255, 255, 0),
+        "cyan" => (0, 255, 255),
+        "magenta" => (255, 0, 255),
+        "orange" => (255, 165, 0),
+        "purple" => (160, 32, 240),
+        "brown" => (165, 42, 42),
+        "pink" => (255, 192, 203),
+        "gray" | "grey" => (190, 190, 190),
+        _ => return None,
+    };
+    Some(Command::PenColor(r, g, b))
+}
+
+/// UCBLogo color lists use a 0–100 range per channel.
+pub(crate) fn logo_rgb(r: f32, g: f32, b: f32) -> Command {
+    Command::PenColor(to_u8(r * 2.55), to_u8(g * 2.55), to_u8(b * 2.55))
+}
+
+/// Approximates UCBLogo's default 16-color palette.
+pub(crate) fn logo_palette_color(index: f32) -> Option<Command> {
+    let (r, g, b) = match index as i32 {
+        0 => (0, 0, 0),
+        1 => (0, 0, 255),
+        2 => (0, 255, 0),
+        3 => (0, 255, 255),
+        4 => (255, 0, 0),
+        5 => (255, 0, 255),
+        6 => (255, 255, 0),
+        7 => (255, 255, 255),
+        8 => (155, 96, 59),
+        9 => (197, 136, 18),
+        10 => (100, 162, 64),
+        11 => (120, 187, 187),
+        12 => (255, 149, 119),
+        13 => (144, 113, 208),
+        14 => (255, 163, 0),
+        15 => (183, 183, 183),
+        _ => return None,
+    };
+    Some(Command::PenColor(r, g, b))
+}
+
+/// Expands a Logo `repeat` block.
+pub(crate) fn repeat(count: f32, body: Vec<Command>) -> Vec<Command> {
+    let count = if count > 0.0 { count as usize } else { 0 };
+    let mut commands = Vec::with_capacity(body.len() * count);
+    for _ in 0..count {
+        commands.extend(body.iter().cloned());
+    }
+    commands
+}
+
+fn to_u8(value: f32) -> u8 {
+    f32::max(0.0, f32::min(255.0, value.round())) as u8
+}
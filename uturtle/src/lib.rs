@@ -11,12 +11,83 @@
 #[macro_use]
 extern crate lalrpop_util;
 
+use crate::ast::Turtle;
+use lalrpop_util::ParseError as LalrpopParseError;
+use std::fmt::{Display, Formatter, Result as FormatResult};
+
+pub use crate::dialect::Dialect;
+
 pub mod ast;
+pub mod dialect;
 
 lalrpop_mod!(pub turtle); // synthesized by LALRPOP
+lalrpop_mod!(pub logo); // synthesized by LALRPOP
+lalrpop_mod!(pub python); // synthesized by LALRPOP
 
 pub type Parser = turtle::TopLevelParser;
 
+/// Parses a script written in any supported dialect into native turtle commands.
+pub fn parse(source: &str, dialect: Dialect) -> Result<Turtle, ParseError> {
+    let source = dialect::blank_header(source);
+    let mut commands = dialect.prologue();
+    match dialect {
+        Dialect::Turtle => {
+            let program = turtle::TopLevelParser::new().parse(&source);
+            commands.extend(program.map_err(|error| ParseError::new(&source, error))?);
+        }
+        Dialect::Logo => {
+            let source = dialect::blank_comments(&source, ';', &[]).to_ascii_lowercase();
+            let program = logo::TopLevelParser::new().parse(&source);
+            commands.extend(program.map_err(|error| ParseError::new(&source, error))?);
+        }
+        Dialect::Python => {
+            let source = dialect::blank_comments(&source, '#', &['"', '\'']);
+            let program = python::TopLevelParser::new().parse(&source);
+            commands.extend(program.map_err(|error| ParseError::new(&source, error))?);
+        }
+    }
+    Ok(commands)
+}
+
+#[derive(Clone, Debug)]
+pub struct ParseError {
+    pub message: String,
+}
+
+impl ParseError {
+    fn new<T>(source: &str, error: LalrpopParseError<usize, T, &'static str>) -> ParseError {
+        let (location, message) = match error {
+            LalrpopParseError::InvalidToken { location } => {
+                (Some(location), "invalid token".to_owned())
+            }
+            LalrpopParseError::UnrecognizedToken { token: None, .. } => {
+                (Some(source.len()), "unexpected end of script".to_owned())
+            }
+            LalrpopParseError::UnrecognizedToken { token: Some((start, _, end)), .. } |
+            LalrpopParseError::ExtraToken { token: (start, _, end) } => {
+                (Some(start), format!("unexpected `{}`", &source[start..end]))
+            }
+            LalrpopParseError::User { error } => (None, error.to_owned()),
+        };
+
+        let message = match location {
+            None => message,
+            Some(location) => {
+                let line = source[..location].matches('\n').count() + 1;
+                let column = location - source[..location].rfind('\n').map_or(0, |i| i + 1) + 1;
+                format!("line {}, column {}: {}", line, column, message)
+            }
+        };
+        ParseError { message }
+    }
+}
+
+impl Display for ParseError {
+    fn fmt(&self, formatter: &mut Formatter) -> FormatResult {
+        formatter.write_str(&self.message)
+    }
+}
+
 #[test]
 fn turtle_command_parser() {
     assert!(turtle::CommandParser::new().parse("penup").is_ok());
@@ -29,10 +100,14 @@ fn turtle_command_parser() {
     assert!(turtle::CommandParser::new().parse("poploc").is_ok());
     assert!(turtle::CommandParser::new().parse("pushrot").is_ok());
     assert!(turtle::CommandParser::new().parse("poprot").is_ok());
+    assert!(turtle::CommandParser::new().parse("pushstate").is_ok());
+    assert!(turtle::CommandParser::new().parse("popstate").is_ok());
     assert!(turtle::CommandParser::new().parse("go 1 3").is_ok());
     assert!(turtle::CommandParser::new().parse("gox 5.3").is_ok());
     assert!(turtle::CommandParser::new().parse("goy 44.2").is_ok());
     assert!(turtle::CommandParser::new().parse("penwidth 2").is_ok());
+    assert!(turtle::CommandParser::new().parse("spritehide").is_ok());
+    assert!(turtle::CommandParser::new().parse("spriteshow").is_ok());
     assert!(turtle::CommandParser::new()
         .parse("pencolor 255,128 ,    128")
         .is_ok());
@@ -51,3 +126,61 @@ fn turtle_program_parser() {
         .parse("turnright turnright 12.3 turnleft")
         .is_ok());
 }
+
+#[test]
+fn logo_program_parser() {
+    use crate::ast::Command;
+
+    let program = parse("; A square.\nREPEAT 2 [FD 10 RT 90]\nsetxy 3 4", Dialect::Logo).unwrap();
+    assert_eq!(program,
+               vec![Command::PenDown,
+                    Command::Direction(-90.0),
+                    Command::Move(10.0),
+                    Command::Turn(90.0),
+                    Command::Move(10.0),
+                    Command::Turn(90.0),
+                    Command::MoveTo(3.0, -4.0)]);
+
+    assert!(parse("setpc [100 0 0] seth 90 setpc 4", Dialect::Logo).is_ok());
+    assert!(parse("setpc 99", Dialect::Logo).is_err());
+    assert!(parse("repeat 4 [fd 10", Dialect::Logo).is_err());
+}
+
+#[test]
+fn python_program_parser() {
+    use crate::ast::Command;
+
+    let source = "import turtle\n\
+                  t = turtle.Turtle()  # The turtle.\n\
+                  t.pencolor(\"#ff8000\")\n\
+                  t.left(90)\n\
+                  fd(10)\n\
+                  turtle.goto(1, 2)\n\
+                  turtle.done()\n";
+    let program = parse(source, Dialect::Python).unwrap();
+    assert_eq!(program,
+               vec![Command::PenDown,
+                    Command::PenColor(255, 128, 0),
+                    Command::Turn(-90.0),
+                    Command::Move(10.0),
+                    Command::MoveTo(1.0, -2.0)]);
+
+    assert!(parse("pencolor(0.5, 0.5, 1.0) circle(20, 90) speed(0)", Dialect::Python).is_ok());
+    assert_eq!(parse("t.hideturtle() st()", Dialect::Python).unwrap()[1..],
+               [Command::SpriteHide, Command::SpriteShow]);
+    assert!(parse("pencolor('chartreuse-ish')", Dialect::Python).is_err());
+    assert!(parse("for i in range(4):", Dialect::Python).is_err());
+}
+
+#[test]
+fn dialect_detection() {
+    assert_eq!(Dialect::detect(Some("turtle"), "forward 10"), Dialect::Turtle);
+    assert_eq!(Dialect::detect(Some("lg"), "fd 10"), Dialect::Logo);
+    assert_eq!(Dialect::detect(None, "# Draw.\nfrom turtle import *\n"), Dialect::Python);
+    assert_eq!(Dialect::detect(Some("turtle"), "#!/usr/bin/env python3\n"), Dialect::Python);
+    assert_eq!(Dialect::detect(None, "#!logo\nfd 10"), Dialect::Logo);
+    assert_eq!(Dialect::detect(None, "forward 10"), Dialect::Turtle);
+
+    let error = parse("#!turtle\nforward 10\nbleh", Dialect::Turtle).unwrap_err();
+    assert_eq!(error.message, "line 3, column 1: invalid token");
+}